@@ -3,6 +3,116 @@
 
 import { createClient } from '@libsql/client/web';
 
+const DEFAULT_RATING = 1200;
+
+// Provisional players move faster until their rating settles
+function kFactor(ratedGames) {
+  return ratedGames < 30 ? 40 : 20;
+}
+
+// Standard Elo update; score is 1 for a win, 0.5 for a draw, 0 for a loss
+function eloUpdate(rating, opponentRating, score, ratedGames) {
+  const expected = 1 / (1 + Math.pow(10, (opponentRating - rating) / 400));
+  return rating + kFactor(ratedGames) * (score - expected);
+}
+
+async function getRating(client, address) {
+  const result = await client.execute({
+    sql: 'SELECT rating, rated_games FROM player_ratings WHERE kaspa_address = ?',
+    args: [address],
+  });
+  if (result.rows.length === 0) {
+    return { rating: DEFAULT_RATING, rated_games: 0 };
+  }
+  return { rating: Number(result.rows[0].rating), rated_games: Number(result.rows[0].rated_games) };
+}
+
+function upsertRating(address, rating) {
+  return {
+    sql: `INSERT INTO player_ratings (kaspa_address, rating, rated_games)
+          VALUES (?, ?, 1)
+          ON CONFLICT(kaspa_address) DO UPDATE SET
+            rating = excluded.rating,
+            rated_games = rated_games + 1,
+            updated_at = unixepoch()`,
+    args: [address, rating],
+  };
+}
+
+// Order-independent, so each player's report of a match maps to the same row
+function matchId(sessionSeed, address1, address2) {
+  const [first, second] = [address1, address2].sort();
+  return `${sessionSeed}-${first}-${second}`;
+}
+
+// True when one side of a match claims a forfeit by a player who also reported it
+function forfeitsConflict(recorded, forfeitedAddress, reporterAddress) {
+  const recordedForfeited = Number(recorded.forfeit) === 1 ? recorded.forfeited_address : null;
+  return (recordedForfeited !== null && recordedForfeited === reporterAddress) ||
+         (forfeitedAddress !== null && forfeitedAddress === recorded.reporter_address);
+}
+
+// Takes back everything a recorded match added to both players, and marks it as no contest
+function voidMatch(recorded) {
+  const undoPlayer = (address, kills, deaths, ratingChange) => [
+    {
+      sql: `UPDATE players SET
+              total_kills = total_kills - ?,
+              total_deaths = total_deaths - ?,
+              games_played = games_played - 1,
+              wins = wins - ?,
+              losses = losses - ?,
+              updated_at = unixepoch()
+            WHERE kaspa_address = ?`,
+      args: [
+        kills,
+        deaths,
+        recorded.winner_address === address ? 1 : 0,
+        recorded.winner_address !== null && recorded.winner_address !== address ? 1 : 0,
+        address,
+      ],
+    },
+    // Unsigned results never touched the ratings
+    ...(Number(recorded.rated) === 1 ? [{
+      sql: `UPDATE player_ratings SET
+              rating = rating - ?,
+              rated_games = rated_games - 1,
+              updated_at = unixepoch()
+            WHERE kaspa_address = ?`,
+      args: [ratingChange, address],
+    }] : []),
+  ];
+  return [
+    ...undoPlayer(recorded.player1_address, recorded.player1_score, recorded.player2_score,
+                  recorded.player1_rating_change),
+    ...undoPlayer(recorded.player2_address, recorded.player2_score, recorded.player1_score,
+                  recorded.player2_rating_change),
+    {
+      sql: 'UPDATE match_history SET no_contest = 1, winner_address = NULL WHERE match_id = ?',
+      args: [recorded.match_id],
+    },
+  ];
+}
+
+function hexToBytes(hex) {
+  if (!/^([0-9a-f]{2})*$/i.test(hex)) {
+    return null;
+  }
+  return new Uint8Array(hex.match(/../g)?.map((byte) => parseInt(byte, 16)) ?? []);
+}
+
+// Results posted by the headless validator carry a hex HMAC-SHA256 of the raw body
+async function verifySignature(secret, body, signature) {
+  const signatureBytes = hexToBytes(signature);
+  if (!secret || !signatureBytes) {
+    return false;
+  }
+  const encoder = new TextEncoder();
+  const key = await crypto.subtle.importKey(
+    'raw', encoder.encode(secret), { name: 'HMAC', hash: 'SHA-256' }, false, ['verify']);
+  return crypto.subtle.verify('HMAC', key, signatureBytes, encoder.encode(body));
+}
+
 export default {
   async fetch(request, env) {
     const url = new URL(request.url);
@@ -28,11 +138,34 @@ export default {
     try {
       // Route: POST /api/stats - Submit game results
       if (url.pathname === '/api/stats' && request.method === 'POST') {
-        const data = await request.json();
+        // The signature covers the exact bytes sent, so read the body before parsing it
+        const body = await request.text();
+        const signature = request.headers.get('X-Validator-Signature');
+        const verified = signature !== null;
+
+        if (verified && !(await verifySignature(env.VALIDATOR_SECRET, body, signature))) {
+          return new Response(JSON.stringify({ error: 'Invalid signature' }), {
+            status: 401,
+            headers: { ...corsHeaders, 'Content-Type': 'application/json' },
+          });
+        }
+
+        // Self-reported browser results count towards stats but not ratings, until a deployment
+        // with a validator on every match stops taking them
+        if (!verified && env.REQUIRE_VERIFIED_RESULTS === 'true') {
+          return new Response(JSON.stringify({ error: 'Only validator-signed results are accepted' }), {
+            status: 403,
+            headers: { ...corsHeaders, 'Content-Type': 'application/json' },
+          });
+        }
+
+        const data = JSON.parse(body);
 
         // Validate payload
         const { player1_address, player2_address, player1_score, player2_score, session_seed,
-                player1_display_name, player2_display_name } = data;
+                player1_display_name, player2_display_name, forfeit, forfeited_address } = data;
+        // Only meaningful for unsigned reports, which say which player sent them
+        const reporter_address = verified ? null : (data.reporter_address || null);
 
         if (!player1_address || !player2_address ||
             typeof player1_score !== 'number' || typeof player2_score !== 'number') {
@@ -42,65 +175,162 @@ export default {
           });
         }
 
-        // Determine winner
-        const winner_address = player1_score > player2_score ? player1_address : player2_address;
-        const match_id = `${session_seed}-${Date.now()}`;
-
-        // Start transaction - insert players BEFORE match history to avoid foreign key constraint
-        await client.batch([
-          // Update player1 stats FIRST
-          {
-            sql: `INSERT INTO players (kaspa_address, display_name, total_kills, total_deaths, games_played, wins, losses)
-                  VALUES (?, ?, ?, ?, 1, ?, ?)
-                  ON CONFLICT(kaspa_address) DO UPDATE SET
-                    display_name = COALESCE(excluded.display_name, display_name),
-                    total_kills = total_kills + excluded.total_kills,
-                    total_deaths = total_deaths + excluded.total_deaths,
-                    games_played = games_played + 1,
-                    wins = wins + excluded.wins,
-                    losses = losses + excluded.losses,
-                    updated_at = unixepoch()`,
-            args: [
-              player1_address,
-              player1_display_name || null,
-              player1_score,
-              player2_score,
-              player1_score > player2_score ? 1 : 0,
-              player1_score < player2_score ? 1 : 0,
-            ],
-          },
-          // Update player2 stats SECOND
-          {
-            sql: `INSERT INTO players (kaspa_address, display_name, total_kills, total_deaths, games_played, wins, losses)
-                  VALUES (?, ?, ?, ?, 1, ?, ?)
-                  ON CONFLICT(kaspa_address) DO UPDATE SET
-                    display_name = COALESCE(excluded.display_name, display_name),
-                    total_kills = total_kills + excluded.total_kills,
-                    total_deaths = total_deaths + excluded.total_deaths,
-                    games_played = games_played + 1,
-                    wins = wins + excluded.wins,
-                    losses = losses + excluded.losses,
-                    updated_at = unixepoch()`,
-            args: [
-              player2_address,
-              player2_display_name || null,
-              player2_score,
-              player1_score,
-              player2_score > player1_score ? 1 : 0,
-              player2_score < player1_score ? 1 : 0,
-            ],
-          },
-          // Insert match history LAST (after players exist)
-          {
-            sql: `INSERT INTO match_history (match_id, player1_address, player2_address,
-                  player1_score, player2_score, winner_address, session_seed)
-                  VALUES (?, ?, ?, ?, ?, ?, ?)`,
-            args: [match_id, player1_address, player2_address, player1_score,
-                   player2_score, winner_address, session_seed],
-          },
-        ]);
+        // Determine winner - a forfeit goes to the player who stayed, regardless of score
+        const is_forfeit = forfeit === true && !!forfeited_address;
+        const player1_result = is_forfeit
+          ? (forfeited_address === player1_address ? 0 : 1)
+          : player1_score > player2_score ? 1 : player1_score < player2_score ? 0 : 0.5;
+        let winner_address;
+        if (player1_result > 0.5) {
+          winner_address = player1_address;
+        } else if (player1_result < 0.5) {
+          winner_address = player2_address;
+        } else {
+          // A draw has no winner
+          winner_address = null;
+        }
+        // Both clients (and the validator) report the same match, so it is keyed on what they share
+        const match_id = matchId(session_seed, player1_address, player2_address);
+
+        // Ratings are read and written in one transaction, so concurrent results for the same
+        // players can't both start from the same pre-match rating
+        let player1_new_rating;
+        let player2_new_rating;
+        const tx = await client.transaction('write');
+        try {
+          // A replayed request or the other client's copy must not count the same match twice,
+          // and once the validator has recorded a match no client report may add to it
+          const existing = await tx.execute({
+            sql: 'SELECT * FROM match_history WHERE match_id = ?',
+            args: [match_id],
+          });
+          const recorded = existing.rows[0];
+          if (recorded && verified && Number(recorded.verified) === 0) {
+            // The validator's result replaces whatever a client reported before it
+            if (Number(recorded.no_contest) === 0) {
+              await tx.batch(voidMatch(recorded));
+            }
+            await tx.execute({ sql: 'DELETE FROM match_history WHERE match_id = ?', args: [match_id] });
+          } else if (recorded) {
+            // A forfeit only stands if the player named as leaving never reported the match
+            // themselves; otherwise the claims conflict and neither side gets the result
+            if (!verified && Number(recorded.verified) === 0 && Number(recorded.no_contest) === 0 &&
+                forfeitsConflict(recorded, is_forfeit ? forfeited_address : null, reporter_address)) {
+              await tx.batch(voidMatch(recorded));
+              await tx.commit();
+              return new Response(JSON.stringify({ error: 'Conflicting forfeit claims, match recorded as no contest' }), {
+                status: 409,
+                headers: { ...corsHeaders, 'Content-Type': 'application/json' },
+              });
+            }
+            await tx.rollback();
+            return new Response(JSON.stringify({
+              error: Number(recorded.verified) === 1 && !verified
+                ? 'Match already has a verified result'
+                : 'Match already recorded',
+            }), {
+              status: 409,
+              headers: { ...corsHeaders, 'Content-Type': 'application/json' },
+            });
+          }
+
+          // Reporting a forfeit by yourself can't be told from a conflicting claim
+          if (is_forfeit && reporter_address === forfeited_address) {
+            await tx.rollback();
+            return new Response(JSON.stringify({ error: 'Forfeit reported by the forfeiting player' }), {
+              status: 400,
+              headers: { ...corsHeaders, 'Content-Type': 'application/json' },
+            });
+          }
+
+          // Elo update from the pre-match ratings of both players; only a result the validator
+          // re-simulated is trusted to move them
+          const player1_rating = await getRating(tx, player1_address);
+          const player2_rating = await getRating(tx, player2_address);
+          player1_new_rating = verified
+            ? eloUpdate(player1_rating.rating, player2_rating.rating, player1_result, player1_rating.rated_games)
+            : player1_rating.rating;
+          player2_new_rating = verified
+            ? eloUpdate(player2_rating.rating, player1_rating.rating, 1 - player1_result, player2_rating.rated_games)
+            : player2_rating.rating;
 
-        return new Response(JSON.stringify({ success: true, match_id }), {
+          // Insert players BEFORE match history to avoid foreign key constraint
+          await tx.batch([
+            // Update player1 stats FIRST
+            {
+              sql: `INSERT INTO players (kaspa_address, display_name, total_kills, total_deaths, games_played, wins, losses)
+                    VALUES (?, ?, ?, ?, 1, ?, ?)
+                    ON CONFLICT(kaspa_address) DO UPDATE SET
+                      display_name = COALESCE(excluded.display_name, display_name),
+                      total_kills = total_kills + excluded.total_kills,
+                      total_deaths = total_deaths + excluded.total_deaths,
+                      games_played = games_played + 1,
+                      wins = wins + excluded.wins,
+                      losses = losses + excluded.losses,
+                      updated_at = unixepoch()`,
+              args: [
+                player1_address,
+                player1_display_name || null,
+                player1_score,
+                player2_score,
+                player1_result === 1 ? 1 : 0,
+                player1_result === 0 ? 1 : 0,
+              ],
+            },
+            // Update player2 stats SECOND
+            {
+              sql: `INSERT INTO players (kaspa_address, display_name, total_kills, total_deaths, games_played, wins, losses)
+                    VALUES (?, ?, ?, ?, 1, ?, ?)
+                    ON CONFLICT(kaspa_address) DO UPDATE SET
+                      display_name = COALESCE(excluded.display_name, display_name),
+                      total_kills = total_kills + excluded.total_kills,
+                      total_deaths = total_deaths + excluded.total_deaths,
+                      games_played = games_played + 1,
+                      wins = wins + excluded.wins,
+                      losses = losses + excluded.losses,
+                      updated_at = unixepoch()`,
+              args: [
+                player2_address,
+                player2_display_name || null,
+                player2_score,
+                player1_score,
+                player1_result === 0 ? 1 : 0,
+                player1_result === 1 ? 1 : 0,
+              ],
+            },
+            // Ratings reference players, so they come after both upserts
+            ...(verified ? [
+              upsertRating(player1_address, player1_new_rating),
+              upsertRating(player2_address, player2_new_rating),
+            ] : []),
+            // Insert match history LAST (after players exist)
+            {
+              sql: `INSERT INTO match_history (match_id, player1_address, player2_address,
+                    player1_score, player2_score, winner_address, session_seed, forfeit, verified, rated,
+                    forfeited_address, reporter_address, player1_rating_change, player2_rating_change)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)`,
+              args: [match_id, player1_address, player2_address, player1_score,
+                     player2_score, winner_address, session_seed, is_forfeit ? 1 : 0, verified ? 1 : 0,
+                     verified ? 1 : 0,
+                     is_forfeit ? forfeited_address : null, reporter_address,
+                     player1_new_rating - player1_rating.rating, player2_new_rating - player2_rating.rating],
+            },
+          ]);
+          await tx.commit();
+        } finally {
+          tx.close();
+        }
+
+        return new Response(JSON.stringify({
+          success: true,
+          match_id,
+          verified,
+          rated: verified,
+          ratings: {
+            [player1_address]: Math.round(player1_new_rating),
+            [player2_address]: Math.round(player2_new_rating),
+          },
+        }), {
           status: 200,
           headers: { ...corsHeaders, 'Content-Type': 'application/json' },
         });
@@ -142,16 +372,29 @@ export default {
         });
       }
 
+      // Route: GET /api/rating/:address - Get a player's matchmaking rating (default for unrated players)
+      if (url.pathname.startsWith('/api/rating/') && request.method === 'GET') {
+        const address = decodeURIComponent(url.pathname.split('/api/rating/')[1]);
+        const { rating, rated_games } = await getRating(client, address);
+
+        return new Response(JSON.stringify({ rating: Math.round(rating), rated_games }), {
+          status: 200,
+          headers: { ...corsHeaders, 'Content-Type': 'application/json' },
+        });
+      }
+
       // Route: GET /api/player/:address - Get player stats
       if (url.pathname.startsWith('/api/player/') && request.method === 'GET') {
         const address = url.pathname.split('/api/player/')[1];
 
         const result = await client.execute({
-          sql: `SELECT kaspa_address, display_name, total_kills, total_deaths,
-                       wins, losses, games_played,
-                       CAST(total_kills AS REAL) / NULLIF(total_deaths, 0) as kd_ratio
-                FROM players
-                WHERE kaspa_address = ?`,
+          sql: `SELECT p.kaspa_address, p.display_name, p.total_kills, p.total_deaths,
+                       p.wins, p.losses, p.games_played,
+                       CAST(p.total_kills AS REAL) / NULLIF(p.total_deaths, 0) as kd_ratio,
+                       COALESCE(r.rating, ${DEFAULT_RATING}) as rating
+                FROM players p
+                LEFT JOIN player_ratings r ON r.kaspa_address = p.kaspa_address
+                WHERE p.kaspa_address = ?`,
           args: [address],
         });
 
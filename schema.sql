@@ -14,8 +14,18 @@ CREATE TABLE IF NOT EXISTS players (
     updated_at INTEGER DEFAULT (unixepoch())
 );
 
+-- Player ratings - Elo rating per player, used for skill-based matchmaking
+CREATE TABLE IF NOT EXISTS player_ratings (
+    kaspa_address TEXT PRIMARY KEY,
+    rating REAL NOT NULL DEFAULT 1200,
+    rated_games INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER DEFAULT (unixepoch()),
+    FOREIGN KEY (kaspa_address) REFERENCES players(kaspa_address)
+);
+
 -- Match history table - stores individual game results
 CREATE TABLE IF NOT EXISTS match_history (
+    -- session_seed and both addresses in sorted order, so every report of a match shares one row
     match_id TEXT PRIMARY KEY,
     player1_address TEXT NOT NULL,
     player2_address TEXT NOT NULL,
@@ -23,10 +33,36 @@ CREATE TABLE IF NOT EXISTS match_history (
     player2_score INTEGER NOT NULL,
     winner_address TEXT,
     session_seed TEXT,
+    -- 1 when a player disconnected and the match was awarded to the other
+    forfeit INTEGER NOT NULL DEFAULT 0,
+    forfeited_address TEXT,
+    -- 1 when the result was re-simulated and signed by the headless validator
+    verified INTEGER NOT NULL DEFAULT 0,
+    -- 1 when the match moved both ratings; unsigned results are only counted in the stats
+    rated INTEGER NOT NULL DEFAULT 1,
+    -- Player whose unsigned report was recorded; NULL for validator results
+    reporter_address TEXT,
+    -- What the match did to each rating, so a voided match can be taken back
+    player1_rating_change REAL NOT NULL DEFAULT 0,
+    player2_rating_change REAL NOT NULL DEFAULT 0,
+    -- 1 when both players claimed the other forfeited and the result was taken back
+    no_contest INTEGER NOT NULL DEFAULT 0,
     played_at INTEGER DEFAULT (unixepoch()),
     FOREIGN KEY (player1_address) REFERENCES players(kaspa_address),
     FOREIGN KEY (player2_address) REFERENCES players(kaspa_address)
 );
+-- Databases created before forfeits were tracked need:
+--   ALTER TABLE match_history ADD COLUMN forfeit INTEGER NOT NULL DEFAULT 0;
+-- and before validator-signed results were tracked:
+--   ALTER TABLE match_history ADD COLUMN verified INTEGER NOT NULL DEFAULT 0;
+-- and before conflicting forfeit claims were voided:
+--   ALTER TABLE match_history ADD COLUMN forfeited_address TEXT;
+--   ALTER TABLE match_history ADD COLUMN reporter_address TEXT;
+--   ALTER TABLE match_history ADD COLUMN player1_rating_change REAL NOT NULL DEFAULT 0;
+--   ALTER TABLE match_history ADD COLUMN player2_rating_change REAL NOT NULL DEFAULT 0;
+--   ALTER TABLE match_history ADD COLUMN no_contest INTEGER NOT NULL DEFAULT 0;
+-- and before unsigned results were left unrated:
+--   ALTER TABLE match_history ADD COLUMN rated INTEGER NOT NULL DEFAULT 1;
 
 -- Index for fast leaderboard queries ordered by K/D ratio
 CREATE INDEX IF NOT EXISTS idx_kd_ratio ON players (
@@ -0,0 +1,4 @@
+// validator.rs - Headless match validator entry point
+fn main() {
+    extreme_bevy::run_validator();
+}
@@ -1,11 +1,148 @@
 use bevy::prelude::*;
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser, Resource, Debug, Clone)]
 pub struct Args {
     /// runs the game in synctest mode
     #[clap(long)]
     pub synctest: bool,
-    #[clap(long, default_value = "2")]
-    pub input_delay: usize,
+    /// fixed input delay in frames; picked from the measured ping when omitted
+    #[clap(long)]
+    pub input_delay: Option<usize>,
+    /// round-trip times (ms) and the input delay (frames) used up to each; slower peers get the last entry
+    #[clap(long, default_value = "40:1,80:2,130:3,200:4,300:5")]
+    pub input_delay_table: String,
+    /// number of players per match (2-8)
+    #[clap(long, default_value = "2", value_parser = clap::value_parser!(u8).range(2..=8))]
+    pub players: u8,
+    /// free-for-all or two teams
+    #[clap(long, value_enum, default_value_t = GameMode::FreeForAll)]
+    pub mode: GameMode,
+    /// matchbox signalling server used for matchmaking
+    #[clap(long, env = "MATCHBOX_SERVER", default_value = "wss://match.helsing.studio")]
+    pub matchbox_server: String,
+    /// matchbox room players are matched in
+    #[clap(long, env = "MATCHBOX_ROOM", default_value = "dagknights")]
+    pub matchbox_room: String,
+    /// matchbox signalling server used for lobby chat
+    #[clap(long, env = "CHAT_SERVER", default_value = "ws://127.0.0.1:3536")]
+    pub chat_server: String,
+    /// matchbox room used for lobby chat
+    #[clap(long, env = "CHAT_ROOM", default_value = "extreme_bevy_chat")]
+    pub chat_room: String,
+    /// simulated one-way latency added to every GGRS packet this instance sends, in milliseconds;
+    /// incoming packets are left alone, so give every instance the same --net-* flags.
+    /// Under --synctest or --bot the other players' inputs are delayed instead
+    #[clap(long, default_value = "0")]
+    pub net_latency: u64,
+    /// random extra delay of up to this many milliseconds per GGRS packet
+    #[clap(long, default_value = "0")]
+    pub net_jitter: u64,
+    /// fraction of GGRS packets dropped (0.0-1.0)
+    #[clap(long, default_value = "0")]
+    pub net_loss: f32,
+    /// fraction of GGRS packets held back so later ones overtake them (0.0-1.0)
+    #[clap(long, default_value = "0")]
+    pub net_reorder: f32,
+    /// practise against a computer opponent playing handle 1, skipping matchmaking
+    #[clap(long, value_enum)]
+    pub bot: Option<BotDifficulty>,
+    /// every player on this machine, each with their own gamepad, skipping matchmaking
+    #[clap(long)]
+    pub couch: bool,
+    /// open online matches to spectators and validators: every player relays the inputs to
+    /// the match's watch room when any of them asks for it
+    #[clap(long)]
+    pub watchable: bool,
+    /// plays back a recorded match instead of matchmaking (a file path, or a URL in the browser)
+    #[clap(long)]
+    pub replay: Option<String>,
+}
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// every player for themselves, last one standing wins the round
+    #[default]
+    FreeForAll,
+    /// even handles vs odd handles, last team standing wins the round
+    Teams,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BotDifficulty {
+    /// slow to react and wild with its shots
+    Easy,
+    #[default]
+    Medium,
+    /// reacts almost instantly and rarely misses
+    Hard,
+}
+
+impl BotDifficulty {
+    pub const ALL: [BotDifficulty; 3] = [BotDifficulty::Easy, BotDifficulty::Medium, BotDifficulty::Hard];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BotDifficulty::Easy => "Easy",
+            BotDifficulty::Medium => "Medium",
+            BotDifficulty::Hard => "Hard",
+        }
+    }
+}
+
+impl Args {
+    /// Parses the command line, or the page's query string in the browser
+    /// (`?matchbox_server=ws://localhost:3536&synctest` becomes `--matchbox-server ws://localhost:3536 --synctest`)
+    pub fn from_env() -> Self {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let search = web_sys::window()
+                .and_then(|w| w.location().search().ok())
+                .unwrap_or_default();
+            let argv = std::iter::once("extreme_bevy".to_string()).chain(query_to_args(&search));
+            Args::try_parse_from(argv).and_then(Args::check).unwrap_or_else(|e| {
+                web_sys::console::warn_1(&format!("Ignoring invalid query string: {e}").into());
+                Args::parse_from(["extreme_bevy"])
+            })
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        Args::parse().check().unwrap_or_else(|e| e.exit())
+    }
+
+    /// Rejects flag combinations clap can't express on its own
+    fn check(self) -> Result<Self, clap::Error> {
+        if self.bot.is_some() && self.players != 2 {
+            return Err(Args::command().error(
+                ErrorKind::ArgumentConflict,
+                "--bot only plays duels; leave out --players or set it to 2",
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// Turns `?a_b=c&d` into `["--a-b", "c", "--d"]`
+#[cfg(target_arch = "wasm32")]
+fn query_to_args(search: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    for pair in search.trim_start_matches('?').split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (pair, None),
+        };
+        args.push(format!("--{}", decode(key).replace('_', "-")));
+        if let Some(value) = value {
+            args.push(decode(value));
+        }
+    }
+    args
+}
+
+#[cfg(target_arch = "wasm32")]
+fn decode(component: &str) -> String {
+    js_sys::decode_uri_component(&component.replace('+', " "))
+        .map(String::from)
+        .unwrap_or_else(|_| component.to_string())
 }
@@ -1,6 +1,41 @@
 // constants.rs (fixed)
+use std::time::Duration;
+use crate::core::fixed::Fixed;
+
 pub const MAP_SIZE: i32 = 41;
 pub const WALL_HEIGHT: f32 = 3.0;
 pub const PLAYER_HEIGHT: f32 = 1.0;
 pub const PLAYER_RADIUS: f32 = 0.3;
-pub const BULLET_RADIUS: f32 = 0.05;
\ No newline at end of file
+pub const BULLET_RADIUS: f32 = 0.05;
+pub const MAX_PLAYERS: usize = 8;
+
+/// Simulation rate; every rollback system advances exactly one tick of this per GGRS frame
+pub const ROLLBACK_FPS: usize = 60;
+/// Peers swap a checksum every this many frames to catch desyncs
+pub const DESYNC_CHECK_INTERVAL: u32 = 1;
+
+/// Leaderboard worker serving stats, ratings and the leaderboard; see `backend/`
+pub const API_BASE_URL: &str = "https://dk-leaderboard-api.dagknights.workers.dev";
+
+/// `path` on the leaderboard worker, e.g. `api_url("/api/stats")`
+pub fn api_url(path: &str) -> String {
+    format!("{API_BASE_URL}{path}")
+}
+
+/// Input delay used when no round-trip time could be measured
+pub const DEFAULT_INPUT_DELAY: usize = 2;
+/// Highest input delay the lobby lets players pick
+pub const MAX_INPUT_DELAY: usize = 8;
+
+/// Distance covered per rollback tick; walking and sprinting are tuned in `MovementTuning`
+pub const BULLET_STEP: Fixed = Fixed::from_ratio(20, ROLLBACK_FPS as i32);
+/// Distance covered per rollback tick of a dodge roll
+pub const DODGE_STEP: Fixed = Fixed::from_ratio(14, ROLLBACK_FPS as i32);
+
+/// Health a bullet takes off; three to a kill at full health
+pub const BULLET_DAMAGE: i32 = 34;
+
+/// Duration of one rollback tick, for timers ticked inside the rollback schedule
+pub const fn rollback_timestep() -> Duration {
+    Duration::from_nanos(1_000_000_000 / ROLLBACK_FPS as u64)
+}
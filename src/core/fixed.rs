@@ -0,0 +1,208 @@
+// fixed.rs - Q16.16 fixed-point math for the rollback simulation
+//
+// Integer arithmetic is bit-identical on every target, unlike libm transcendentals and
+// glam's SIMD/scalar code paths, so native and WASM peers can't drift apart.
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use bevy::prelude::*;
+
+pub const FRAC_BITS: u32 = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(pub i32);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << FRAC_BITS);
+    pub const HALF: Fixed = Fixed(1 << (FRAC_BITS - 1));
+
+    pub const fn from_int(value: i32) -> Self {
+        Fixed(value << FRAC_BITS)
+    }
+
+    /// `numerator / denominator`, for constants that aren't whole numbers
+    pub const fn from_ratio(numerator: i32, denominator: i32) -> Self {
+        Fixed((((numerator as i64) << FRAC_BITS) / denominator as i64) as i32)
+    }
+
+    /// Only for values that are already agreed on by every peer (constants, map data)
+    pub fn from_f32(value: f32) -> Self {
+        Fixed((value * Self::ONE.0 as f32).round() as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+
+    pub fn abs(self) -> Self {
+        Fixed(self.0.abs())
+    }
+
+    pub fn signum(self) -> Self {
+        Fixed::from_int(self.0.signum())
+    }
+
+    pub fn sqrt(self) -> Self {
+        // The raw value is x * 2^16, so sqrt(raw << 16) == sqrt(x) * 2^16, still in Q16.16
+        Fixed(isqrt((self.0.max(0) as u64) << FRAC_BITS) as i32)
+    }
+
+    /// Sine of an angle measured in turns, via Bhaskara's approximation (error < 0.002)
+    pub fn sin_turns(turns: Fixed) -> Self {
+        let t = Fixed(turns.0 & (Self::ONE.0 - 1));
+        let (half, sign) = if t < Self::HALF { (t, 1) } else { (t - Self::HALF, -1) };
+        // u in [0, 1) spans half a turn: sin(pi * u) ~= 16u(1 - u) / (5 - 4u(1 - u))
+        let u = half * Fixed::from_int(2);
+        let p = u * (Self::ONE - u);
+        let value = Fixed::from_int(16) * p / (Fixed::from_int(5) - Fixed::from_int(4) * p);
+        Fixed(value.0 * sign)
+    }
+
+    pub fn cos_turns(turns: Fixed) -> Self {
+        Self::sin_turns(turns + Fixed::from_ratio(1, 4))
+    }
+}
+
+/// Integer square root (floor) by Newton's method
+fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, rhs: Fixed) -> Fixed {
+        Fixed(((self.0 as i64 * rhs.0 as i64) >> FRAC_BITS) as i32)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+    fn div(self, rhs: Fixed) -> Fixed {
+        Fixed((((self.0 as i64) << FRAC_BITS) / rhs.0 as i64) as i32)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(-self.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Fixed) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Fixed) {
+        self.0 -= rhs.0;
+    }
+}
+
+/// A point or vector on the XZ ground plane; `y` maps to world Z
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FixedVec2 {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+
+impl FixedVec2 {
+    pub const ZERO: FixedVec2 = FixedVec2::new(Fixed::ZERO, Fixed::ZERO);
+
+    pub const fn new(x: Fixed, y: Fixed) -> Self {
+        Self { x, y }
+    }
+
+    pub fn to_vec2(self) -> Vec2 {
+        Vec2::new(self.x.to_f32(), self.y.to_f32())
+    }
+
+    /// World position at the given height
+    pub fn to_vec3(self, height: f32) -> Vec3 {
+        Vec3::new(self.x.to_f32(), height, self.y.to_f32())
+    }
+
+    /// Unit vector for an angle measured in turns, counter-clockwise from +X
+    pub fn from_turns(turns: Fixed) -> Self {
+        Self::new(Fixed::cos_turns(turns), Fixed::sin_turns(turns))
+    }
+
+    pub fn abs(self) -> Self {
+        Self::new(self.x.abs(), self.y.abs())
+    }
+
+    pub fn length_squared(self) -> Fixed {
+        self.x * self.x + self.y * self.y
+    }
+
+    pub fn length(self) -> Fixed {
+        self.length_squared().sqrt()
+    }
+
+    pub fn normalize_or(self, fallback: FixedVec2) -> Self {
+        let length = self.length();
+        if length == Fixed::ZERO {
+            return fallback;
+        }
+        Self::new(self.x / length, self.y / length)
+    }
+}
+
+impl Add for FixedVec2 {
+    type Output = FixedVec2;
+    fn add(self, rhs: FixedVec2) -> FixedVec2 {
+        FixedVec2::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Sub for FixedVec2 {
+    type Output = FixedVec2;
+    fn sub(self, rhs: FixedVec2) -> FixedVec2 {
+        FixedVec2::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl Mul<Fixed> for FixedVec2 {
+    type Output = FixedVec2;
+    fn mul(self, rhs: Fixed) -> FixedVec2 {
+        FixedVec2::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl Neg for FixedVec2 {
+    type Output = FixedVec2;
+    fn neg(self) -> FixedVec2 {
+        FixedVec2::new(-self.x, -self.y)
+    }
+}
+
+impl AddAssign for FixedVec2 {
+    fn add_assign(&mut self, rhs: FixedVec2) {
+        self.x += rhs.x;
+        self.y += rhs.y;
+    }
+}
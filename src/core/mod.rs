@@ -4,6 +4,7 @@ pub mod states;
 pub mod constants;
 pub mod args;
 pub mod resources;
+pub mod fixed;
 
 // Re-export commonly used items
 pub use states::GameState;
@@ -1,5 +1,12 @@
 // resources.rs
+use std::time::Duration;
 use bevy::prelude::*;
+use serde::Deserialize;
+use crate::core::{
+    args::{Args, BotDifficulty, GameMode},
+    constants::{MAX_INPUT_DELAY, MAX_PLAYERS, ROLLBACK_FPS},
+    fixed::Fixed,
+};
 
 #[derive(Resource, Clone, Deref, DerefMut)]
 pub struct RoundEndTimer(pub Timer);
@@ -10,38 +17,414 @@ impl Default for RoundEndTimer {
     }
 }
 
-#[derive(Resource, Default, Clone, Copy, Debug)]
-pub struct Scores(pub u32, pub u32);
+/// Rounds won per player handle. A fixed-size array keeps this `Copy` for rollback.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Scores {
+    pub rounds_played: u32,
+    pub by_handle: [u32; MAX_PLAYERS],
+}
+
+impl Scores {
+    pub fn get(&self, handle: usize) -> u32 {
+        self.by_handle.get(handle).copied().unwrap_or(0)
+    }
+}
 
 #[derive(Resource, Default, Clone, Copy, Debug, Deref, DerefMut)]
 pub struct SessionSeed(pub u64);
 
-/// Maps player handles (0 or 1) to their Kaspa wallet addresses and display names
+/// How players walk and sprint, from `assets/config/movement.json`
+///
+/// The file is compiled in rather than loaded, so every peer simulates with the same numbers.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovementTuning {
+    /// Units per second
+    pub walk_speed: Fixed,
+    /// Units per second
+    pub sprint_speed: Fixed,
+    /// Units per second squared, speeding up and slowing down alike
+    pub acceleration: Fixed,
+}
+
+/// `MovementTuning` as written in the file
+#[derive(Deserialize)]
+struct MovementTuningFile {
+    walk_speed: f32,
+    sprint_speed: f32,
+    acceleration: f32,
+}
+
+impl MovementTuning {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let file: MovementTuningFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        Ok(Self {
+            walk_speed: Fixed::from_f32(file.walk_speed),
+            sprint_speed: Fixed::from_f32(file.sprint_speed),
+            acceleration: Fixed::from_f32(file.acceleration),
+        })
+    }
+
+    /// Distance walked per rollback tick
+    pub fn walk_step(&self) -> Fixed {
+        self.walk_speed / Fixed::from_int(ROLLBACK_FPS as i32)
+    }
+
+    /// Distance sprinted per rollback tick
+    pub fn sprint_step(&self) -> Fixed {
+        self.sprint_speed / Fixed::from_int(ROLLBACK_FPS as i32)
+    }
+
+    /// Change in distance per tick from one tick to the next
+    pub fn acceleration_step(&self) -> Fixed {
+        let fps = Fixed::from_int(ROLLBACK_FPS as i32);
+        self.acceleration / fps / fps
+    }
+}
+
+impl Default for MovementTuning {
+    fn default() -> Self {
+        Self::parse(include_str!("../../assets/config/movement.json")).expect("movement config is valid")
+    }
+}
+
+/// Player count and team layout for the current match
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchRules {
+    pub num_players: usize,
+    pub mode: GameMode,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            num_players: 2,
+            mode: GameMode::FreeForAll,
+        }
+    }
+}
+
+impl MatchRules {
+    /// Presets offered in the lobby
+    pub const PRESETS: [MatchRules; 4] = [
+        MatchRules { num_players: 2, mode: GameMode::FreeForAll },
+        MatchRules { num_players: 4, mode: GameMode::Teams },
+        MatchRules { num_players: 4, mode: GameMode::FreeForAll },
+        MatchRules { num_players: 8, mode: GameMode::FreeForAll },
+    ];
+
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            num_players: (args.players as usize).clamp(2, MAX_PLAYERS),
+            mode: args.mode,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.mode {
+            GameMode::FreeForAll if self.num_players == 2 => "Duel".to_string(),
+            GameMode::FreeForAll => format!("Free-for-all ({})", self.num_players),
+            GameMode::Teams => {
+                let per_team = self.num_players.div_ceil(2);
+                format!("{per_team}v{}", self.num_players - per_team)
+            }
+        }
+    }
+
+    /// Suffix appended to the matchbox room so different modes never share a session
+    pub fn room_suffix(&self) -> &'static str {
+        match self.mode {
+            GameMode::FreeForAll => "",
+            GameMode::Teams => "_teams",
+        }
+    }
+
+    pub fn num_teams(&self) -> usize {
+        match self.mode {
+            GameMode::FreeForAll => self.num_players,
+            GameMode::Teams => 2,
+        }
+    }
+
+    /// Teams alternate by handle, so 2v2 is handles {0, 2} vs {1, 3}
+    pub fn team_of(&self, handle: usize) -> usize {
+        match self.mode {
+            GameMode::FreeForAll => handle,
+            GameMode::Teams => handle % 2,
+        }
+    }
+
+    pub fn team_members(&self, team: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.num_players).filter(move |&handle| self.team_of(handle) == team)
+    }
+
+    /// Position of a handle around the spawn ring, with teammates placed next to each other
+    pub fn spawn_slot(&self, handle: usize) -> usize {
+        match self.mode {
+            GameMode::FreeForAll => handle,
+            GameMode::Teams => {
+                let team = self.team_of(handle);
+                let first_slot: usize = (0..team).map(|t| self.team_members(t).count()).sum();
+                first_slot + handle / 2
+            }
+        }
+    }
+
+    /// Round wins are credited to every member of the winning team, so any member's score is the team's
+    pub fn team_score(&self, scores: &Scores, team: usize) -> u32 {
+        self.team_members(team)
+            .map(|handle| scores.get(handle))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn winning_team(&self, scores: &Scores, winning_score: u32) -> Option<usize> {
+        (0..self.num_teams()).find(|&team| self.team_score(scores, team) >= winning_score)
+    }
+}
+
+/// Bucket widths tried in order while queueing; past the last one we fall back to the open room
+pub const RATING_BUCKET_WIDTHS: [u32; 3] = [200, 400, 800];
+
+/// Rating range `[centre - width / 2, centre + width / 2)` sharing a matchbox room.
+/// Rooms are named by centre alone, and each tier's centres are a subset of the narrower
+/// tier's, so a widened search lands where narrower searches near that centre are waiting.
+/// A tier's edges never line up with the previous tier's, so two close ratings split by
+/// one boundary share a room after the next widening.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RatingBucket {
+    pub centre: u32,
+    pub width: u32,
+}
+
+impl RatingBucket {
+    /// Bucket for `rating` at the given widening tier, or `None` once every tier is exhausted
+    pub fn for_rating(rating: u32, tier: usize) -> Option<Self> {
+        let width = *RATING_BUCKET_WIDTHS.get(tier)?;
+        Some(Self {
+            centre: (rating + width / 2) / width * width,
+            width,
+        })
+    }
+
+    pub fn lower(&self) -> u32 {
+        self.centre.saturating_sub(self.width / 2)
+    }
+
+    pub fn upper(&self) -> u32 {
+        self.centre + self.width / 2
+    }
+}
+
+/// Which matchbox room the next matchmaking attempt joins
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MatchRoom {
+    #[default]
+    Public,
+    /// Public queue restricted to players of similar rating
+    Rated(RatingBucket),
+    /// Invite-only room identified by a short shareable code
+    Private(String),
+    /// Spectators only: the watch room of the match playing under this code
+    Watch(String),
+}
+
+/// Whether the next matchmaking attempt plays or only watches
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchRole {
+    #[default]
+    Player,
+    /// Follows a match through its players' watch relays; never takes a player handle
+    Spectator,
+}
+
+/// Difficulty of the computer opponent for the next match, or `None` to matchmake with people
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BotOpponent(pub Option<BotDifficulty>);
+
+impl BotOpponent {
+    pub fn from_args(args: &Args) -> Self {
+        Self(args.bot)
+    }
+}
+
+/// Whether the next match is played by everyone on this machine, each with their own gamepad
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CouchMatch(pub bool);
+
+impl CouchMatch {
+    pub fn from_args(args: &Args) -> Self {
+        Self(args.couch)
+    }
+}
+
+/// Whether this player asks for their online matches to be open to spectators
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WatchRequest(pub bool);
+
+impl WatchRequest {
+    pub fn from_args(args: &Args) -> Self {
+        Self(args.watchable)
+    }
+}
+
+/// Whether the match being played is relayed to its watch room; any player's request opens it,
+/// and every player sees every handshake, so they all agree
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WatchedMatch(pub bool);
+
+/// Frames of input delay for the next session, chosen in the lobby
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputDelay {
+    /// Looked up in `InputDelayTable` from the round-trip time measured during the handshake
+    #[default]
+    Auto,
+    Fixed(usize),
+}
+
+impl InputDelay {
+    pub fn from_args(args: &Args) -> Self {
+        match args.input_delay {
+            Some(frames) => InputDelay::Fixed(frames.min(MAX_INPUT_DELAY)),
+            None => InputDelay::Auto,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            InputDelay::Auto => "Auto (from ping)".to_string(),
+            InputDelay::Fixed(frames) => format!("{frames} frames"),
+        }
+    }
+}
+
+/// Round-trip time thresholds and the input delay used up to each, in ascending order
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct InputDelayTable(pub Vec<(Duration, usize)>);
+
+impl InputDelayTable {
+    pub fn from_args(args: &Args) -> Self {
+        Self::parse(&args.input_delay_table).unwrap_or_else(|e| {
+            warn!("Ignoring invalid input delay table {:?}: {e}", args.input_delay_table);
+            Self::default()
+        })
+    }
+
+    /// Parses `"40:1,80:2"` (round-trip milliseconds : frames)
+    pub fn parse(table: &str) -> Result<Self, String> {
+        let mut entries = table
+            .split(',')
+            .map(|entry| {
+                let (rtt, frames) = entry
+                    .split_once(':')
+                    .ok_or_else(|| format!("{entry:?} is not rtt_ms:frames"))?;
+                let rtt = rtt.trim().parse::<u64>().map_err(|e| format!("{rtt:?}: {e}"))?;
+                let frames = frames.trim().parse::<usize>().map_err(|e| format!("{frames:?}: {e}"))?;
+                Ok((Duration::from_millis(rtt), frames.min(MAX_INPUT_DELAY)))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        entries.sort();
+        Ok(Self(entries))
+    }
+
+    pub fn delay_for(&self, rtt: Duration) -> usize {
+        self.0
+            .iter()
+            .find(|(max_rtt, _)| rtt <= *max_rtt)
+            .or(self.0.last())
+            .map_or(0, |&(_, frames)| frames)
+    }
+}
+
+impl Default for InputDelayTable {
+    fn default() -> Self {
+        Self::parse("40:1,80:2,130:3,200:4,300:5").expect("default input delay table is valid")
+    }
+}
+
+/// Signalling servers and rooms, seeded from `Args` and editable in the lobby
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct ServerSettings {
+    pub matchbox_server: String,
+    pub matchbox_room: String,
+    pub chat_server: String,
+    pub chat_room: String,
+}
+
+impl ServerSettings {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            matchbox_server: args.matchbox_server.clone(),
+            matchbox_room: args.matchbox_room.clone(),
+            chat_server: args.chat_server.clone(),
+            chat_room: args.chat_room.clone(),
+        }
+    }
+
+    pub fn matchmaking_url(&self, rules: &MatchRules, room: &MatchRoom) -> String {
+        let room_id = match room {
+            MatchRoom::Public => self.matchbox_room.clone(),
+            MatchRoom::Rated(bucket) => {
+                format!("{}_r{}", self.matchbox_room, bucket.centre)
+            }
+            MatchRoom::Private(code) => format!("{}_private_{code}", self.matchbox_room),
+            MatchRoom::Watch(code) => return self.watch_url(code),
+        };
+        let url = format!(
+            "{}/{room_id}{}",
+            self.matchbox_server.trim_end_matches('/'),
+            rules.room_suffix()
+        );
+        match room {
+            // A full mesh, so an extra arrival finds the room full instead of starting a second match
+            MatchRoom::Private(_) => url,
+            _ => format!("{url}?next={}", rules.num_players),
+        }
+    }
+
+    /// Room every player of the match relays its inputs to, joined by spectators at any point
+    pub fn watch_url(&self, code: &str) -> String {
+        format!("{}/{}_watch_{code}", self.matchbox_server.trim_end_matches('/'), self.matchbox_room)
+    }
+
+    pub fn chat_url(&self) -> String {
+        format!("{}/{}?next=2", self.chat_server.trim_end_matches('/'), self.chat_room)
+    }
+}
+
+/// Wallet address, display name and loadout of a single player
+#[derive(Clone, Debug, Default)]
+pub struct PlayerInfo {
+    pub address: Option<String>,
+    pub display_name: Option<String>,
+    pub loadout: Vec<String>,
+}
+
+/// Maps player handles to their Kaspa wallet addresses and display names
 /// This is populated at the start of each match
 #[derive(Resource, Default, Clone, Debug)]
 pub struct PlayerAddressMapping {
     pub local_player_handle: Option<usize>,
-    pub player0_address: Option<String>,
-    pub player1_address: Option<String>,
-    pub player0_display_name: Option<String>,
-    pub player1_display_name: Option<String>,
+    pub players: Vec<PlayerInfo>,
 }
 
 impl PlayerAddressMapping {
-    pub fn get_address_by_handle(&self, handle: usize) -> Option<&String> {
-        match handle {
-            0 => self.player0_address.as_ref(),
-            1 => self.player1_address.as_ref(),
-            _ => None,
+    pub fn set_player(&mut self, handle: usize, address: String, display_name: String, loadout: Vec<String>) {
+        if self.players.len() <= handle {
+            self.players.resize(handle + 1, PlayerInfo::default());
         }
+        self.players[handle] = PlayerInfo {
+            address: Some(address),
+            display_name: Some(display_name),
+            loadout,
+        };
+    }
+
+    pub fn get_address_by_handle(&self, handle: usize) -> Option<&String> {
+        self.players.get(handle).and_then(|p| p.address.as_ref())
     }
 
     pub fn get_display_name_by_handle(&self, handle: usize) -> Option<&String> {
-        match handle {
-            0 => self.player0_display_name.as_ref(),
-            1 => self.player1_display_name.as_ref(),
-            _ => None,
-        }
+        self.players.get(handle).and_then(|p| p.display_name.as_ref())
     }
 
     pub fn get_local_address(&self) -> Option<&String> {
@@ -54,17 +437,10 @@ impl PlayerAddressMapping {
             .and_then(|handle| self.get_display_name_by_handle(handle))
     }
 
-    pub fn get_opponent_address(&self) -> Option<&String> {
-        self.local_player_handle.and_then(|handle| {
-            let opponent_handle = if handle == 0 { 1 } else { 0 };
-            self.get_address_by_handle(opponent_handle)
-        })
-    }
-
-    pub fn get_opponent_display_name(&self) -> Option<&String> {
-        self.local_player_handle.and_then(|handle| {
-            let opponent_handle = if handle == 0 { 1 } else { 0 };
-            self.get_display_name_by_handle(opponent_handle)
-        })
+    /// Display name for a handle, falling back to "Player N"
+    pub fn name_or_default(&self, handle: usize) -> String {
+        self.get_display_name_by_handle(handle)
+            .cloned()
+            .unwrap_or_else(|| format!("Player {}", handle + 1))
     }
-}
\ No newline at end of file
+}
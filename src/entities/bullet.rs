@@ -1,14 +1,17 @@
 // bullet.rs
 use bevy::prelude::*;
 use bevy_ggrs::AddRollbackCommandExtension;
-use crate::{Config, PlayerInputs, entities::components::{Bullet, BulletReady, MoveDir, Player}, core::constants::BULLET_RADIUS, game::input::fire};
+use crate::{Config, PlayerInputs, entities::components::{Aim, Bullet, BulletReady, MoveDir, Player, Position}, core::constants::{BULLET_DAMAGE, BULLET_RADIUS, BULLET_STEP}, core::fixed::Fixed, game::input::fire};
+
+/// How far in front of the player a shot leaves from
+const MUZZLE_DISTANCE: Fixed = Fixed::HALF;
 
 pub fn reload_bullet(
     inputs: Res<PlayerInputs<Config>>,
     mut players: Query<(&mut BulletReady, &Player)>,
 ) {
     for (mut can_fire, player) in players.iter_mut() {
-        let (input, _) = inputs[player.handle];
+        let input = inputs[player.handle].0.buttons;
         if !fire(input) {
             can_fire.0 = true;
         }
@@ -20,30 +23,18 @@ pub fn fire_bullets(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     inputs: Res<PlayerInputs<Config>>,
-    mut players: Query<(&Transform, &Player, &mut BulletReady, &MoveDir)>,
+    mut players: Query<(&Transform, &Position, &Player, &mut BulletReady, &Aim)>,
 ) {
-    for (transform, player, mut bullet_ready, move_dir) in &mut players {
-        let (input, _) = inputs[player.handle];
+    for (transform, player_pos, player, mut bullet_ready, aim) in &mut players {
+        let input = inputs[player.handle].0.buttons;
         if fire(input) && bullet_ready.0 {
-            let player_pos = transform.translation;
-            
-            // Muzzle offset in 3D space
-            let muzzle_offset = match move_dir.octant() {
-                0 => Vec3::new(0.5, 0.0, 0.0),
-                1 => Vec3::new(0.5, 0.0, 0.25),
-                2 => Vec3::new(0.25, 0.0, 0.5),
-                3 => Vec3::new(-0.4, 0.0, 0.3),
-                4 => Vec3::new(-0.5, 0.0, 0.0),
-                5 => Vec3::new(-0.4, 0.0, -0.25),
-                6 => Vec3::new(-0.25, 0.0, -0.5),
-                7 => Vec3::new(0.25, 0.0, -0.25),
-                _ => unreachable!(),
-            };
-            
-            let pos = player_pos + muzzle_offset;
+            // Shots leave along the aim, whichever way the player is moving
+            let position = player_pos.0 + aim.0 * MUZZLE_DISTANCE;
+            let pos = position.to_vec3(transform.translation.y);
             
             // Calculate rotation to face direction
-            let forward = Vec3::new(move_dir.0.x, 0.0, move_dir.0.y).normalize_or_zero();
+            let direction = aim.0.to_vec2();
+            let forward = Vec3::new(direction.x, 0.0, direction.y).normalize_or_zero();
             let rotation = if forward != Vec3::ZERO {
                 Quat::from_rotation_arc(Vec3::X, forward)
             } else {
@@ -52,8 +43,12 @@ pub fn fire_bullets(
             
             commands
                 .spawn((
-                    Bullet,
-                    *move_dir,
+                    Bullet {
+                        owner: player.handle,
+                        damage: BULLET_DAMAGE,
+                    },
+                    MoveDir(aim.0),
+                    Position(position),
                     Mesh3d(meshes.add(Capsule3d::new(BULLET_RADIUS, 0.3))),
                     MeshMaterial3d(materials.add(StandardMaterial {
                         base_color: Color::srgb(1.0, 1.0, 0.0),
@@ -69,10 +64,8 @@ pub fn fire_bullets(
     }
 }
 
-pub fn move_bullet(mut bullets: Query<(&mut Transform, &MoveDir), With<Bullet>>, time: Res<Time>) {
-    for (mut transform, dir) in &mut bullets {
-        let speed = 20.;
-        let delta = Vec3::new(dir.0.x, 0.0, dir.0.y) * speed * time.delta_secs();
-        transform.translation += delta;
+pub fn move_bullet(mut bullets: Query<(&mut Position, &MoveDir), With<Bullet>>) {
+    for (mut position, dir) in &mut bullets {
+        position.0 += dir.0 * BULLET_STEP;
     }
 }
\ No newline at end of file
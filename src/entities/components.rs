@@ -1,12 +1,7 @@
 use bevy::prelude::*;
-use bevy_ggrs::{checksum_hasher, PlayerInputs};
-use std::{
-    f32::consts::PI,
-    hash::{Hash, Hasher},
-};
 
 // Import from your crate
-use crate::Config;
+use crate::core::fixed::{Fixed, FixedVec2};
 
 /// Component to mark game entities that should be despawned when leaving InGame state
 #[derive(Component)]
@@ -18,67 +13,78 @@ pub struct Player {
     pub handle: usize,
 }
 
-#[derive(Component, Clone, Copy)]
+#[derive(Component, Clone, Copy, Hash)]
 pub struct BulletReady(pub bool);
 
 #[derive(Component, Clone, Copy)]
-pub struct Bullet;
-
-#[derive(Component, Clone, Copy)]
-pub struct MoveDir(pub Vec2);
-
-impl MoveDir {
-    /// Gets the index of the octant (45 degree sectors), starting from 0 (right) and going counter-clockwise:
-    pub fn octant(&self) -> usize {
-        // in radians, signed: 0 is right, PI/2 is up, -PI/2 is down
-        let angle = self.0.to_angle();
-
-        // divide the angle by 45 degrees (PI/4) to get the octant
-        let octant = (angle / (PI / 4.)).round() as i32;
-
-        // convert to an octant index in the range [0, 7]
-        let octant = if octant < 0 { octant + 8 } else { octant } as usize;
-
-        octant
+pub struct Bullet {
+    /// Handle of the player who fired it
+    pub owner: usize,
+    pub damage: i32,
+}
+
+/// Last direction moved in, or a bullet's heading; fixed-point like `Position`
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MoveDir(pub FixedVec2);
+
+/// Where the player faces: the aim from the input while aiming, otherwise `MoveDir`;
+/// shots, swings and the guard all point this way
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Aim(pub FixedVec2);
+
+/// Authoritative ground-plane position in fixed-point; `Transform` is derived from it
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Position(pub FixedVec2);
+
+/// Copies simulated positions into `Transform` so rendering and the snapshot agree
+pub fn sync_transforms(mut query: Query<(&Position, &mut Transform), Changed<Position>>) {
+    for (position, mut transform) in &mut query {
+        transform.translation.x = position.0.x.to_f32();
+        transform.translation.z = position.0.y.to_f32();
     }
 }
 
-#[derive(Component, Default, Clone, Copy)]
-pub struct DistanceTraveled(pub f32);
+/// Current ground speed, per rollback tick, and whether it's a sprint
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Locomotion {
+    pub velocity: FixedVec2,
+    pub sprinting: bool,
+}
+
+#[derive(Component, Default, Clone, Copy, Hash)]
+pub struct DistanceTraveled(pub Fixed);
 
 #[derive(Component, Clone, Copy)]
 pub struct Wall;
 
-pub fn checksum_transform(transform: &Transform) -> u64 {
-    let mut hasher = checksum_hasher();
-
-    assert!(
-        transform.is_finite(),
-        "Hashing is not stable for NaN f32 values."
-    );
-
-    transform.translation.x.to_bits().hash(&mut hasher);
-    transform.translation.y.to_bits().hash(&mut hasher);
-    transform.translation.z.to_bits().hash(&mut hasher);
-
-    transform.rotation.x.to_bits().hash(&mut hasher);
-    transform.rotation.y.to_bits().hash(&mut hasher);
-    transform.rotation.z.to_bits().hash(&mut hasher);
-    transform.rotation.w.to_bits().hash(&mut hasher);
-
-    // skip transform.scale as it's not used for gameplay
-
-    hasher.finish()
-}
-
 // ============= COMBAT COMPONENTS =============
 
-#[derive(Component, Clone, Copy, Debug, Default)]
+/// Where a player is in their melee moveset; counted in rollback frames so every peer agrees
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct CombatState {
     pub current_action: CombatAction,
-    pub can_cancel: bool,
-    pub can_combo: bool,
-    pub combo_window_active: bool,
+    /// Frames spent in `current_action`
+    pub action_frames: u32,
+    /// Swings thrown in the current combo
+    pub combo_count: u8,
+    /// Frames left to chain the next swing after the last one finished
+    pub combo_frames: u32,
+    /// Attack was held last frame; every swing needs a fresh press
+    pub attack_held: bool,
+    /// Dodge was held last frame; every roll needs a fresh press
+    pub dodge_held: bool,
+    /// Frames until the next roll is allowed
+    pub dodge_cooldown: u32,
+    /// Block was held last frame; only a fresh press raises the guard, and with it a parry
+    pub block_held: bool,
+}
+
+impl CombatState {
+    /// Moves on to `action` from its first frame
+    pub fn start(&mut self, action: CombatAction) {
+        self.current_action = action;
+        self.action_frames = 0;
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -89,52 +95,93 @@ pub enum CombatAction {
     Attack2,
     Attack3,
     Dodge,
+    /// Guard up until block is released or stamina runs out
     Block,
     Hit,
-    Dead,
+    /// Swing knocked aside by a parry
+    Staggered,
 }
 
-#[derive(Component, Clone, Copy, Debug)]
+/// Spent by attacks, dodges, sprinting and blocking; refills after a pause
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Stamina {
-    pub current: f32,
-    pub max: f32,
-    pub regen_rate: f32,
-    pub regen_delay: f32,
-    pub last_use_time: f32,
+    pub current: Fixed,
+    pub max: Fixed,
+    /// Refilled per second once regen starts
+    pub regen_rate: Fixed,
+    /// Frames after spending before regen starts
+    pub regen_delay: u32,
+    /// Frames since stamina was last spent
+    pub frames_since_use: u32,
 }
 
 impl Default for Stamina {
     fn default() -> Self {
         Self {
-            current: 100.0,
-            max: 100.0,
-            regen_rate: 20.0,
-            regen_delay: 1.0,
-            last_use_time: 0.0,
+            current: Fixed::from_int(100),
+            max: Fixed::from_int(100),
+            regen_rate: Fixed::from_int(20),
+            regen_delay: 60,
+            frames_since_use: 0,
+        }
+    }
+}
+
+impl Stamina {
+    /// Pays `cost` if there's enough for all of it
+    pub fn try_spend(&mut self, cost: Fixed) -> bool {
+        if self.current < cost {
+            return false;
         }
+        self.current -= cost;
+        self.frames_since_use = 0;
+        true
     }
 }
 
-#[derive(Component, Clone, Copy, Debug, Default)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Health {
-    pub current: f32,
-    pub max: f32,
+    pub current: i32,
+    pub max: i32,
 }
 
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: 100,
+            max: 100,
+        }
+    }
+}
+
+/// A melee swing's reach while it's out; spawned by the attacker, gone after one hit
 #[derive(Component, Clone, Copy, Debug)]
 pub struct AttackBox {
-    pub active: bool,
-    pub damage: f32,
-    pub range: f32,
-    pub knockback: f32,
-    pub owner: Entity,
+    /// Handle of the player swinging
+    pub owner: usize,
+    pub damage: i32,
+    /// Anyone whose centre is closer than this to the box's `Position` is hit
+    pub radius: Fixed,
+    /// Frames left before the swing stops connecting
+    pub frames_left: u32,
 }
 
-#[derive(Component, Clone, Copy, Debug, Default)]
+/// Frames left in which hits don't land
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Invincible {
-    pub active: bool,
-    pub duration: f32,
-    pub elapsed: f32,
+    pub frames_left: u32,
+}
+
+impl Invincible {
+    pub fn active(&self) -> bool {
+        self.frames_left > 0
+    }
+}
+
+/// Percent of a frontal hit a block stops; set by the shield in the player's loadout for the whole match
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Guard {
+    pub reduction: i32,
 }
 
 #[derive(Component, Clone, Copy, Debug)]
@@ -173,50 +220,14 @@ impl Default for AnimationState {
     }
 }
 
-#[derive(Component, Clone, Copy, Debug, Default)]
-pub struct ComboState {
-    pub combo_count: u32,
-    pub max_combo: u32,
-    pub combo_timer: f32,
-    pub combo_window: f32,
-}
-
 // ============= COMBAT COSTS =============
 
-pub const ATTACK_STAMINA_COST: f32 = 20.0;
-pub const DODGE_STAMINA_COST: f32 = 25.0;
-pub const BLOCK_STAMINA_DRAIN: f32 = 10.0;
-pub const SPRINT_STAMINA_DRAIN: f32 = 15.0;
-
-// ============= ATTACK TIMINGS =============
-
-pub struct AttackTiming {
-    pub startup: f32,      // Frames before hitbox activates
-    pub active: f32,       // Frames hitbox is active
-    pub recovery: f32,     // Frames after hitbox deactivates
-    pub combo_window: f32, // When you can chain next attack
-}
-
-pub const ATTACK1_TIMING: AttackTiming = AttackTiming {
-    startup: 0.2,
-    active: 0.15,
-    recovery: 0.35,
-    combo_window: 0.5,
-};
-
-pub const ATTACK2_TIMING: AttackTiming = AttackTiming {
-    startup: 0.25,
-    active: 0.2,
-    recovery: 0.4,
-    combo_window: 0.6,
-};
-
-pub const ATTACK3_TIMING: AttackTiming = AttackTiming {
-    startup: 0.3,
-    active: 0.25,
-    recovery: 0.6,
-    combo_window: 0.0, // No combo after final attack
-};
+pub const ATTACK_STAMINA_COST: Fixed = Fixed::from_int(20);
+pub const DODGE_STAMINA_COST: Fixed = Fixed::from_int(25);
+/// Per second held
+pub const BLOCK_STAMINA_DRAIN: Fixed = Fixed::from_int(10);
+/// Per second held
+pub const SPRINT_STAMINA_DRAIN: Fixed = Fixed::from_int(15);
 
 // ============= ANIMATION MAPPINGS =============
 
@@ -237,305 +248,3 @@ pub struct AnimationClips {
     pub hit_react: Handle<AnimationClip>,
     pub death: Handle<AnimationClip>,
 }
-
-// ============= COMBAT SYSTEMS =============
-
-pub fn combat_input_system(
-    mut players: Query<(
-        &mut CombatState,
-        &mut Stamina,
-        &mut AnimationState,
-        &mut ComboState,
-        &Player,
-    )>,
-    time: Res<Time>,
-    inputs: Res<PlayerInputs<Config>>,
-) {
-    use crate::game::input::{attack, dodge, block};
-    
-    for (mut combat, mut stamina, mut anim, mut combo, player) in &mut players {
-        let (input, _) = inputs[player.handle];
-        
-        // Skip if in uninterruptible action
-        if matches!(combat.current_action, CombatAction::Hit | CombatAction::Dead) {
-            continue;
-        }
-        
-        // Update combo timer
-        if combo.combo_timer > 0.0 {
-            combo.combo_timer -= time.delta_secs();
-        } else if combo.combo_count > 0 {
-            combo.combo_count = 0; // Reset combo
-        }
-        
-        // Attack input
-        if attack(input) && stamina.current >= ATTACK_STAMINA_COST {
-            if combat.current_action == CombatAction::Idle || combat.can_combo {
-                // Determine which attack in combo
-                let next_action = match combo.combo_count {
-                    0 => {
-                        anim.current_animation = AnimationType::Attack1;
-                        CombatAction::Attack1
-                    }
-                    1 => {
-                        anim.current_animation = AnimationType::Attack2;
-                        CombatAction::Attack2
-                    }
-                    2 => {
-                        anim.current_animation = AnimationType::Attack3;
-                        CombatAction::Attack3
-                    }
-                    _ => continue,
-                };
-                
-                combat.current_action = next_action;
-                combat.can_combo = false;
-                anim.animation_time = 0.0;
-                
-                // Consume stamina
-                stamina.current -= ATTACK_STAMINA_COST;
-                stamina.last_use_time = time.elapsed_secs();
-                
-                // Advance combo
-                combo.combo_count = (combo.combo_count + 1).min(combo.max_combo);
-                combo.combo_timer = combo.combo_window;
-            }
-        }
-        
-        // Dodge input
-        if dodge(input) && stamina.current >= DODGE_STAMINA_COST {
-            if combat.can_cancel || combat.current_action == CombatAction::Idle {
-                combat.current_action = CombatAction::Dodge;
-                anim.current_animation = AnimationType::Dodge;
-                anim.animation_time = 0.0;
-                
-                stamina.current -= DODGE_STAMINA_COST;
-                stamina.last_use_time = time.elapsed_secs();
-                
-                // Reset combo
-                combo.combo_count = 0;
-                combo.combo_timer = 0.0;
-            }
-        }
-        
-        // Block input (hold)
-        if block(input) {
-            if combat.current_action == CombatAction::Idle {
-                combat.current_action = CombatAction::Block;
-            }
-        } else if combat.current_action == CombatAction::Block {
-            combat.current_action = CombatAction::Idle;
-        }
-    }
-}
-
-pub fn stamina_system(
-    mut players: Query<(&mut Stamina, &CombatState)>,
-    time: Res<Time>,
-) {
-    for (mut stamina, combat) in &mut players {
-        let current_time = time.elapsed_secs();
-        
-        // Drain stamina while blocking
-        if combat.current_action == CombatAction::Block {
-            stamina.current -= BLOCK_STAMINA_DRAIN * time.delta_secs();
-            stamina.current = stamina.current.max(0.0);
-            stamina.last_use_time = current_time;
-        }
-        
-        // Regenerate stamina after delay
-        if current_time - stamina.last_use_time >= stamina.regen_delay {
-            stamina.current += stamina.regen_rate * time.delta_secs();
-            stamina.current = stamina.current.min(stamina.max);
-        }
-    }
-}
-
-pub fn animation_state_system(
-    mut players: Query<(
-        &mut AnimationState,
-        &mut CombatState,
-        &MoveDir,
-        &DistanceTraveled,
-    )>,
-    time: Res<Time>,
-) {
-    for (mut anim_state, mut combat, move_dir, _distance) in &mut players {
-        // Update animation time
-        anim_state.animation_time += time.delta_secs();
-        
-        // Handle attack animations with timing
-        match combat.current_action {
-            CombatAction::Attack1 => {
-                if anim_state.animation_time > ATTACK1_TIMING.startup + ATTACK1_TIMING.active {
-                    if anim_state.animation_time < ATTACK1_TIMING.startup + ATTACK1_TIMING.active + ATTACK1_TIMING.recovery {
-                        combat.combo_window_active = true;
-                        combat.can_combo = true;
-                    } else {
-                        combat.current_action = CombatAction::Idle;
-                        combat.can_combo = false;
-                        combat.combo_window_active = false;
-                    }
-                }
-            }
-            CombatAction::Attack2 => {
-                if anim_state.animation_time > ATTACK2_TIMING.startup + ATTACK2_TIMING.active {
-                    if anim_state.animation_time < ATTACK2_TIMING.startup + ATTACK2_TIMING.active + ATTACK2_TIMING.recovery {
-                        combat.combo_window_active = true;
-                        combat.can_combo = true;
-                    } else {
-                        combat.current_action = CombatAction::Idle;
-                        combat.can_combo = false;
-                        combat.combo_window_active = false;
-                    }
-                }
-            }
-            CombatAction::Attack3 => {
-                if anim_state.animation_time > ATTACK3_TIMING.startup + ATTACK3_TIMING.active + ATTACK3_TIMING.recovery {
-                    combat.current_action = CombatAction::Idle;
-                    combat.can_combo = false;
-                    combat.combo_window_active = false;
-                }
-            }
-            CombatAction::Dodge => {
-                if anim_state.animation_time > 0.6 {
-                    combat.current_action = CombatAction::Idle;
-                }
-            }
-            CombatAction::Idle => {
-                // Choose appropriate idle/movement animation
-                if move_dir.0.length() > 0.01 {
-                    anim_state.current_animation = AnimationType::WalkForward;
-                } else {
-                    anim_state.current_animation = AnimationType::Idle;
-                }
-            }
-            _ => {}
-        }
-    }
-}
-
-pub fn attack_hitbox_system(
-    mut commands: Commands,
-    players: Query<(
-        Entity,
-        &Transform,
-        &CombatState,
-        &AnimationState,
-        &MoveDir,
-    )>,
-) {
-    for (entity, transform, combat, anim, move_dir) in &players {
-        let (should_spawn, damage, range) = match combat.current_action {
-            CombatAction::Attack1 => {
-                if anim.animation_time >= ATTACK1_TIMING.startup 
-                    && anim.animation_time <= ATTACK1_TIMING.startup + ATTACK1_TIMING.active {
-                    (true, 25.0, 2.0)
-                } else {
-                    (false, 0.0, 0.0)
-                }
-            }
-            CombatAction::Attack2 => {
-                if anim.animation_time >= ATTACK2_TIMING.startup 
-                    && anim.animation_time <= ATTACK2_TIMING.startup + ATTACK2_TIMING.active {
-                    (true, 30.0, 2.2)
-                } else {
-                    (false, 0.0, 0.0)
-                }
-            }
-            CombatAction::Attack3 => {
-                if anim.animation_time >= ATTACK3_TIMING.startup 
-                    && anim.animation_time <= ATTACK3_TIMING.startup + ATTACK3_TIMING.active {
-                    (true, 40.0, 2.5)
-                } else {
-                    (false, 0.0, 0.0)
-                }
-            }
-            _ => (false, 0.0, 0.0),
-        };
-        
-        if should_spawn {
-            // Spawn hitbox in front of player
-            let forward = Vec3::new(move_dir.0.x, 0.0, move_dir.0.y).normalize_or_zero();
-            let hitbox_pos = transform.translation + forward * range / 2.0;
-            
-            commands.spawn((
-                AttackBox {
-                    active: true,
-                    damage,
-                    range,
-                    knockback: 1.5,
-                    owner: entity,
-                },
-                Transform::from_translation(hitbox_pos),
-                GlobalTransform::default(),
-            ));
-        }
-    }
-}
-
-pub fn damage_system(
-    mut commands: Commands,
-    hitboxes: Query<(Entity, &AttackBox, &Transform)>,
-    mut targets: Query<(
-        Entity,
-        &Transform,
-        &mut Health,
-        &mut CombatState,
-        &Invincible,
-    ), Without<AttackBox>>,
-) {
-    for (hitbox_entity, attack, hitbox_transform) in &hitboxes {
-        for (target_entity, target_transform, mut health, mut combat, invincible) in &mut targets {
-            // Don't hit yourself
-            if target_entity == attack.owner {
-                continue;
-            }
-            
-            // Skip if invincible
-            if invincible.active {
-                continue;
-            }
-            
-            let distance = hitbox_transform.translation.distance(target_transform.translation);
-            
-            if distance < attack.range {
-                // Apply damage
-                health.current -= attack.damage;
-                
-                // Set hit state
-                if health.current <= 0.0 {
-                    combat.current_action = CombatAction::Dead;
-                } else {
-                    combat.current_action = CombatAction::Hit;
-                }
-                
-                // Remove hitbox after hit
-                commands.entity(hitbox_entity).despawn();
-                break;
-            }
-        }
-    }
-}
-
-pub fn invincibility_system(
-    mut players: Query<(&mut Invincible, &CombatState)>,
-    time: Res<Time>,
-) {
-    for (mut invincible, combat) in &mut players {
-        // Activate iframes during dodge
-        if combat.current_action == CombatAction::Dodge {
-            invincible.active = true;
-            invincible.duration = 0.4; // 400ms of iframes
-            invincible.elapsed = 0.0;
-        }
-        
-        // Update iframe timer
-        if invincible.active {
-            invincible.elapsed += time.delta_secs();
-            if invincible.elapsed >= invincible.duration {
-                invincible.active = false;
-            }
-        }
-    }
-}
\ No newline at end of file
@@ -7,7 +7,47 @@ use bevy::prelude::*;
 use bevy::animation::{AnimationPlayer, graph::{AnimationGraph, AnimationGraphHandle, AnimationNodeIndex}};
 use bevy::asset::Assets;
 
-use crate::entities::components::{Player, MoveDir, AnimationState, AnimationType};
+use crate::entities::components::{AnimationState, AnimationType, CombatAction, CombatState, Locomotion};
+use crate::core::fixed::FixedVec2;
+
+/// The animation a combat action shows; `None` leaves locomotion in charge
+fn combat_animation(action: CombatAction) -> Option<AnimationType> {
+    match action {
+        CombatAction::Idle => None,
+        CombatAction::Attack1 => Some(AnimationType::Attack1),
+        CombatAction::Attack2 => Some(AnimationType::Attack2),
+        CombatAction::Attack3 => Some(AnimationType::Attack3),
+        CombatAction::Dodge => Some(AnimationType::Dodge),
+        // No guard clip yet; the alert stance is the closest to a raised shield
+        CombatAction::Block => Some(AnimationType::IdleAlert),
+        CombatAction::Hit | CombatAction::Staggered => Some(AnimationType::HitReact),
+    }
+}
+
+/// Mirrors the rollback `CombatState` and `Locomotion` into the cosmetic `AnimationState` each rendered frame
+///
+/// `animation_time` restarts with every new action, so a roll replayed after a rollback
+/// picks up where the simulation says it is rather than where the last render left it.
+pub fn update_animation_state(
+    time: Res<Time>,
+    mut players: Query<(&CombatState, &Locomotion, &mut AnimationState)>,
+) {
+    for (combat, locomotion, mut animation) in &mut players {
+        let wanted = combat_animation(combat.current_action).unwrap_or(if locomotion.sprinting {
+            AnimationType::RunForward
+        } else if locomotion.velocity != FixedVec2::ZERO {
+            AnimationType::WalkForward
+        } else {
+            AnimationType::Idle
+        });
+        if animation.current_animation != wanted {
+            animation.current_animation = wanted;
+            animation.animation_time = 0.0;
+        } else {
+            animation.animation_time += time.delta_secs();
+        }
+    }
+}
 
 // TODO: AnimationDirection needs to be defined or this code needs to be updated
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -0,0 +1,265 @@
+// bot.rs - Computer opponent that plays player handle 1 in a local session
+//
+// The bot only ever produces input bits, exactly like `read_local_inputs`, so GGRS records
+// and replays it like a human. Its decisions use fixed-point math and a BFS with a fixed
+// neighbour order, so the same match state always yields the same input on every target.
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use bevy_ggrs::{LocalInputs, RollbackFrameCount};
+use crate::{
+    Config,
+    core::args::BotDifficulty,
+    core::constants::{MAP_SIZE, PLAYER_RADIUS},
+    core::fixed::{Fixed, FixedVec2, FRAC_BITS},
+    core::resources::{BotOpponent, MatchRules},
+    entities::components::{Aim, BulletReady, Player, Position, Wall},
+    game::input::{direction, PlayerInput, INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_RIGHT, INPUT_UP},
+    world::collisions::wall_bounds,
+};
+
+/// The handle the bot plays in a local session
+pub const BOT_HANDLE: usize = 1;
+
+/// The eight ways a player can face, as input bits; bullets fly along these
+const OCTANT_INPUTS: [u8; 8] = [
+    INPUT_RIGHT,
+    INPUT_RIGHT | INPUT_DOWN,
+    INPUT_DOWN,
+    INPUT_LEFT | INPUT_DOWN,
+    INPUT_LEFT,
+    INPUT_LEFT | INPUT_UP,
+    INPUT_UP,
+    INPUT_RIGHT | INPUT_UP,
+];
+
+/// Grid offsets matching `OCTANT_INPUTS`; +y is down the screen, like `direction`
+const OCTANT_STEPS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+/// Spacing of the line-of-sight samples; thinner than any wall
+const SIGHT_STEP: Fixed = Fixed::from_ratio(1, 4);
+
+/// How a difficulty plays
+struct BotSkill {
+    /// Frames between decisions; the input is held in between
+    reaction_frames: i32,
+    /// Fires when the target is within this distance of the bullet's line
+    aim_tolerance: Fixed,
+    /// Won't shoot at targets further away than this
+    engage_range: Fixed,
+}
+
+fn skill(difficulty: BotDifficulty) -> BotSkill {
+    match difficulty {
+        BotDifficulty::Easy => BotSkill {
+            reaction_frames: 20,
+            aim_tolerance: Fixed::from_int(1),
+            engage_range: Fixed::from_int(8),
+        },
+        BotDifficulty::Medium => BotSkill {
+            reaction_frames: 8,
+            aim_tolerance: Fixed::from_ratio(1, 2),
+            engage_range: Fixed::from_int(14),
+        },
+        BotDifficulty::Hard => BotSkill {
+            reaction_frames: 2,
+            aim_tolerance: Fixed::from_ratio(3, 10),
+            engage_range: Fixed::from_int(MAP_SIZE),
+        },
+    }
+}
+
+/// The bot's held input; reset with every session so decisions line up with frame 0
+#[derive(Resource, Default, Debug)]
+pub struct BotBrain {
+    pub input: u8,
+    pub next_decision: i32,
+}
+
+pub fn bot_match(bot: Res<BotOpponent>) -> bool {
+    bot.0.is_some()
+}
+
+/// Overrides the keyboard input `read_local_inputs` wrote for the bot's handle
+pub fn bot_inputs(
+    mut local_inputs: ResMut<LocalInputs<Config>>,
+    mut brain: ResMut<BotBrain>,
+    bot: Res<BotOpponent>,
+    rules: Res<MatchRules>,
+    frame: Res<RollbackFrameCount>,
+    players: Query<(&Player, &Position, &Aim, &BulletReady)>,
+    walls: Query<&Transform, With<Wall>>,
+) {
+    let Some(difficulty) = bot.0 else {
+        return;
+    };
+    let skill = skill(difficulty);
+
+    if frame.0 >= brain.next_decision {
+        brain.next_decision = frame.0 + skill.reaction_frames;
+        let walls: Vec<(FixedVec2, FixedVec2)> = walls.iter().map(wall_bounds).collect();
+        brain.input = decide(&skill, &rules, &players, &walls);
+    }
+
+    // Fire is a trigger pull: never hold it past the shot, or the gun won't reload
+    let ready = players
+        .iter()
+        .find(|(player, ..)| player.handle == BOT_HANDLE)
+        .is_some_and(|(.., ready)| ready.0);
+    let mut input = brain.input;
+    if !ready {
+        input &= !INPUT_FIRE;
+    }
+    if input & INPUT_FIRE != 0 {
+        brain.input &= !INPUT_FIRE;
+    }
+
+    // The bot never aims, so it faces where it walks like a keyboard-only player
+    local_inputs.0.insert(BOT_HANDLE, PlayerInput::from_buttons(input));
+}
+
+fn decide(
+    skill: &BotSkill,
+    rules: &MatchRules,
+    players: &Query<(&Player, &Position, &Aim, &BulletReady)>,
+    walls: &[(FixedVec2, FixedVec2)],
+) -> u8 {
+    let Some((_, &Position(me), &Aim(facing), _)) =
+        players.iter().find(|(player, ..)| player.handle == BOT_HANDLE)
+    else {
+        // Dead until the next round
+        return 0;
+    };
+
+    let bot_team = rules.team_of(BOT_HANDLE);
+    let Some(target) = players
+        .iter()
+        .filter(|(player, ..)| rules.team_of(player.handle) != bot_team)
+        .map(|(player, position, ..)| (player.handle, position.0))
+        .min_by_key(|&(handle, position)| ((position - me).length_squared(), handle))
+        .map(|(_, position)| position)
+    else {
+        return 0;
+    };
+
+    let to_target = target - me;
+    let in_sight = line_of_sight(me, target, walls);
+
+    if in_sight && to_target.length() <= skill.engage_range {
+        if let Some(octant) = aimed_octant(to_target, skill.aim_tolerance) {
+            let input = OCTANT_INPUTS[octant];
+            // Turning takes a frame of movement, so only pull the trigger once facing the target
+            let aimed = direction(input) == facing;
+            return if aimed { input | INPUT_FIRE } else { input };
+        }
+        return line_up(to_target);
+    }
+
+    next_step(me, target, walls)
+}
+
+/// The octant whose bullet line passes within `tolerance` of the target, if any
+fn aimed_octant(to_target: FixedVec2, tolerance: Fixed) -> Option<usize> {
+    OCTANT_INPUTS
+        .iter()
+        .enumerate()
+        .filter_map(|(octant, &input)| {
+            let aim = direction(input);
+            let along = to_target.x * aim.x + to_target.y * aim.y;
+            let off_line = (to_target.x * aim.y - to_target.y * aim.x).abs();
+            (along > Fixed::ZERO && off_line <= tolerance).then_some((off_line, octant))
+        })
+        .min()
+        .map(|(_, octant)| octant)
+}
+
+/// Steps onto the nearest horizontal, vertical or diagonal line through the target
+fn line_up(to_target: FixedVec2) -> u8 {
+    let (dx, dy) = (to_target.x.abs(), to_target.y.abs());
+    let towards_x = if to_target.x > Fixed::ZERO { INPUT_RIGHT } else { INPUT_LEFT };
+    let towards_y = if to_target.y > Fixed::ZERO { INPUT_DOWN } else { INPUT_UP };
+    let diagonal_gap = (dx - dy).abs();
+
+    if dx <= dy && dx <= diagonal_gap {
+        towards_x
+    } else if dy <= diagonal_gap {
+        towards_y
+    } else if dx > dy {
+        towards_x
+    } else {
+        towards_y
+    }
+}
+
+fn inside_wall(point: FixedVec2, margin: Fixed, walls: &[(FixedVec2, FixedVec2)]) -> bool {
+    walls.iter().any(|&(centre, size)| {
+        let gap = (point - centre).abs() - size * Fixed::HALF;
+        gap.x < margin && gap.y < margin
+    })
+}
+
+fn line_of_sight(from: FixedVec2, to: FixedVec2, walls: &[(FixedVec2, FixedVec2)]) -> bool {
+    let delta = to - from;
+    let steps = (delta.length() / SIGHT_STEP).0 >> FRAC_BITS;
+    (1..=steps).all(|step| {
+        let t = Fixed::from_ratio(step, steps.max(1));
+        !inside_wall(from + delta * t, Fixed::ZERO, walls)
+    })
+}
+
+/// Map cells are one unit wide, centred on integer coordinates
+const GRID_HALF: i32 = (MAP_SIZE - 1) / 2;
+const GRID_WIDTH: usize = MAP_SIZE as usize;
+
+fn cell_of(point: FixedVec2) -> (i32, i32) {
+    let round = |value: Fixed| ((value + Fixed::HALF).0 >> FRAC_BITS).clamp(-GRID_HALF, GRID_HALF);
+    (round(point.x), round(point.y))
+}
+
+fn cell_index((x, y): (i32, i32)) -> usize {
+    (y + GRID_HALF) as usize * GRID_WIDTH + (x + GRID_HALF) as usize
+}
+
+/// First move of the shortest walkable path to the target's cell (breadth-first, 8-connected)
+fn next_step(from: FixedVec2, to: FixedVec2, walls: &[(FixedVec2, FixedVec2)]) -> u8 {
+    let player_radius = Fixed::from_f32(PLAYER_RADIUS);
+    let blocked = |(x, y): (i32, i32)| {
+        inside_wall(FixedVec2::new(Fixed::from_int(x), Fixed::from_int(y)), player_radius, walls)
+    };
+
+    let start = cell_of(from);
+    let goal = cell_of(to);
+    if start == goal {
+        return 0;
+    }
+
+    // Remembers which first step reached each cell, so the path needn't be walked back
+    let mut first_step: Vec<Option<u8>> = vec![None; GRID_WIDTH * GRID_WIDTH];
+    let mut queue = VecDeque::from([start]);
+    first_step[cell_index(start)] = Some(0);
+
+    while let Some(cell) = queue.pop_front() {
+        for (&input, &(dx, dy)) in OCTANT_INPUTS.iter().zip(&OCTANT_STEPS) {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if next.0.abs() > GRID_HALF || next.1.abs() > GRID_HALF {
+                continue;
+            }
+            // No cutting corners past a wall
+            let cuts_corner = dx != 0 && dy != 0 && (blocked((cell.0 + dx, cell.1)) || blocked((cell.0, cell.1 + dy)));
+            if blocked(next) || cuts_corner {
+                continue;
+            }
+            if first_step[cell_index(next)].is_some() {
+                continue;
+            }
+            let first = if cell == start { input } else { first_step[cell_index(cell)].unwrap_or(input) };
+            if next == goal {
+                return first;
+            }
+            first_step[cell_index(next)] = Some(first);
+            queue.push_back(next);
+        }
+    }
+
+    // Walled off: head straight for the target and let the collisions sort it out
+    line_up(to - from)
+}
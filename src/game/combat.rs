@@ -1,352 +1,473 @@
-// combat.rs
+// combat.rs - Melee combos, hitboxes and hit stun, simulated inside the rollback schedule
 use bevy::prelude::*;
 use bevy_roll_safe::prelude::*;
-use bevy_ggrs::{PlayerInputs, Rollback, AddRollbackCommandExtension};
-use crate::entities::components::*;
-use crate::core::constants::*;
-use crate::Config;
-use crate::game::input::{INPUT_FIRE, INPUT_DOWN};
-
-// Combat Components
-#[derive(Component, Copy, Clone, Debug, PartialEq)]
-pub struct CombatState {
-    pub current_action: CombatAction,
-    pub combo_count: u8,
-    pub last_attack_time: f32,
-    pub attack_cooldown: f32,
-    pub is_attacking: bool,
-    pub hit_landed: bool,
-}
+use bevy_ggrs::{PlayerInputs, AddRollbackCommandExtension, RollbackFrameCount};
+use crate::{
+    Config,
+    core::constants::{PLAYER_HEIGHT, PLAYER_RADIUS, ROLLBACK_FPS},
+    core::fixed::{Fixed, FixedVec2},
+    core::resources::MatchRules,
+    core::states::RollbackState,
+    entities::bullet::{fire_bullets, reload_bullet},
+    entities::components::*,
+    game::input::{attack, block, dodge},
+    game::player::move_players,
+    world::collisions::{kill_players, resolve_wall_collisions},
+};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum CombatAction {
-    Idle,
-    Walking,
-    Running,
-    Crouching,
-    Attack(u8), // Attack combo number (1-3)
-    Hit,
-    Death,
+/// Frame timings, damage and reach of one swing of the combo
+pub struct Swing {
+    /// Frames before the hitbox comes out
+    pub startup: u32,
+    /// Frames the hitbox stays out
+    pub active: u32,
+    /// Frames after the hitbox is gone; the next swing can be chained from here on
+    pub recovery: u32,
+    /// Frames after the swing ends in which a press still chains the next one
+    pub combo_window: u32,
+    pub damage: i32,
+    /// How far in front of the attacker the swing connects
+    pub reach: Fixed,
 }
 
-#[derive(Component, Copy, Clone, Debug)]
-pub struct AttackHitbox {
-    pub damage: f32,
-    pub knockback: f32,
-    pub active: bool,
-    pub lifetime: f32,
+impl Swing {
+    pub fn frames(&self) -> u32 {
+        self.startup + self.active + self.recovery
+    }
 }
 
-#[derive(Component, Copy, Clone, Debug)]
-pub struct Health {
-    pub current: f32,
-    pub max: f32,
-    pub invulnerable_timer: f32,
-}
+/// The three-hit combo; the finisher can't be chained
+pub const SWINGS: [Swing; 3] = [
+    Swing { startup: 12, active: 9, recovery: 21, combo_window: 30, damage: 25, reach: Fixed::from_int(2) },
+    Swing { startup: 15, active: 12, recovery: 24, combo_window: 36, damage: 30, reach: Fixed::from_ratio(11, 5) },
+    Swing { startup: 18, active: 15, recovery: 36, combo_window: 0, damage: 40, reach: Fixed::from_ratio(5, 2) },
+];
+
+/// Frames a hit player can't attack or shoot
+pub const HIT_STUN_FRAMES: u32 = 18;
+
+/// Frames after a hit in which further hits don't land
+pub const INVULNERABILITY_FRAMES: u32 = 30;
+
+/// Frames a dodge roll carries the player along `MoveDir`, at `DODGE_STEP` per frame
+pub const DODGE_FRAMES: u32 = 18;
+
+/// Frames from the start of a roll in which hits pass through
+pub const DODGE_INVINCIBLE_FRAMES: u32 = 12;
+
+/// Frames from the start of one roll to the next
+pub const DODGE_COOLDOWN_FRAMES: u32 = 45;
 
-// AnimationState is defined in entities::components, imported above
+/// Frames at the start of a block in which a frontal hit is parried instead
+pub const PARRY_FRAMES: u32 = 8;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum Direction {
-    Forward,
-    Backward,
-    Left,
-    Right,
+/// Frames a parried attacker can't act
+pub const PARRY_STAGGER_FRAMES: u32 = 40;
+
+/// Guard of each shield by item id; a loadout without one blocks with the weapon alone.
+/// Kept here rather than read from the inventory catalogue, which headless apps don't load
+const SHIELD_GUARDS: [(&str, i32); 2] = [("round_shield", 60), ("kite_shield", 80)];
+const WEAPON_GUARD: i32 = 30;
+
+/// One hit as it was simulated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitRecord {
+    pub frame: i32,
+    pub victim: usize,
+    pub attacker: usize,
+    pub damage: i32,
+    /// Where the victim stood when hit
+    pub position: FixedVec2,
 }
 
-impl Default for CombatState {
-    fn default() -> Self {
-        Self {
-            current_action: CombatAction::Idle,
-            combo_count: 0,
-            last_attack_time: 0.0,
-            attack_cooldown: 0.5,
-            is_attacking: false,
-            hit_landed: false,
-        }
-    }
+/// Hits waiting for feedback outside the rollback schedule; deliberately not a rollback resource.
+/// A hit is only handed out once its frame is confirmed, and a rollback forgets the hits of the
+/// frames it is about to resimulate, so a mispredicted hit is never shown and a real one shows once
+#[derive(Resource, Default)]
+pub struct HitLog {
+    pending: Vec<HitRecord>,
 }
 
-impl Default for Health {
-    fn default() -> Self {
-        Self {
-            current: 100.0,
-            max: 100.0,
-            invulnerable_timer: 0.0,
-        }
+impl HitLog {
+    pub fn record(&mut self, hit: HitRecord) {
+        self.pending.push(hit);
+    }
+
+    /// Forgets hits simulated after `frame`, which was just loaded and is about to be advanced again
+    pub fn rollback(&mut self, frame: i32) {
+        self.pending.retain(|hit| hit.frame <= frame);
+    }
+
+    /// Hits from frames no rollback can reach any more
+    pub fn drain_confirmed(&mut self, confirmed_frame: i32) -> Vec<HitRecord> {
+        let (confirmed, predicted) = self.pending.iter().partition(|hit| hit.frame <= confirmed_frame);
+        self.pending = predicted;
+        confirmed
     }
 }
 
-// Constants
-const COMBO_WINDOW: f32 = 1.0; // Time window to continue combo
-const ATTACK_DURATION: f32 = 0.4;
-const HIT_STUN_DURATION: f32 = 0.3;
-const INVULNERABILITY_DURATION: f32 = 0.5;
-const HITBOX_RANGE: f32 = 2.0;
+/// Lets the `HitLog` forget hits a rollback is about to resimulate
+pub fn rollback_hit_log(mut hits: ResMut<HitLog>, frame: Res<RollbackFrameCount>) {
+    hits.rollback(frame.0);
+}
 
-// Input handling for combat
-pub fn process_combat_input(
-    mut query: Query<(&mut CombatState, &Player, &Transform, &MoveDir, &Health)>,
-    inputs: Res<PlayerInputs<Config>>,
-    time: Res<Time>,
-) {
-    for (mut combat, player, transform, move_dir, health) in query.iter_mut() {
-        if health.current <= 0.0 {
-            combat.current_action = CombatAction::Death;
-            continue;
-        }
+fn swing(action: CombatAction) -> Option<&'static Swing> {
+    match action {
+        CombatAction::Attack1 => Some(&SWINGS[0]),
+        CombatAction::Attack2 => Some(&SWINGS[1]),
+        CombatAction::Attack3 => Some(&SWINGS[2]),
+        _ => None,
+    }
+}
 
-        let (input, _) = inputs[player.handle];
-        let current_time = time.elapsed_secs();
+fn combo_swing(combo_count: u8) -> CombatAction {
+    match combo_count {
+        0 => CombatAction::Attack1,
+        1 => CombatAction::Attack2,
+        _ => CombatAction::Attack3,
+    }
+}
 
-        // Update attack cooldown
-        if combat.is_attacking && current_time - combat.last_attack_time > ATTACK_DURATION {
-            combat.is_attacking = false;
+/// Counts down the current action, and returns to idle once it's over
+fn advance_action(combat: &mut CombatState) {
+    combat.action_frames = combat.action_frames.saturating_add(1);
+    combat.dodge_cooldown = combat.dodge_cooldown.saturating_sub(1);
+    match combat.current_action {
+        CombatAction::Idle => {
+            combat.combo_frames = combat.combo_frames.saturating_sub(1);
+            if combat.combo_frames == 0 {
+                combat.combo_count = 0;
+            }
         }
-
-        // Reset combo if window expired
-        if current_time - combat.last_attack_time > COMBO_WINDOW {
-            combat.combo_count = 0;
+        CombatAction::Hit => {
+            if combat.action_frames >= HIT_STUN_FRAMES {
+                combat.start(CombatAction::Idle);
+            }
         }
-
-        // Handle attack input
-        if input & INPUT_FIRE != 0 && !combat.is_attacking {
-            combat.combo_count = (combat.combo_count % 3) + 1;
-            combat.current_action = CombatAction::Attack(combat.combo_count);
-            combat.last_attack_time = current_time;
-            combat.is_attacking = true;
-            combat.hit_landed = false;
+        CombatAction::Dodge => {
+            if combat.action_frames >= DODGE_FRAMES {
+                combat.start(CombatAction::Idle);
+            }
         }
-        // Handle crouch
-        else if input & INPUT_DOWN != 0 && !combat.is_attacking {
-            combat.current_action = CombatAction::Crouching;
+        // Lowered by `process_combat_input` once block is released or unpaid
+        CombatAction::Block => {}
+        CombatAction::Staggered => {
+            if combat.action_frames >= PARRY_STAGGER_FRAMES {
+                combat.start(CombatAction::Idle);
+            }
         }
-        // Handle movement states
-        else if !combat.is_attacking {
-            let moving = move_dir.0.x.abs() > 0.01 || move_dir.0.y.abs() > 0.01;
-            
-            if moving {
-                // Determine if running (shift key) or walking
-                let is_running = input & INPUT_FIRE != 0; // Reuse fire as sprint for demo
-                combat.current_action = if is_running {
-                    CombatAction::Running
-                } else {
-                    CombatAction::Walking
-                };
-            } else {
-                combat.current_action = CombatAction::Idle;
+        action => {
+            let Some(swing) = swing(action) else {
+                return;
+            };
+            if combat.action_frames >= swing.frames() {
+                combat.combo_frames = swing.combo_window;
+                if swing.combo_window == 0 {
+                    combat.combo_count = 0;
+                }
+                combat.start(CombatAction::Idle);
             }
         }
     }
 }
 
-// Spawn attack hitboxes
-pub fn spawn_attack_hitboxes(
-    mut commands: Commands,
-    query: Query<(Entity, &CombatState, &Transform, &Player), Changed<CombatState>>,
-    time: Res<Time>,
-) {
-    for (entity, combat, transform, player) in query.iter() {
-        if let CombatAction::Attack(combo) = combat.current_action {
-            if combat.is_attacking && !combat.hit_landed {
-                // Calculate hitbox position based on player facing direction
-                let forward = transform.rotation * Vec3::Z;
-                let hitbox_pos = transform.translation + forward * HITBOX_RANGE;
-
-                // Different damage and knockback for each combo hit
-                let (damage, knockback) = match combo {
-                    1 => (15.0, 5.0),
-                    2 => (20.0, 8.0),
-                    3 => (30.0, 12.0), // Finisher
-                    _ => (15.0, 5.0),
-                };
-
-                commands.spawn((
-                    AttackHitbox {
-                        damage,
-                        knockback,
-                        active: true,
-                        lifetime: ATTACK_DURATION,
-                    },
-                    Transform::from_translation(hitbox_pos),
-                    GlobalTransform::default(),
-                    *player,
-                )).add_rollback();
-            }
+/// The swing a fresh attack press starts, if any
+fn next_swing(combat: &CombatState, enemy_in_reach: bool) -> Option<CombatAction> {
+    match combat.current_action {
+        // Chaining doesn't care where the enemy went
+        CombatAction::Idle if combat.combo_frames > 0 => Some(combo_swing(combat.combo_count)),
+        // Otherwise the trigger only swings up close, and shoots at range
+        CombatAction::Idle if enemy_in_reach => Some(CombatAction::Attack1),
+        action => {
+            let swing = swing(action)?;
+            let recovering = combat.action_frames >= swing.startup + swing.active;
+            (recovering && swing.combo_window > 0).then(|| combo_swing(combat.combo_count))
         }
     }
 }
 
-// Update hitbox lifetimes
-pub fn update_hitboxes(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut AttackHitbox)>,
-    time: Res<Time>,
-) {
-    for (entity, mut hitbox) in query.iter_mut() {
-        hitbox.lifetime -= time.delta_secs();
-        
-        if hitbox.lifetime <= 0.0 {
-            commands.entity(entity).despawn();
-        }
+/// Starts a roll from idle if it's off cooldown and paid for; `move_players` carries it out
+fn try_dodge(combat: &mut CombatState, stamina: &mut Stamina, invincible: &mut Invincible) -> bool {
+    if combat.current_action != CombatAction::Idle || combat.dodge_cooldown > 0 {
+        return false;
+    }
+    if !stamina.try_spend(DODGE_STAMINA_COST) {
+        return false;
     }
+
+    combat.start(CombatAction::Dodge);
+    combat.combo_count = 0;
+    combat.combo_frames = 0;
+    combat.dodge_cooldown = DODGE_COOLDOWN_FRAMES;
+    invincible.frames_left = invincible.frames_left.max(DODGE_INVINCIBLE_FRAMES);
+    true
 }
 
-// Detect hits and apply damage
-pub fn detect_hits(
-    mut victim_query: Query<(Entity, &Transform, &mut Health, &mut CombatState, &Player)>,
-    hitbox_query: Query<(&Transform, &AttackHitbox, &Player)>,
-    mut attacker_query: Query<&mut CombatState, With<Player>>,
+/// How much a block stops with `loadout` (equipped item ids) on
+pub fn guard_for(loadout: &[String]) -> Guard {
+    let reduction = SHIELD_GUARDS
+        .iter()
+        .find(|(id, _)| loadout.iter().any(|item| item == id))
+        .map_or(WEAPON_GUARD, |&(_, reduction)| reduction);
+    Guard { reduction }
+}
+
+fn block_drain() -> Fixed {
+    BLOCK_STAMINA_DRAIN / Fixed::from_int(ROLLBACK_FPS as i32)
+}
+
+/// Raises the guard from idle, paying for its first frame
+fn try_block(combat: &mut CombatState, stamina: &mut Stamina) -> bool {
+    if combat.current_action != CombatAction::Idle || !stamina.try_spend(block_drain()) {
+        return false;
+    }
+
+    combat.start(CombatAction::Block);
+    combat.combo_count = 0;
+    combat.combo_frames = 0;
+    true
+}
+
+/// A hit that got past the parry window
+pub struct GuardedHit {
+    pub damage: i32,
+    /// Landed on a raised guard, which took part of the damage
+    pub blocked: bool,
+}
+
+/// What's left of a hit of `damage` coming from `source`, or `None` if it was parried;
+/// only the front half of a player is guarded
+pub fn guarded_damage(
+    combat: &CombatState,
+    guard: &Guard,
+    facing: FixedVec2,
+    defender: FixedVec2,
+    source: FixedVec2,
+    damage: i32,
+) -> Option<GuardedHit> {
+    let unguarded = GuardedHit { damage, blocked: false };
+    if combat.current_action != CombatAction::Block {
+        return Some(unguarded);
+    }
+
+    let to_source = source - defender;
+    if to_source.x * facing.x + to_source.y * facing.y <= Fixed::ZERO {
+        return Some(unguarded);
+    }
+    if combat.action_frames < PARRY_FRAMES {
+        return None;
+    }
+    Some(GuardedHit { damage: damage * (100 - guard.reduction) / 100, blocked: true })
+}
+
+/// Turns fresh dodge, block and attack presses into rolls, guards and swings; a press
+/// that swings, or comes mid-action, doesn't also shoot
+pub fn process_combat_input(
+    mut players: Query<(
+        &mut CombatState,
+        &mut BulletReady,
+        &mut Stamina,
+        &mut Invincible,
+        &Player,
+        &Position,
+    )>,
+    inputs: Res<PlayerInputs<Config>>,
+    rules: Res<MatchRules>,
 ) {
-    for (victim_entity, victim_transform, mut health, mut victim_combat, victim_player) in victim_query.iter_mut() {
-        // Skip if invulnerable
-        if health.invulnerable_timer > 0.0 {
-            continue;
+    let positions: Vec<(usize, FixedVec2)> = players
+        .iter()
+        .map(|(.., player, position)| (player.handle, position.0))
+        .collect();
+    let reach = SWINGS[0].reach + Fixed::from_f32(PLAYER_RADIUS);
+
+    for (mut combat, mut bullet_ready, mut stamina, mut invincible, player, position) in &mut players {
+        let input = inputs[player.handle].0.buttons;
+        let pressed = attack(input) && !combat.attack_held;
+        let dodge_pressed = dodge(input) && !combat.dodge_held;
+        let block_pressed = block(input) && !combat.block_held;
+        combat.attack_held = attack(input);
+        combat.dodge_held = dodge(input);
+        combat.block_held = block(input);
+
+        advance_action(&mut combat);
+        if combat.current_action == CombatAction::Block && !(combat.block_held && stamina.try_spend(block_drain())) {
+            combat.start(CombatAction::Idle);
         }
 
-        for (hitbox_transform, hitbox, attacker_player) in hitbox_query.iter() {
-            // Don't hit yourself
-            if victim_player.handle == attacker_player.handle {
-                continue;
+        // Rolling or raising the guard takes priority over a trigger pulled on the same frame
+        if (dodge_pressed && try_dodge(&mut combat, &mut stamina, &mut invincible))
+            || (block_pressed && try_block(&mut combat, &mut stamina))
+        {
+            if pressed {
+                bullet_ready.0 = false;
             }
+            continue;
+        }
+        if !pressed {
+            continue;
+        }
 
-            // Check distance
-            let distance = victim_transform.translation.distance(hitbox_transform.translation);
-
-            if distance < HITBOX_RANGE && hitbox.active {
-                // Apply damage
-                health.current = (health.current - hitbox.damage).max(0.0);
-                health.invulnerable_timer = INVULNERABILITY_DURATION;
+        let team = rules.team_of(player.handle);
+        let enemy_in_reach = positions.iter().any(|&(handle, other)| {
+            rules.team_of(handle) != team && (other - position.0).length_squared() < reach * reach
+        });
 
-                // Set hit state
-                if health.current > 0.0 {
-                    victim_combat.current_action = CombatAction::Hit;
-                    victim_combat.is_attacking = false;
-                } else {
-                    victim_combat.current_action = CombatAction::Death;
-                }
-
-                // Note: Can't easily get attacker entity from hitbox Player component
-                // This would need entity tracking in AttackHitbox component
+        if let Some(action) = next_swing(&combat, enemy_in_reach) {
+            // Even a press too winded to swing was meant for the sword, so it never shoots
+            bullet_ready.0 = false;
+            if !stamina.try_spend(ATTACK_STAMINA_COST) {
+                continue;
             }
+            combat.combo_count += 1;
+            combat.combo_frames = 0;
+            combat.start(action);
+        } else if combat.current_action != CombatAction::Idle {
+            bullet_ready.0 = false;
         }
     }
 }
 
-// Update health timers
-pub fn update_health_timers(
-    mut query: Query<&mut Health>,
-    time: Res<Time>,
+/// Puts a hitbox in front of the attacker on the frame a swing's startup ends
+pub fn spawn_attack_hitboxes(
+    mut commands: Commands,
+    players: Query<(&CombatState, &Player, &Position, &Aim)>,
 ) {
-    for mut health in query.iter_mut() {
-        if health.invulnerable_timer > 0.0 {
-            health.invulnerable_timer -= time.delta_secs();
+    for (combat, player, position, aim) in &players {
+        let Some(swing) = swing(combat.current_action) else {
+            continue;
+        };
+        if combat.action_frames != swing.startup {
+            continue;
         }
+
+        let radius = swing.reach * Fixed::HALF;
+        let centre = position.0 + aim.0 * radius;
+        commands
+            .spawn((
+                AttackBox {
+                    owner: player.handle,
+                    damage: swing.damage,
+                    radius,
+                    frames_left: swing.active,
+                },
+                Position(centre),
+                Transform::from_translation(centre.to_vec3(PLAYER_HEIGHT / 2.)),
+                GameEntity,
+            ))
+            .add_rollback();
     }
 }
 
-// Recover from hit stun
-pub fn recover_from_hit(
-    mut query: Query<(&mut CombatState, &Health)>,
-    time: Res<Time>,
-) {
-    for (mut combat, health) in query.iter_mut() {
-        if combat.current_action == CombatAction::Hit {
-            if health.invulnerable_timer <= INVULNERABILITY_DURATION - HIT_STUN_DURATION {
-                combat.current_action = CombatAction::Idle;
-            }
+/// Refills stamina at `regen_rate` once `regen_delay` frames have passed without spending
+pub fn regen_stamina(mut players: Query<&mut Stamina>) {
+    let frames_per_second = Fixed::from_int(ROLLBACK_FPS as i32);
+    for mut stamina in &mut players {
+        stamina.frames_since_use = stamina.frames_since_use.saturating_add(1);
+        if stamina.frames_since_use >= stamina.regen_delay {
+            let refill = stamina.regen_rate / frames_per_second;
+            stamina.current = (stamina.current + refill).min(stamina.max);
         }
     }
 }
 
-// Animation selection system
-pub fn select_animation(
-    query: Query<(&CombatState, &MoveDir, &Transform), Changed<CombatState>>,
-) {
-    for (combat, move_dir, transform) in query.iter() {
-        let direction = determine_direction(move_dir, transform);
-        let alert = combat.is_attacking || matches!(combat.current_action, CombatAction::Hit);
-        
-        let animation_name = match combat.current_action {
-            CombatAction::Idle => {
-                if alert {
-                    format!("Anim_WKM_Idle_Alert")
-                } else {
-                    format!("Anim_WKM_Idle")
-                }
-            }
-            CombatAction::Walking => {
-                let dir_suffix = get_direction_suffix(direction);
-                if alert {
-                    format!("Anim_WKM_Walk_Alert_{}", dir_suffix)
-                } else {
-                    format!("Anim_WKM_Walk_{}", dir_suffix)
-                }
-            }
-            CombatAction::Running => {
-                let dir_suffix = get_direction_suffix(direction);
-                if alert {
-                    format!("Anim_WKM_Run_Alert_{}", dir_suffix)
-                } else {
-                    format!("Anim_WKM_Run_{}", dir_suffix)
-                }
-            }
-            CombatAction::Crouching => {
-                let dir_suffix = get_direction_suffix(direction);
-                if alert {
-                    format!("Anim_WKM_Crouch_Alert_{}", dir_suffix)
-                } else {
-                    format!("Anim_WKM_Crouch_{}", dir_suffix)
-                }
-            }
-            CombatAction::Attack(combo) => {
-                format!("Anim_WKM_Attack_{:02}", combo)
-            }
-            CombatAction::Hit => {
-                let dir_suffix = get_direction_suffix(direction);
-                format!("Anim_WKM_Hit_Alert_{}", dir_suffix)
-            }
-            CombatAction::Death => {
-                "Anim_WKM_Death".to_string()
-            }
-        };
-        
-        // Here you would apply the animation to your animation player
-        // This depends on your animation system setup
+pub fn tick_invincibility(mut players: Query<&mut Invincible>) {
+    for mut invincible in &mut players {
+        invincible.frames_left = invincible.frames_left.saturating_sub(1);
     }
 }
 
-fn determine_direction(move_dir: &MoveDir, transform: &Transform) -> Direction {
-    if move_dir.0.x.abs() < 0.01 && move_dir.0.y.abs() < 0.01 {
-        return Direction::Forward;
+/// What a hit reads and changes on the player it lands on
+type Victim = (
+    &'static Player,
+    &'static Position,
+    &'static Aim,
+    &'static Guard,
+    &'static mut Health,
+    &'static mut CombatState,
+    &'static mut Invincible,
+);
+
+/// Applies each hitbox to the first enemy it touches; dying is left to `kill_players`
+pub fn detect_hits(
+    mut commands: Commands,
+    hitboxes: Query<(Entity, &AttackBox, &Position)>,
+    mut victims: Query<Victim, Without<AttackBox>>,
+    rules: Res<MatchRules>,
+    frame: Res<RollbackFrameCount>,
+    mut hits: ResMut<HitLog>,
+) {
+    // Entity order can differ between peers, so resolve overlaps by handle
+    let mut hitboxes: Vec<_> = hitboxes.iter().collect();
+    hitboxes.sort_by_key(|(_, attack, _)| attack.owner);
+    let attackers: Vec<(usize, FixedVec2)> = victims
+        .iter()
+        .map(|(player, position, ..)| (player.handle, position.0))
+        .collect();
+    let mut parried = Vec::new();
+
+    for (hitbox, attack, hitbox_pos) in hitboxes {
+        let hit_distance = attack.radius + Fixed::from_f32(PLAYER_RADIUS);
+        let mut targets: Vec<_> = victims
+            .iter_mut()
+            .filter(|(player, ..)| rules.team_of(player.handle) != rules.team_of(attack.owner))
+            .collect();
+        targets.sort_by_key(|(player, ..)| player.handle);
+
+        let target = targets.into_iter().find(|(_, position, .., invincible)| {
+            !invincible.active() && (position.0 - hitbox_pos.0).length_squared() < hit_distance * hit_distance
+        });
+        let Some((player, position, facing, guard, mut health, mut combat, mut invincible)) = target else {
+            continue;
+        };
+
+        commands.entity(hitbox).despawn();
+        let source = attackers
+            .iter()
+            .find(|(handle, _)| *handle == attack.owner)
+            .map_or(hitbox_pos.0, |&(_, position)| position);
+        let Some(GuardedHit { damage, blocked }) =
+            guarded_damage(&combat, guard, facing.0, position.0, source, attack.damage)
+        else {
+            parried.push(attack.owner);
+            continue;
+        };
+
+        health.current = (health.current - damage).max(0);
+        invincible.frames_left = INVULNERABILITY_FRAMES;
+        // A blocked hit doesn't knock the guard down
+        if !blocked {
+            combat.combo_count = 0;
+            combat.combo_frames = 0;
+            combat.start(CombatAction::Hit);
+        }
+        hits.record(HitRecord {
+            frame: frame.0,
+            victim: player.handle,
+            attacker: attack.owner,
+            damage,
+            position: position.0,
+        });
     }
-    
-    let forward = transform.rotation * Vec3::Z;
-    let move_vec = Vec3::new(move_dir.0.x, 0.0, move_dir.0.y).normalize();
-    let dot = forward.dot(move_vec);
-    let cross = forward.cross(move_vec).y;
-    
-    if dot > 0.5 {
-        Direction::Forward
-    } else if dot < -0.5 {
-        Direction::Backward
-    } else if cross > 0.0 {
-        Direction::Right
-    } else {
-        Direction::Left
+
+    for (player, .., mut combat, _) in &mut victims {
+        if parried.contains(&player.handle) {
+            combat.combo_count = 0;
+            combat.combo_frames = 0;
+            combat.start(CombatAction::Staggered);
+        }
     }
 }
 
-fn get_direction_suffix(direction: Direction) -> &'static str {
-    match direction {
-        Direction::Forward => "Fwd",
-        Direction::Backward => "Bwd",
-        Direction::Left => "Left",
-        Direction::Right => "Right",
+pub fn update_hitboxes(mut commands: Commands, mut hitboxes: Query<(Entity, &mut AttackBox)>) {
+    for (entity, mut hitbox) in &mut hitboxes {
+        hitbox.frames_left = hitbox.frames_left.saturating_sub(1);
+        if hitbox.frames_left == 0 {
+            commands.entity(entity).despawn();
+        }
     }
 }
 
-// Plugin for easy integration
+/// Melee runs between movement and bullets, so a trigger pull is either a swing or a shot
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
@@ -354,14 +475,22 @@ impl Plugin for CombatPlugin {
         app.add_systems(
             RollbackUpdate,
             (
-                process_combat_input,
+                process_combat_input
+                    .after(move_players)
+                    .after(resolve_wall_collisions)
+                    .after(reload_bullet)
+                    .before(fire_bullets),
                 spawn_attack_hitboxes.after(process_combat_input),
-                update_hitboxes,
-                detect_hits.after(spawn_attack_hitboxes),
-                update_health_timers,
-                recover_from_hit.after(detect_hits),
-                select_animation.after(process_combat_input),
-            ),
+                regen_stamina.after(process_combat_input),
+                tick_invincibility.after(process_combat_input),
+                detect_hits
+                    .after(spawn_attack_hitboxes)
+                    .after(tick_invincibility)
+                    .before(kill_players),
+                update_hitboxes.after(detect_hits),
+            )
+                .run_if(in_state(RollbackState::InRound))
+                .after(bevy_roll_safe::apply_state_transition::<RollbackState>),
         );
     }
-}
\ No newline at end of file
+}
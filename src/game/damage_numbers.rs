@@ -1,12 +1,24 @@
 // game/damage_numbers.rs - Floating combat text (D2R style)
 use bevy::prelude::*;
+use bevy_ggrs::{RollbackFrameCount, Session};
+use crate::{
+    Config,
+    core::constants::PLAYER_HEIGHT,
+    core::states::GameState,
+    entities::components::GameEntity,
+    game::combat::HitLog,
+};
 
 /// Component for damage numbers that float upward and fade
 #[derive(Component)]
 pub struct DamageNumber {
     pub lifetime: f32,
+    /// Screen-space drift, in pixels per second
     pub velocity: Vec2,
-    pub is_crit: bool,
+    /// World point the number rises from
+    pub anchor: Vec3,
+    /// Drift so far, in pixels
+    pub offset: Vec2,
 }
 
 /// Spawn a damage number at a position
@@ -39,39 +51,84 @@ pub fn spawn_damage_number(
     };
 
     commands.spawn((
-        Text2d::new(text),
+        Text::new(text),
         TextFont {
             font_size,
             ..default()
         },
         TextColor(color),
-        Transform::from_translation(position + Vec3::new(0.0, 1.5, 0.0)),
+        // Placed over the anchor by `update_damage_numbers`; hidden until then
+        Node {
+            position_type: PositionType::Absolute,
+            ..default()
+        },
+        Visibility::Hidden,
         DamageNumber {
             lifetime: 1.5,
             velocity: Vec2::new(
                 rand::random::<f32>() * 20.0 - 10.0, // Small random X movement
                 60.0, // Float upward
             ),
-            is_crit,
+            anchor: position + Vec3::new(0.0, 1.5, 0.0),
+            offset: Vec2::ZERO,
         },
+        GameEntity,
     ));
 }
 
+/// Floats the damage of every confirmed hit over whoever took it
+pub fn spawn_hit_numbers(
+    mut commands: Commands,
+    mut hits: ResMut<HitLog>,
+    session: Option<Res<Session<Config>>>,
+    frame: Res<RollbackFrameCount>,
+) {
+    let confirmed_frame = match session.as_deref() {
+        // Inputs of GGRS frame `n` are simulated as rollback frame `n + 1`
+        Some(Session::P2P(s)) => s.confirmed_frame() + 1,
+        Some(Session::SyncTest(s)) => frame.0 - s.check_distance() as i32,
+        // Spectators only ever simulate confirmed inputs
+        _ => frame.0,
+    };
+    for hit in hits.drain_confirmed(confirmed_frame) {
+        spawn_damage_number(
+            &mut commands,
+            hit.position.to_vec3(PLAYER_HEIGHT),
+            hit.damage as f32,
+            false,
+            DamageType::Physical,
+        );
+    }
+}
+
 /// Update damage numbers - move upward and fade out
 pub fn update_damage_numbers(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut Transform, &mut DamageNumber, &mut TextColor)>,
+    mut query: Query<(Entity, &mut Node, &mut Visibility, &mut DamageNumber, &mut TextColor)>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
     time: Res<Time>,
 ) {
-    for (entity, mut transform, mut number, mut color) in &mut query {
+    let camera = cameras.single().ok();
+
+    for (entity, mut node, mut visibility, mut number, mut color) in &mut query {
         number.lifetime -= time.delta_secs();
 
         if number.lifetime <= 0.0 {
             commands.entity(entity).despawn();
         } else {
             // Float upward with slight horizontal drift
-            transform.translation.x += number.velocity.x * time.delta_secs();
-            transform.translation.y += number.velocity.y * time.delta_secs();
+            let drift = number.velocity * time.delta_secs();
+            number.offset += drift;
+
+            // Follow the anchor on screen as the camera moves
+            let screen = camera.and_then(|(camera, camera_transform)| {
+                camera.world_to_viewport(camera_transform, number.anchor).ok()
+            });
+            if let Some(screen) = screen {
+                node.left = Val::Px(screen.x + number.offset.x);
+                node.top = Val::Px(screen.y - number.offset.y);
+                *visibility = Visibility::Inherited;
+            }
 
             // Fade out based on lifetime
             let alpha = (number.lifetime / 1.5).clamp(0.0, 1.0);
@@ -83,6 +140,8 @@ pub fn update_damage_numbers(
     }
 }
 
+// Hits only deal physical damage until equipment and skills bring the elements in
+#[allow(dead_code)]
 #[derive(Clone, Copy)]
 pub enum DamageType {
     Physical,
@@ -97,7 +156,13 @@ pub struct DamageNumberPlugin;
 
 impl Plugin for DamageNumberPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, update_damage_numbers);
+        app.add_systems(
+            Update,
+            (
+                spawn_hit_numbers.run_if(in_state(GameState::InGame)),
+                update_damage_numbers.after(spawn_hit_numbers),
+            ),
+        );
     }
 }
 
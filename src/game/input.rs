@@ -1,6 +1,17 @@
-use crate::Config;
-use bevy::{platform::collections::HashMap, prelude::*};
+use crate::{
+    Config,
+    core::fixed::{Fixed, FixedVec2},
+    entities::components::Player,
+};
+use bevy::{
+    ecs::system::SystemParam,
+    input::gamepad::GamepadConnectionEvent,
+    platform::collections::HashMap,
+    prelude::*,
+    window::PrimaryWindow,
+};
 use bevy_ggrs::{LocalInputs, LocalPlayers};
+use serde::{Deserialize, Serialize};
 
 // Input bit flags
 pub const INPUT_UP: u8 = 1 << 0;
@@ -12,72 +23,294 @@ pub const INPUT_DODGE: u8 = 1 << 5;     // Dodge/Roll
 pub const INPUT_BLOCK: u8 = 1 << 6;     // Block
 pub const INPUT_SPRINT: u8 = 1 << 7;    // Sprint (optional)
 
+/// Aim angles a full turn is quantized into
+pub const AIM_STEPS: u8 = 128;
+
+/// Right stick deflection below which the stick doesn't aim
+pub const AIM_STICK_DEADZONE: f32 = 0.3;
+
+/// One player's input for one frame, and the only game data sent to peers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PlayerInput {
+    /// `INPUT_*` bits
+    pub buttons: u8,
+    /// Aim angle from `quantize_aim`; 0 means not aiming, so facing follows movement
+    pub aim: u8,
+}
+
+impl PlayerInput {
+    pub fn from_buttons(buttons: u8) -> Self {
+        Self { buttons, aim: 0 }
+    }
+}
+
+/// Ground-plane direction to an aim step, counter-clockwise from +X like `FixedVec2::from_turns`;
+/// the float angle only decides which step is sent, so peers never have to agree on it
+pub fn quantize_aim(direction: Vec2) -> u8 {
+    if direction == Vec2::ZERO {
+        return 0;
+    }
+    let turns = (direction.y.atan2(direction.x) / std::f32::consts::TAU).rem_euclid(1.0);
+    let step = (turns * AIM_STEPS as f32).round() as u32 % AIM_STEPS as u32;
+    step as u8 + 1
+}
+
+/// Where the player aims, rebuilt from the quantized step in fixed point
+pub fn aim_direction(input: PlayerInput) -> Option<FixedVec2> {
+    (input.aim != 0).then(|| FixedVec2::from_turns(Fixed::from_ratio(input.aim as i32 - 1, AIM_STEPS as i32)))
+}
+
+/// Left stick deflection below which the stick doesn't move the player
+pub const MOVE_STICK_DEADZONE: f32 = 0.25;
+
+/// Share of the stick's deflection an axis needs to count as pressed; sin(22.5°) splits
+/// the stick into eight equal sectors like the keyboard's eight directions
+const MOVE_STICK_AXIS_SHARE: f32 = 0.38;
+
+fn keyboard_buttons(keys: &ButtonInput<KeyCode>) -> u8 {
+    let mut input = 0u8;
+
+    // Movement inputs
+    if keys.any_pressed([KeyCode::ArrowUp, KeyCode::KeyW]) {
+        input |= INPUT_UP;
+    }
+    if keys.any_pressed([KeyCode::ArrowDown, KeyCode::KeyS]) {
+        input |= INPUT_DOWN;
+    }
+    if keys.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]) {
+        input |= INPUT_LEFT
+    }
+    if keys.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD]) {
+        input |= INPUT_RIGHT;
+    }
+
+    // Combat inputs
+    if keys.any_pressed([KeyCode::Space, KeyCode::Enter]) {
+        input |= INPUT_FIRE;
+    }
+    if keys.pressed(KeyCode::ShiftLeft) {
+        input |= INPUT_DODGE;
+    }
+    if keys.pressed(KeyCode::ControlLeft) {
+        input |= INPUT_BLOCK;
+    }
+    if keys.pressed(KeyCode::ShiftRight) {
+        input |= INPUT_SPRINT;
+    }
+
+    input
+}
+
+/// Left stick or d-pad to move, South to fire, East to dodge, West to block, right trigger to sprint
+fn gamepad_buttons(gamepad: &Gamepad) -> u8 {
+    let mut input = 0u8;
+
+    let stick = gamepad.left_stick();
+    if stick.length() > MOVE_STICK_DEADZONE {
+        let share = stick.length() * MOVE_STICK_AXIS_SHARE;
+        if stick.y > share {
+            input |= INPUT_UP;
+        }
+        if stick.y < -share {
+            input |= INPUT_DOWN;
+        }
+        if stick.x < -share {
+            input |= INPUT_LEFT;
+        }
+        if stick.x > share {
+            input |= INPUT_RIGHT;
+        }
+    }
+    if gamepad.pressed(GamepadButton::DPadUp) {
+        input |= INPUT_UP;
+    }
+    if gamepad.pressed(GamepadButton::DPadDown) {
+        input |= INPUT_DOWN;
+    }
+    if gamepad.pressed(GamepadButton::DPadLeft) {
+        input |= INPUT_LEFT;
+    }
+    if gamepad.pressed(GamepadButton::DPadRight) {
+        input |= INPUT_RIGHT;
+    }
+
+    if gamepad.pressed(GamepadButton::South) {
+        input |= INPUT_FIRE;
+    }
+    if gamepad.pressed(GamepadButton::East) {
+        input |= INPUT_DODGE;
+    }
+    if gamepad.pressed(GamepadButton::West) {
+        input |= INPUT_BLOCK;
+    }
+    if gamepad.pressed(GamepadButton::RightTrigger2) {
+        input |= INPUT_SPRINT;
+    }
+
+    input
+}
+
+/// The right stick, if it's pushed past the deadzone
+fn stick_aim(gamepad: &Gamepad) -> Option<Vec2> {
+    // Stick up is up the screen, which is -Z on the ground
+    let stick = gamepad.right_stick() * Vec2::new(1.0, -1.0);
+    (stick.length() > AIM_STICK_DEADZONE).then_some(stick)
+}
+
+/// The cursor projected onto the ground at the player's height
+fn cursor_aim(player: Vec3, window: Option<&Window>, camera: Option<(&Camera, &GlobalTransform)>) -> Vec2 {
+    let (Some(window), Some((camera, camera_transform))) = (window, camera) else {
+        return Vec2::ZERO;
+    };
+    let Some(ray) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor).ok())
+    else {
+        return Vec2::ZERO;
+    };
+    let Some(distance) = ray.intersect_plane(player, InfinitePlane3d::new(Vec3::Y)) else {
+        return Vec2::ZERO;
+    };
+    (ray.get_point(distance) - player).xz()
+}
+
+/// Which gamepad plays which local slot; a pad keeps its slot until it disconnects,
+/// and a pad connecting later takes the first free one
+#[derive(Resource, Default, Debug)]
+pub struct GamepadSlots(Vec<Option<Entity>>);
+
+impl GamepadSlots {
+    pub fn gamepad(&self, slot: usize) -> Option<Entity> {
+        self.0.get(slot).copied().flatten()
+    }
+
+    pub fn connected(&self) -> usize {
+        self.0.iter().flatten().count()
+    }
+
+    fn assign(&mut self, gamepad: Entity) {
+        if self.0.contains(&Some(gamepad)) {
+            return;
+        }
+        match self.0.iter_mut().find(|slot| slot.is_none()) {
+            Some(free) => *free = Some(gamepad),
+            None => self.0.push(Some(gamepad)),
+        }
+    }
+
+    fn free(&mut self, gamepad: Entity) {
+        for slot in self.0.iter_mut().filter(|slot| **slot == Some(gamepad)) {
+            *slot = None;
+        }
+    }
+}
+
+pub fn assign_gamepad_slots(
+    mut slots: ResMut<GamepadSlots>,
+    mut connections: MessageReader<GamepadConnectionEvent>,
+) {
+    for event in connections.read() {
+        if event.connected() {
+            slots.assign(event.gamepad);
+        } else {
+            slots.free(event.gamepad);
+        }
+        info!("Gamepad slots: {:?}", slots.0);
+    }
+}
+
+/// The connected gamepads, looked up by the local slot they were assigned
+#[derive(SystemParam)]
+pub struct LocalGamepads<'w, 's> {
+    slots: Res<'w, GamepadSlots>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl LocalGamepads<'_, '_> {
+    fn in_slot(&self, slot: usize) -> Option<&Gamepad> {
+        self.slots.gamepad(slot).and_then(|entity| self.gamepads.get(entity).ok())
+    }
+}
+
+/// What the cursor aim is worked out from
+#[derive(SystemParam)]
+pub struct CursorAim<'w, 's> {
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    cameras: Query<'w, 's, (&'static Camera, &'static GlobalTransform), With<Camera3d>>,
+}
+
+impl CursorAim<'_, '_> {
+    fn toward(&self, player: Vec3) -> Vec2 {
+        cursor_aim(player, self.windows.single().ok(), self.cameras.single().ok())
+    }
+}
+
+/// Each local handle reads the gamepad in its slot, so two players sharing a machine
+/// each get their own; keyboard and mouse also play the first handle
 pub fn read_local_inputs(
     mut commands: Commands,
     keys: Res<ButtonInput<KeyCode>>,
     local_players: Res<LocalPlayers>,
     chat_input: Option<Res<crate::ui::chat::ChatInput>>,
+    gamepads: LocalGamepads,
+    cursor: CursorAim,
+    players: Query<(&Player, &Transform)>,
 ) {
     let mut local_inputs = HashMap::new();
 
     // Don't process gameplay inputs if chat is focused
     let chat_is_focused = chat_input.as_ref().map(|c| c.is_focused).unwrap_or(false);
 
-    for handle in &local_players.0 {
+    let mut handles = local_players.0.clone();
+    handles.sort();
+
+    for (slot, &handle) in handles.iter().enumerate() {
+        let gamepad = gamepads.in_slot(slot);
         let mut input = 0u8;
+        let mut aim = 0u8;
+        let has_keyboard = slot == 0;
 
         if !chat_is_focused {
-            // Movement inputs
-            if keys.any_pressed([KeyCode::ArrowUp, KeyCode::KeyW]) {
-                input |= INPUT_UP;
+            if has_keyboard {
+                input |= keyboard_buttons(&keys);
             }
-            if keys.any_pressed([KeyCode::ArrowDown, KeyCode::KeyS]) {
-                input |= INPUT_DOWN;
-            }
-            if keys.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]) {
-                input |= INPUT_LEFT
-            }
-            if keys.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD]) {
-                input |= INPUT_RIGHT;
+            if let Some(gamepad) = gamepad {
+                input |= gamepad_buttons(gamepad);
             }
 
-            // Combat inputs
-            if keys.any_pressed([KeyCode::Space, KeyCode::Enter]) {
-                input |= INPUT_FIRE;
-            }
-            if keys.pressed(KeyCode::ShiftLeft) {
-                input |= INPUT_DODGE;
-            }
-            if keys.pressed(KeyCode::ControlLeft) {
-                input |= INPUT_BLOCK;
-            }
-            if keys.pressed(KeyCode::ShiftRight) {
-                input |= INPUT_SPRINT;
+            // Dead players have nothing to aim from
+            if let Some((_, transform)) = players.iter().find(|(player, _)| player.handle == handle) {
+                let direction = match gamepad.and_then(stick_aim) {
+                    Some(stick) => stick,
+                    None if has_keyboard => cursor.toward(transform.translation),
+                    None => Vec2::ZERO,
+                };
+                aim = quantize_aim(direction);
             }
         }
 
-        local_inputs.insert(*handle, input);
+        local_inputs.insert(handle, PlayerInput { buttons: input, aim });
     }
 
     commands.insert_resource(LocalInputs::<Config>(local_inputs));
 }
 
 // Helper functions
-pub fn direction(input: u8) -> Vec2 {
-    let mut direction = Vec2::ZERO;
+pub fn direction(input: u8) -> FixedVec2 {
+    let mut direction = FixedVec2::ZERO;
     if input & INPUT_UP != 0 {
-        direction.y -= 1.;  // W moves up on screen (negative Z)
+        direction.y -= Fixed::ONE;  // W moves up on screen (negative Z)
     }
     if input & INPUT_DOWN != 0 {
-        direction.y += 1.;  // S moves down on screen (positive Z)
+        direction.y += Fixed::ONE;  // S moves down on screen (positive Z)
     }
     if input & INPUT_RIGHT != 0 {
-        direction.x += 1.;
+        direction.x += Fixed::ONE;
     }
     if input & INPUT_LEFT != 0 {
-        direction.x -= 1.;
+        direction.x -= Fixed::ONE;
     }
-    direction.normalize_or_zero()
+    direction.normalize_or(FixedVec2::ZERO)
 }
 
 pub fn fire(input: u8) -> bool {
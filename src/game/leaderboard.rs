@@ -1,12 +1,16 @@
 // leaderboard.rs - Stats submission and game completion
 use bevy::prelude::*;
+use bevy_ggrs::RollbackFrameCount;
 use serde::{Deserialize, Serialize};
 use crate::{
-    core::resources::{Scores, SessionSeed, PlayerAddressMapping},
+    core::args::GameMode,
+    core::resources::{Scores, SessionSeed, PlayerAddressMapping, MatchRules},
     core::states::GameState,
+    network::replay::ReplayRecorder,
+    network::session::PeerConnections,
 };
 
-/// Win condition: first player to reach this score wins
+/// Win condition: first player or team to win this many rounds wins
 pub const WINNING_SCORE: u32 = 3;
 
 /// Resource to track if stats have been submitted for this game
@@ -30,20 +34,71 @@ impl Default for GameEndTimer {
 /// Resource to store game end data for display
 #[derive(Resource, Default, Clone, Debug)]
 pub struct GameEndData {
-    pub player0_score: u32,
-    pub player1_score: u32,
-    pub winner_handle: Option<usize>,
+    /// Final score per handle
+    pub scores: Vec<u32>,
+    /// Team of each handle (equal to the handle in free-for-all)
+    pub teams: Vec<usize>,
+    pub names: Vec<String>,
+    pub team_mode: bool,
+    pub winning_team: Option<usize>,
     pub local_player_handle: Option<usize>,
+    /// Handles that disconnected and forfeited; non-empty means the match ended by forfeit
+    pub forfeited: Vec<usize>,
 }
 
-/// Check if the game has ended (someone reached winning score)
+impl GameEndData {
+    pub fn team_name(team: usize) -> String {
+        format!("Team {}", (b'A' + team as u8) as char)
+    }
+
+    pub fn is_winner(&self, handle: usize) -> bool {
+        self.winning_team.is_some() && self.teams.get(handle).copied() == self.winning_team
+    }
+
+    pub fn is_forfeit(&self) -> bool {
+        !self.forfeited.is_empty()
+    }
+}
+
+/// Frame on which a team first reached the winning score, as last simulated. Not a rollback
+/// resource: resimulating the frames before it without a winner takes the decision back
+#[derive(Resource, Default, Debug)]
+pub struct DecidingFrame(pub Option<i32>);
+
+/// Runs at the end of every rollback frame, including resimulated ones
+pub fn record_deciding_frame(
+    scores: Res<Scores>,
+    rules: Res<MatchRules>,
+    frame: Res<RollbackFrameCount>,
+    mut deciding: ResMut<DecidingFrame>,
+) {
+    let decided = rules.winning_team(&scores, WINNING_SCORE).is_some();
+    if decided && deciding.0.is_none_or(|first| frame.0 < first) {
+        deciding.0 = Some(frame.0);
+    } else if !decided && deciding.0.is_some_and(|first| frame.0 <= first) {
+        deciding.0 = None;
+    }
+}
+
+/// Check if the game has ended (a player or team reached winning score)
 /// If so, transition to GameEnd state to show results
 pub fn check_game_end(
     scores: Res<Scores>,
+    rules: Res<MatchRules>,
+    deciding: Res<DecidingFrame>,
+    recorder: Res<ReplayRecorder>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
-    if scores.0 >= WINNING_SCORE || scores.1 >= WINNING_SCORE {
-        info!("Game ended! Final scores: {:?}. Transitioning to GameEnd state.", *scores);
+    // A predicted win can still be rolled back, so wait until every input up to it has arrived;
+    // the replay and any spectators then see the match all the way to the deciding frame
+    let Some(frame) = deciding.0 else {
+        return;
+    };
+    if recorder.confirmed < frame as usize {
+        return;
+    }
+    if let Some(team) = rules.winning_team(&scores, WINNING_SCORE) {
+        info!("Game ended! Team {team} wins with scores {:?}. Transitioning to GameEnd state.", *scores);
         next_state.set(GameState::GameEnd);
     }
 }
@@ -52,25 +107,30 @@ pub fn check_game_end(
 pub fn setup_game_end(
     mut commands: Commands,
     scores: Res<Scores>,
+    rules: Res<MatchRules>,
     address_mapping: Res<PlayerAddressMapping>,
+    connections: Res<PeerConnections>,
 ) {
-    let winner_handle = if scores.0 >= WINNING_SCORE {
-        Some(0)
-    } else if scores.1 >= WINNING_SCORE {
-        Some(1)
+    // A forfeit hands the match to whoever is still connected, regardless of score
+    let winning_team = if connections.has_forfeit() {
+        connections.surviving_team(&rules)
     } else {
-        None
+        rules.winning_team(&scores, WINNING_SCORE)
     };
 
+    let handles = 0..rules.num_players;
     let game_end_data = GameEndData {
-        player0_score: scores.0,
-        player1_score: scores.1,
-        winner_handle,
+        scores: handles.clone().map(|handle| scores.get(handle)).collect(),
+        teams: handles.clone().map(|handle| rules.team_of(handle)).collect(),
+        names: handles.map(|handle| address_mapping.name_or_default(handle)).collect(),
+        team_mode: rules.mode == GameMode::Teams,
+        winning_team,
         local_player_handle: address_mapping.local_player_handle,
+        forfeited: connections.forfeited.clone(),
     };
 
-    info!("Game ended - P0: {} | P1: {} | Winner: {:?}",
-          scores.0, scores.1, winner_handle);
+    info!("Game ended - scores: {:?} | Winning team: {:?} | Forfeited: {:?}",
+          game_end_data.scores, game_end_data.winning_team, game_end_data.forfeited);
 
     commands.insert_resource(game_end_data);
     commands.insert_resource(GameEndTimer::default());
@@ -90,12 +150,16 @@ pub fn game_end_timer(
     }
 }
 
-/// Submit stats to the backend API when entering GameEnd state
+/// Submit stats to the backend API when entering GameEnd state; the report is unsigned,
+/// so it counts towards the leaderboard but only a validator's result moves ratings
 #[cfg(target_arch = "wasm32")]
 pub fn submit_stats_on_game_end(
     scores: Res<Scores>,
+    rules: Res<MatchRules>,
     session_seed: Res<SessionSeed>,
     address_mapping: Res<PlayerAddressMapping>,
+    connections: Res<PeerConnections>,
+    local_players: Res<bevy_ggrs::LocalPlayers>,
     mut stats_submitted: ResMut<StatsSubmitted>,
 ) {
     use wasm_bindgen_futures::spawn_local;
@@ -105,34 +169,27 @@ pub fn submit_stats_on_game_end(
         return;
     }
 
-    let player0_addr = address_mapping.player0_address.clone();
-    let player1_addr = address_mapping.player1_address.clone();
-
-    // Only submit if we have at least one known address
-    if player0_addr.is_none() && player1_addr.is_none() {
-        warn!("Cannot submit stats: no player addresses available");
-        return;
-    }
-
-    // Prevent duplicate submissions when both players have the same address (testing scenario)
-    if player0_addr.is_some() && player1_addr.is_some() && player0_addr == player1_addr {
-        warn!("Both players have the same address - skipping stats submission to prevent duplicates");
+    // The leaderboard API only understands 1v1 results
+    if rules.num_players != 2 {
+        info!("Skipping stats submission for {} match", rules.label());
+        stats_submitted.0 = true;
         return;
     }
 
-    let player0_name = address_mapping.player0_display_name.clone();
-    let player1_name = address_mapping.player1_display_name.clone();
-
-    let payload = MatchResult {
-        player1_address: player0_addr.unwrap_or_else(|| "unknown".to_string()),
-        player2_address: player1_addr.unwrap_or_else(|| "unknown".to_string()),
-        player1_display_name: player0_name,
-        player2_display_name: player1_name,
-        player1_score: scores.0,
-        player2_score: scores.1,
-        session_seed: format!("{}", session_seed.0),
+    let mut payload = match MatchResult::for_duel(&scores, &session_seed, &address_mapping, &connections) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Cannot submit stats: {e}");
+            return;
+        }
     };
 
+    // Lets the backend tell whether a player named as forfeiting was still around to report
+    payload.reporter_address = local_players
+        .0
+        .first()
+        .and_then(|&handle| address_mapping.get_address_by_handle(handle).cloned());
+
     info!("Submitting stats to API: {:?}", payload);
 
     stats_submitted.0 = true;
@@ -150,6 +207,7 @@ pub fn submit_stats_on_game_end(
 pub fn submit_stats_on_game_end(
     scores: Res<Scores>,
     address_mapping: Res<PlayerAddressMapping>,
+    connections: Res<PeerConnections>,
     mut stats_submitted: ResMut<StatsSubmitted>,
 ) {
     if stats_submitted.0 {
@@ -161,17 +219,65 @@ pub fn submit_stats_on_game_end(
     info!("Stats submission skipped (not running in browser)");
     info!("Game ended with scores: {:?}", *scores);
     info!("Player addresses: {:?}", *address_mapping);
+    if connections.has_forfeit() {
+        info!("Match ended by forfeit of handles {:?}", connections.forfeited);
+    }
 }
 
+/// Body of a `POST /api/stats` request
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct MatchResult {
-    player1_address: String,
-    player2_address: String,
-    player1_display_name: Option<String>,
-    player2_display_name: Option<String>,
-    player1_score: u32,
-    player2_score: u32,
-    session_seed: String,
+pub struct MatchResult {
+    pub player1_address: String,
+    pub player2_address: String,
+    pub player1_display_name: Option<String>,
+    pub player2_display_name: Option<String>,
+    pub player1_score: u32,
+    pub player2_score: u32,
+    pub session_seed: String,
+    /// Set when a player disconnected and the survivor was awarded the match
+    pub forfeit: bool,
+    pub forfeited_address: Option<String>,
+    /// Address of the player sending this report; the validator's signed results carry none
+    pub reporter_address: Option<String>,
+}
+
+impl MatchResult {
+    /// Builds the result of a 1v1 match, or explains why it can't be reported
+    pub fn for_duel(
+        scores: &Scores,
+        session_seed: &SessionSeed,
+        address_mapping: &PlayerAddressMapping,
+        connections: &PeerConnections,
+    ) -> Result<Self, String> {
+        let player0_addr = address_mapping.get_address_by_handle(0).cloned();
+        let player1_addr = address_mapping.get_address_by_handle(1).cloned();
+
+        // Only submit if we have at least one known address
+        if player0_addr.is_none() && player1_addr.is_none() {
+            return Err("no player addresses available".to_string());
+        }
+
+        // Prevent duplicate submissions when both players have the same address (testing scenario)
+        if player0_addr.is_some() && player1_addr.is_some() && player0_addr == player1_addr {
+            return Err("both players have the same address".to_string());
+        }
+
+        Ok(Self {
+            player1_address: player0_addr.unwrap_or_else(|| "unknown".to_string()),
+            player2_address: player1_addr.unwrap_or_else(|| "unknown".to_string()),
+            player1_display_name: address_mapping.get_display_name_by_handle(0).cloned(),
+            player2_display_name: address_mapping.get_display_name_by_handle(1).cloned(),
+            player1_score: scores.get(0),
+            player2_score: scores.get(1),
+            session_seed: format!("{}", session_seed.0),
+            forfeit: connections.has_forfeit(),
+            forfeited_address: connections
+                .forfeited
+                .first()
+                .and_then(|&handle| address_mapping.get_address_by_handle(handle).cloned()),
+            reporter_address: None,
+        })
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -180,7 +286,7 @@ async fn submit_stats_async(payload: MatchResult) -> Result<(), String> {
     use wasm_bindgen_futures::JsFuture;
     use web_sys::{Request, RequestInit, RequestMode, Response, Headers};
 
-    let api_url = "https://dk-leaderboard-api.dagknights.workers.dev/api/stats";
+    let api_url = crate::core::constants::api_url("/api/stats");
 
     let mut opts = RequestInit::new();
     opts.method("POST");
@@ -195,7 +301,7 @@ async fn submit_stats_async(payload: MatchResult) -> Result<(), String> {
         .map_err(|e| format!("Serialization error: {}", e))?;
     opts.body(Some(&JsValue::from_str(&json_str)));
 
-    let request = Request::new_with_str_and_init(api_url, &opts)
+    let request = Request::new_with_str_and_init(&api_url, &opts)
         .map_err(|e| format!("Request creation error: {:?}", e))?;
 
     let window = web_sys::window().ok_or("No window object")?;
@@ -9,6 +9,8 @@ pub mod animation;
 pub mod damage_numbers;
 pub mod leaderboard;
 pub mod cleanup;
+pub mod simulation;
+pub mod bot;
 
 // Re-export commonly used items
 pub use player::*;
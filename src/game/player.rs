@@ -1,5 +1,6 @@
 // player.rs
 use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
 use bevy::math::primitives::Cylinder;
 use bevy::light::NotShadowCaster;
 use bevy_ggrs::{prelude::*, AddRollbackCommandExtension};
@@ -8,62 +9,52 @@ use rand::{Rng, SeedableRng};
 use crate::{
     Config,
     core::constants::*,
-    entities::components::{Player, BulletReady, MoveDir, DistanceTraveled, Bullet, GameEntity},
+    core::fixed::{Fixed, FixedVec2},
+    entities::components::{
+        Aim, AnimationState, AttackBox, Bullet, BulletReady, CombatAction, CombatState, DistanceTraveled, GameEntity, Health,
+        Invincible, Locomotion, MoveDir, Player, Position, Stamina, SPRINT_STAMINA_DRAIN,
+    },
     ModelAssets,
-    core::resources::{Scores, SessionSeed},
-    game::input::direction,
+    core::resources::{Scores, SessionSeed, MatchRules, MovementTuning, PlayerAddressMapping},
+    game::combat::guard_for,
+    game::input::{aim_direction, direction, sprint},
     materials::aura::{AuraMaterial, EFFECT_FIRE},
     systems::aura_effects::AuraDisc,
 };
 
+/// Everything that decides who spawns where this round, and with what
+#[derive(SystemParam)]
+pub struct RoundLineup<'w> {
+    scores: Res<'w, Scores>,
+    rules: Res<'w, MatchRules>,
+    session_seed: Res<'w, SessionSeed>,
+    // Loadouts come from the handshake, so every peer guards each player with the same shield
+    address_mapping: Option<Res<'w, PlayerAddressMapping>>,
+}
+
+/// Players, bullets, swings and aura discs still out from the last round
+type RoundLeftovers = Or<(With<Player>, With<Bullet>, With<AttackBox>, With<AuraDisc>)>;
+
 pub fn spawn_players(
     mut commands: Commands,
-    players: Query<Entity, With<Player>>,
-    bullets: Query<Entity, With<Bullet>>,
-    aura_discs: Query<Entity, With<AuraDisc>>, // ADD: To despawn auras separately
-    scores: Res<Scores>,
-    session_seed: Res<SessionSeed>,
+    leftovers: Query<Entity, RoundLeftovers>,
+    lineup: RoundLineup,
     models: Res<ModelAssets>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut aura_materials: Option<ResMut<Assets<AuraMaterial>>>, // Fixed: Option to avoid panic
 ) {
     info!("Spawning players");
 
-    // Despawn players
-    for player in &players {
-        commands.entity(player).despawn();
-    }
-
-    // Despawn bullets
-    for bullet in &bullets {
-        commands.entity(bullet).despawn();
-    }
-
-    // Despawn existing aura discs (safe fallback without recursive)
-    for disc in &aura_discs {
-        commands.entity(disc).despawn();
+    for entity in &leftovers {
+        commands.entity(entity).despawn();
     }
 
-    let mut rng = Xoshiro256PlusPlus::seed_from_u64((scores.0 + scores.1) as u64 ^ **session_seed);
-    let half = MAP_SIZE as f32 / 2.;
-    let p1_pos = Vec3::new(
-        rng.random_range(-half..half),
-        PLAYER_HEIGHT / 2.,
-        rng.random_range(-half..half),
-    );
-    let p2_pos = Vec3::new(
-        rng.random_range(-half..half),
-        PLAYER_HEIGHT / 2.,
-        rng.random_range(-half..half),
-    );
+    let RoundLineup { scores, rules, session_seed, address_mapping } = lineup;
 
-    let initial_dir = -Vec2::X;
-    let forward = Vec3::new(initial_dir.x, 0.0, initial_dir.y).normalize_or_zero();
-    let initial_rotation = if forward != Vec3::ZERO {
-        Quat::from_rotation_arc(Vec3::X, forward)
-    } else {
-        Quat::IDENTITY
-    };
+    // Spawn everyone on a ring facing the centre, rotated by a per-round random angle
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(scores.rounds_played as u64 ^ **session_seed);
+    let spawn_radius = Fixed::from_ratio(MAP_SIZE - 6, 2);
+    let ring_offset = Fixed(rng.random_range(0..Fixed::ONE.0));
 
     // Common aura setup (only if materials resource exists)
     let disc_mesh = meshes.add(Cylinder::new(2.0, 0.05).mesh());
@@ -92,75 +83,137 @@ pub fn spawn_players(
         NotShadowCaster, // Prevent aura from casting shadows
     );
 
-    // Player 1
-    commands
-        .spawn((
-            Player { handle: 0 },
-            BulletReady(true),
-            MoveDir(initial_dir),
-            DistanceTraveled(0.0),
-            SceneRoot(models.player_1.clone()),
-            Transform::from_translation(p1_pos).with_rotation(initial_rotation),
-            Visibility::default(),
-            GameEntity, // Mark for cleanup
-        ))
-        .add_rollback()
-        .with_children(|parent| {
-            parent.spawn(aura_bundle.clone());
-        });
-
-    // Player 2
-    commands
-        .spawn((
-            Player { handle: 1 },
-            BulletReady(true),
-            MoveDir(initial_dir),
-            DistanceTraveled(0.0),
-            SceneRoot(models.player_2.clone()),
-            Transform::from_translation(p2_pos).with_rotation(initial_rotation),
-            Visibility::default(),
-            GameEntity, // Mark for cleanup
-        ))
-        .add_rollback()
-        .with_children(|parent| {
-            parent.spawn(aura_bundle.clone());
-        });
+    for handle in 0..rules.num_players {
+        let turns = ring_offset + Fixed::from_ratio(rules.spawn_slot(handle) as i32, rules.num_players as i32);
+        let position = FixedVec2::from_turns(turns) * spawn_radius;
+        let pos = position.to_vec3(PLAYER_HEIGHT / 2.);
+
+        let initial_dir = (-position).normalize_or(FixedVec2::new(Fixed::ONE, Fixed::ZERO));
+        let forward = initial_dir.to_vec3(0.0);
+        let initial_rotation = Quat::from_rotation_arc(Vec3::X, forward);
+
+        let loadout = address_mapping
+            .as_ref()
+            .and_then(|mapping| mapping.players.get(handle))
+            .map_or(&[][..], |info| info.loadout.as_slice());
+
+        let model = if handle % 2 == 0 {
+            models.player_1.clone()
+        } else {
+            models.player_2.clone()
+        };
+
+        commands
+            .spawn((
+                Player { handle },
+                BulletReady(true),
+                // Combat state, grouped to stay within the bundle tuple limit
+                (
+                    Health::default(),
+                    CombatState::default(),
+                    Stamina::default(),
+                    Invincible::default(),
+                    guard_for(loadout),
+                    AnimationState::default(),
+                ),
+                MoveDir(initial_dir),
+                Aim(initial_dir),
+                Locomotion::default(),
+                DistanceTraveled::default(),
+                Position(position),
+                SceneRoot(model),
+                Transform::from_translation(pos).with_rotation(initial_rotation),
+                Visibility::default(),
+                GameEntity, // Mark for cleanup
+            ))
+            .add_rollback()
+            .with_children(|parent| {
+                parent.spawn(aura_bundle.clone());
+            });
+    }
 }
 
+/// Moves `velocity` towards `target` by at most `max_change`
+fn approach(velocity: FixedVec2, target: FixedVec2, max_change: Fixed) -> FixedVec2 {
+    let change = target - velocity;
+    if change.length_squared() <= max_change * max_change {
+        return target;
+    }
+    velocity + change.normalize_or(FixedVec2::ZERO) * max_change
+}
+
+/// What `move_players` reads and steers on each player
+type MovingPlayer = (
+    &'static mut Transform,
+    &'static mut Position,
+    &'static mut MoveDir,
+    &'static mut Aim,
+    &'static mut Locomotion,
+    &'static mut Stamina,
+    &'static mut DistanceTraveled,
+    &'static CombatState,
+    &'static Player,
+);
+
 pub fn move_players(
-    mut players: Query<(&mut Transform, &mut MoveDir, &mut DistanceTraveled, &Player)>,
+    mut players: Query<MovingPlayer>,
     inputs: Res<PlayerInputs<Config>>,
-    time: Res<Time>,
+    tuning: Res<MovementTuning>,
 ) {
-    for (mut transform, mut move_direction, mut distance, player) in &mut players {
+    let sprint_drain = SPRINT_STAMINA_DRAIN / Fixed::from_int(ROLLBACK_FPS as i32);
+
+    for (
+        mut transform,
+        mut position,
+        mut move_direction,
+        mut aim,
+        mut locomotion,
+        mut stamina,
+        mut distance,
+        combat,
+        player,
+    ) in &mut players
+    {
         let (input, _) = inputs[player.handle];
-
-        let direction = direction(input);
-
-        if direction == Vec2::ZERO {
-            continue;
+        let direction = direction(input.buttons);
+
+        if combat.current_action == CombatAction::Dodge {
+            // A roll is committed: it carries on along the facing it started with, whatever is held
+            locomotion.sprinting = false;
+            locomotion.velocity = move_direction.0 * DODGE_STEP;
+        } else {
+            // Only a free player sprints, and only while there's stamina to pay for the frame
+            locomotion.sprinting = sprint(input.buttons)
+                && direction != FixedVec2::ZERO
+                && combat.current_action == CombatAction::Idle
+                && stamina.try_spend(sprint_drain);
+            let top_step = if locomotion.sprinting { tuning.sprint_step() } else { tuning.walk_step() };
+            let target = direction * top_step;
+            locomotion.velocity = approach(locomotion.velocity, target, tuning.acceleration_step());
+
+            if direction != FixedVec2::ZERO {
+                move_direction.0 = direction;
+            }
         }
 
-        move_direction.0 = direction;
-
-        // Set rotation to face movement direction
-        let forward = Vec3::new(direction.x, 0.0, direction.y).normalize_or_zero();
+        // Twin-stick: strafing keeps facing the aim, and without one the player faces where they go
+        aim.0 = aim_direction(input).unwrap_or(move_direction.0);
+        let facing = aim.0.to_vec2();
+        let forward = Vec3::new(facing.x, 0.0, facing.y).normalize_or_zero();
         if forward != Vec3::ZERO {
             transform.rotation = Quat::from_rotation_arc(Vec3::X, forward);
         }
 
-        let move_speed = 6.;
-        let move_delta = direction * move_speed * time.delta_secs();
+        // One fixed tick per GGRS frame, so no frame time enters the simulation
+        let move_delta = locomotion.velocity;
+        if move_delta == FixedVec2::ZERO {
+            continue;
+        }
+        let limit = Fixed::from_ratio(MAP_SIZE - 1, 2);
 
-        let old_pos = transform.translation;
-        let limit = MAP_SIZE as f32 / 2. - 0.5;
-        
         // Move in XZ plane (horizontal plane in 3D)
-        let new_x = (old_pos.x + move_delta.x).clamp(-limit, limit);
-        let new_z = (old_pos.z + move_delta.y).clamp(-limit, limit);
-
-        transform.translation.x = new_x;
-        transform.translation.z = new_z;
+        position.0.x = (position.0.x + move_delta.x).clamp(-limit, limit);
+        position.0.y = (position.0.y + move_delta.y).clamp(-limit, limit);
 
         distance.0 += move_delta.length();
     }
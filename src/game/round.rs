@@ -1,16 +1,35 @@
 // round.rs
 use bevy::prelude::*;
+use bevy_ggrs::RollbackFrameCount;
+use bevy_roll_safe::InitialStateEntered;
 use crate::core::states::RollbackState;
-use crate::core::resources::RoundEndTimer;
+use crate::core::resources::{RoundEndTimer, Scores};
+use crate::core::constants::rollback_timestep;
+use crate::game::combat::HitLog;
+use crate::game::leaderboard::DecidingFrame;
 
 pub fn round_end_timeout(
     mut timer: ResMut<RoundEndTimer>,
     mut state: ResMut<NextState<RollbackState>>,
-    time: Res<Time>,
 ) {
-    timer.tick(time.delta());
+    // Exactly one tick per frame, so the timer expires on the same frame for every peer
+    timer.tick(rollback_timestep());
 
     if timer.just_finished() {
         state.set(RollbackState::InRound);
     }
-}
\ No newline at end of file
+}
+
+/// Puts the rollback world back to frame 0 of a fresh match, so every session (and every
+/// replay of it) starts from the same state and re-runs `OnEnter(InRound)` on its first frame
+pub fn reset_round_state(commands: &mut Commands) {
+    commands.insert_resource(State::new(RollbackState::InRound));
+    commands.insert_resource(NextState::<RollbackState>::Unchanged);
+    commands.insert_resource(InitialStateEntered::<RollbackState>::default());
+    commands.insert_resource(RoundEndTimer::default());
+    commands.insert_resource(Scores::default());
+    commands.insert_resource(RollbackFrameCount(0));
+    // Frame numbers start over, so hits from the last match must not count as already shown
+    commands.insert_resource(HitLog::default());
+    commands.insert_resource(DecidingFrame::default());
+}
@@ -0,0 +1,103 @@
+// simulation.rs - The rollback simulation, shared by the game client and the headless validator
+use bevy::prelude::*;
+use bevy_ggrs::prelude::*;
+use bevy_roll_safe::prelude::*;
+use crate::{
+    Config,
+    core::constants::ROLLBACK_FPS,
+    core::resources::{MovementTuning, RoundEndTimer, Scores},
+    core::states::RollbackState,
+    entities::{self, components::*},
+    game, network, utils, world,
+};
+
+/// Registers rollback state and every system that runs inside GGRS frames, so a spectator
+/// re-simulating a match runs exactly what the players ran
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((GgrsPlugin::<Config>::default(), RollbackSchedulePlugin::new_ggrs()))
+            // GGRS rollback setup
+            .init_ggrs_state::<RollbackState>()
+            .rollback_resource_with_clone::<RoundEndTimer>()
+            .rollback_resource_with_copy::<Scores>()
+            .rollback_component_with_clone::<Transform>()
+            .rollback_component_with_copy::<Bullet>()
+            .rollback_component_with_copy::<BulletReady>()
+            .rollback_component_with_copy::<Player>()
+            .rollback_component_with_copy::<Wall>()
+            .rollback_component_with_copy::<MoveDir>()
+            .rollback_component_with_copy::<Aim>()
+            .rollback_component_with_copy::<DistanceTraveled>()
+            .rollback_component_with_copy::<Position>()
+            .rollback_component_with_copy::<Locomotion>()
+            .rollback_component_with_copy::<Health>()
+            .rollback_component_with_copy::<CombatState>()
+            .rollback_component_with_copy::<Stamina>()
+            .rollback_component_with_copy::<Invincible>()
+            .rollback_component_with_copy::<Guard>()
+            .rollback_component_with_copy::<AttackBox>()
+            .insert_resource(RollbackFrameRate(ROLLBACK_FPS))
+            .add_plugins((utils::ChecksumPlugin, game::combat::CombatPlugin))
+            .init_resource::<MovementTuning>()
+            .init_resource::<RoundEndTimer>()
+            .init_resource::<Scores>()
+            .init_resource::<network::desync::DesyncLog>()
+            .init_resource::<game::combat::HitLog>()
+            .init_resource::<network::replay::ReplayRecorder>()
+            .init_resource::<game::leaderboard::DecidingFrame>()
+            .init_resource::<network::stats::RollbackStats>()
+            // Round entry
+            .add_systems(
+                OnEnter(RollbackState::InRound),
+                (world::map::generate_map, game::player::spawn_players.after(world::map::generate_map)),
+            )
+            // Record the inputs each frame was simulated with, and count resimulated frames
+            .add_systems(
+                RollbackPreUpdate,
+                (network::replay::record_replay_inputs, network::stats::count_rollbacks),
+            )
+            // Rollback logic
+            .add_systems(
+                RollbackUpdate,
+                (
+                    game::player::move_players,
+                    world::collisions::resolve_wall_collisions.after(game::player::move_players),
+                    entities::bullet::reload_bullet,
+                    entities::bullet::fire_bullets
+                        .after(game::player::move_players)
+                        .after(entities::bullet::reload_bullet)
+                        .after(world::collisions::resolve_wall_collisions),
+                    entities::bullet::move_bullet.after(entities::bullet::fire_bullets),
+                    world::collisions::bullet_wall_collisions.after(entities::bullet::move_bullet),
+                    world::collisions::bullet_hits
+                        .after(entities::bullet::move_bullet)
+                        .after(game::player::move_players)
+                        .after(game::combat::detect_hits),
+                    world::collisions::kill_players.after(world::collisions::bullet_hits),
+                )
+                    .run_if(in_state(RollbackState::InRound))
+                    .after(bevy_roll_safe::apply_state_transition::<RollbackState>),
+            )
+            .add_systems(
+                RollbackUpdate,
+                game::round::round_end_timeout
+                    .run_if(in_state(RollbackState::RoundEnd))
+                    .ambiguous_with(world::collisions::kill_players),
+            )
+            // Derive transforms from fixed-point positions, then record the frame for desync diagnostics
+            .add_systems(
+                RollbackPostUpdate,
+                (
+                    entities::components::sync_transforms,
+                    network::desync::record_desync_state,
+                )
+                    .chain(),
+            )
+            .add_systems(RollbackPostUpdate, game::leaderboard::record_deciding_frame)
+            .add_systems(bevy_ggrs::LoadWorld, game::combat::rollback_hit_log)
+            // Settle which recorded frames are final once GGRS has run for this update
+            .add_systems(Update, network::replay::confirm_replay_inputs);
+    }
+}
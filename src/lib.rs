@@ -29,15 +29,16 @@ mod materials;
 // Utilities
 mod utils;
 
+// Headless simulation tests
+#[cfg(test)]
+mod tests;
+
 use core::args::Args;
 use core::states::GameState;
 use bevy_asset_loader::prelude::*;
 use bevy_egui::{EguiPlugin, EguiPrimaryContextPass}; // Fixed: Import EguiPrimaryContextPass instead
 use bevy_ggrs::prelude::*;
 use bevy_matchbox::prelude::PeerId;
-use bevy_roll_safe::prelude::*;
-use clap::Parser;
-use entities::components::*;
 use game::input::read_local_inputs;
 
 // Define UiReady resource here for simplicity (or move to resources.rs) - made pub for cross-module access
@@ -48,7 +49,7 @@ use ui::chat::{ChatPlugin, setup_chat_socket, ChatUIPlugin};
 use ui::auth::AuthUIPlugin;
 use systems::aura_effects::{setup_aura_effects, aura_effects_ui, handle_shader_reload}; // ADD
 
-type Config = bevy_ggrs::GgrsConfig<u8, PeerId>;
+type Config = bevy_ggrs::GgrsConfig<game::input::PlayerInput, PeerId>;
 
 #[derive(AssetCollection, Resource)]
 struct ModelAssets {
@@ -58,22 +59,25 @@ struct ModelAssets {
     player_2: Handle<Scene>,
 }
 
+// Headless match validator, see `src/bin/validator.rs`
+#[cfg(feature = "validator")]
+pub use network::validator::run_validator;
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub fn run() {
     run_app();
 }
 
 // Reset game stats when returning to lobby
-fn reset_game_stats(
-    mut scores: ResMut<core::resources::Scores>,
-    mut stats_submitted: ResMut<game::leaderboard::StatsSubmitted>,
-    mut address_mapping: ResMut<core::resources::PlayerAddressMapping>,
-    mut game_end_data: ResMut<game::leaderboard::GameEndData>,
-) {
-    *scores = core::resources::Scores::default();
-    stats_submitted.0 = false;
-    *address_mapping = core::resources::PlayerAddressMapping::default();
-    *game_end_data = game::leaderboard::GameEndData::default();
+fn reset_game_stats(mut commands: Commands) {
+    commands.insert_resource(core::resources::Scores::default());
+    commands.insert_resource(game::leaderboard::StatsSubmitted::default());
+    commands.insert_resource(core::resources::PlayerAddressMapping::default());
+    commands.insert_resource(game::leaderboard::GameEndData::default());
+    commands.insert_resource(network::session::PeerConnections::default());
+    commands.insert_resource(network::desync::DesyncLog::default());
+    commands.insert_resource(network::replay::ReplayRecorder::default());
+    commands.insert_resource(network::stats::RollbackStats::default());
 }
 
 // Force leaderboard refresh when entering lobby
@@ -85,7 +89,7 @@ fn force_leaderboard_refresh(
 }
 
 fn run_app() {
-    let args = Args::parse();
+    let args = Args::from_env();
     eprintln!("{args:?}");
 
     App::new()
@@ -101,8 +105,6 @@ fn run_app() {
                     ..default()
                 })
                 .set(ImagePlugin::default_nearest()),
-            GgrsPlugin::<Config>::default(),
-            RollbackSchedulePlugin::new_ggrs(),
             EguiPlugin::default(),
             MaterialPlugin::<materials::aura::AuraMaterial>::default(),
         ))
@@ -110,6 +112,7 @@ fn run_app() {
         .add_plugins(ChatPlugin)
         .add_plugins(ChatUIPlugin)
         .add_plugins(AuthUIPlugin)
+        .add_plugins(game::damage_numbers::DamageNumberPlugin)
         // State machine
         .init_state::<GameState>()
         .insert_state(GameState::WalletAuth)
@@ -119,27 +122,30 @@ fn run_app() {
                 .load_collection::<ModelAssets>()
                 .continue_to_state(core::states::GameState::Lobby), // Go to Lobby after loading
         )
-        // GGRS rollback setup
-        .init_ggrs_state::<core::states::RollbackState>()
-        .rollback_resource_with_clone::<core::resources::RoundEndTimer>()
-        .rollback_resource_with_copy::<core::resources::Scores>()
-        .rollback_component_with_clone::<Transform>()
-        .rollback_component_with_copy::<Bullet>()
-        .rollback_component_with_copy::<BulletReady>()
-        .rollback_component_with_copy::<Player>()
-        .rollback_component_with_copy::<Wall>()
-        .rollback_component_with_copy::<MoveDir>()
-        .rollback_component_with_copy::<DistanceTraveled>()
-        .checksum_component::<Transform>(utils::checksum_transform)
+        // Rollback simulation
+        .add_plugins(game::simulation::SimulationPlugin)
         // Resources
+        .insert_resource(core::resources::MatchRules::from_args(&args))
+        .insert_resource(core::resources::ServerSettings::from_args(&args))
+        .insert_resource(core::resources::InputDelay::from_args(&args))
+        .insert_resource(core::resources::BotOpponent::from_args(&args))
+        .insert_resource(core::resources::CouchMatch::from_args(&args))
+        .insert_resource(core::resources::WatchRequest::from_args(&args))
+        .init_resource::<core::resources::WatchedMatch>()
+        .init_resource::<game::input::GamepadSlots>()
+        .init_resource::<game::bot::BotBrain>()
+        .insert_resource(core::resources::InputDelayTable::from_args(&args))
         .insert_resource(args)
         .insert_resource(ClearColor(Color::srgb(0.0, 0.0, 0.0)))
-        .init_resource::<core::resources::RoundEndTimer>()
-        .init_resource::<core::resources::Scores>()
         .init_resource::<core::resources::PlayerAddressMapping>()
+        .init_resource::<core::resources::MatchRoom>()
+        .init_resource::<core::resources::MatchRole>()
         .init_resource::<game::leaderboard::StatsSubmitted>()
         .init_resource::<game::leaderboard::GameEndData>()
+        .init_resource::<network::session::PeerConnections>()
+        .init_resource::<ui::network_stats::NetworkStatsOverlay>()
         .init_resource::<ui::leaderboard::LeaderboardData>()
+        .init_resource::<network::rating::PlayerRating>()
         .init_resource::<UiReady>()
         .init_resource::<ui::lobby::PlayerProfile>()
         .init_resource::<ui::lobby::LobbyNotifications>()
@@ -164,9 +170,27 @@ fn run_app() {
                 ui::hud::setup_player_vitals,
                 reset_game_stats,
                 force_leaderboard_refresh,
+                network::rating::refresh_player_rating,
                 setup_chat_socket.run_if(network::matchmaking::p2p_mode),
+                network::watch::close_watch_room,
             ),
         )
+        // Matchmaking aborted (e.g. handshake timeout) - drop the arena spawned on entry
+        .add_systems(
+            OnTransition {
+                exited: core::states::GameState::Matchmaking,
+                entered: core::states::GameState::Lobby,
+            },
+            game::cleanup::cleanup_game_entities,
+        )
+        // Spectating ended early (host left) - same cleanup GameEnd would have done
+        .add_systems(
+            OnTransition {
+                exited: core::states::GameState::InGame,
+                entered: core::states::GameState::Lobby,
+            },
+            game::cleanup::cleanup_game_entities,
+        )
         // Lobby exit - cleanup
         .add_systems(
             OnExit(core::states::GameState::Lobby),
@@ -177,7 +201,30 @@ fn run_app() {
             OnEnter(core::states::GameState::Matchmaking),
             (
                 utils::setup::setup,
-                network::matchmaking::start_matchbox_socket.run_if(network::matchmaking::p2p_mode),
+                network::matchmaking::start_matchbox_socket
+                    .run_if(network::matchmaking::p2p_mode)
+                    .run_if(not(network::spectator::spectating)),
+                network::spectator::join_watch_room
+                    .run_if(network::matchmaking::p2p_mode)
+                    .run_if(network::spectator::spectating),
+            ),
+        )
+        // Players open the watch room spectators follow them through, if anyone asked for one
+        .add_systems(
+            OnEnter(core::states::GameState::InGame),
+            network::watch::open_watch_relay
+                .run_if(network::matchmaking::p2p_mode)
+                .run_if(not(network::spectator::spectating))
+                .run_if(network::watch::watched_match),
+        )
+        // Replays leave virtual time paused or sped up, so restore it for the end screen,
+        // and refill the HUD orbs rather than showing how the last match ended
+        .add_systems(
+            OnExit(core::states::GameState::InGame),
+            (
+                network::replay::reset_replay_time.run_if(network::replay::replay_mode),
+                network::spectator::reset_live_time.run_if(network::spectator::spectating),
+                ui::hud::setup_player_vitals,
             ),
         )
         // GameEnd entry - cleanup game entities, setup end screen and submit stats
@@ -185,8 +232,10 @@ fn run_app() {
             OnEnter(core::states::GameState::GameEnd),
             (
                 game::cleanup::cleanup_game_entities,
+                network::replay::finish_replay_recording,
+                network::watch::finish_watch_relay.run_if(resource_exists::<network::watch::WatchRelay>),
                 game::leaderboard::setup_game_end,
-                game::leaderboard::submit_stats_on_game_end,
+                game::leaderboard::submit_stats_on_game_end.run_if(network::session::online_session),
             ).chain(), // Chain to ensure cleanup happens first
         )
         // Aura systems (visual only, no rollback needed)
@@ -196,14 +245,35 @@ fn run_app() {
             (
                 aura_effects_ui,
                 ui::lobby::lobby_ui.run_if(in_state(core::states::GameState::Lobby)),
+                ui::lobby::server_settings_ui.run_if(in_state(core::states::GameState::Lobby)),
+                ui::lobby::matchmaking_room_ui
+                    .run_if(not(network::spectator::spectating))
+                    .run_if(in_state(core::states::GameState::Matchmaking)),
+                ui::lobby::watch_join_ui
+                    .run_if(network::spectator::spectating)
+                    .run_if(in_state(core::states::GameState::Matchmaking)),
                 ui::leaderboard::render_leaderboard_ui.run_if(in_state(core::states::GameState::Lobby)),
                 ui::game_end::render_game_end_ui.run_if(in_state(core::states::GameState::GameEnd)),
+                network::replay::replay_controls_ui
+                    .run_if(in_state(core::states::GameState::InGame))
+                    .run_if(resource_exists::<network::replay::ReplayPlayback>),
+                ui::network_stats::network_stats_ui.run_if(in_state(core::states::GameState::InGame)),
             ),
         )
         .add_systems(
             Update,
             handle_shader_reload,
         )
+        // Pads keep their local slot from connection to disconnection, in every state
+        .add_systems(
+            Update,
+            game::input::assign_gamepad_slots,
+        )
+        // Spectators keep being served through the end screen
+        .add_systems(
+            Update,
+            network::watch::serve_watch_relay.run_if(resource_exists::<network::watch::WatchRelay>),
+        )
         // Update systems
         .add_systems(
             Update,
@@ -218,25 +288,65 @@ fn run_app() {
                 )
                     .run_if(in_state(core::states::GameState::Lobby)),
                 // HUD systems in Lobby
+                ui::hud::render_diablo_hud.run_if(in_state(core::states::GameState::Lobby)),
+                // Leaderboard system in Lobby
+                ui::leaderboard::fetch_leaderboard.run_if(in_state(core::states::GameState::Lobby)),
+                network::rating::fetch_player_rating.run_if(in_state(core::states::GameState::Lobby)),
+                // Replay mode skips the lobby once the replay is loaded
                 (
-                    ui::hud::render_diablo_hud,
-                    ui::hud::simulate_vitals_changes,
+                    network::replay::load_replay
+                        .run_if(not(resource_exists::<network::replay::ReplayPlayback>)),
+                    network::replay::start_replay
+                        .run_if(resource_exists::<network::replay::ReplayPlayback>),
                 )
+                    .run_if(network::replay::replay_mode)
                     .run_if(in_state(core::states::GameState::Lobby)),
-                // Leaderboard system in Lobby
-                ui::leaderboard::fetch_leaderboard.run_if(in_state(core::states::GameState::Lobby)),
                 // Matchmaking systems
                 (
-                    network::matchmaking::wait_for_players.run_if(network::matchmaking::p2p_mode),
-                    network::matchmaking::start_synctest_session.run_if(network::matchmaking::synctest_mode),
+                    network::matchmaking::wait_for_players
+                        .run_if(network::matchmaking::p2p_mode)
+                        .run_if(not(network::spectator::spectating)),
+                    network::spectator::wait_for_match
+                        .run_if(network::matchmaking::p2p_mode)
+                        .run_if(network::spectator::spectating),
+                    network::rating::widen_rating_bucket
+                        .run_if(network::matchmaking::p2p_mode)
+                        .run_if(resource_exists::<network::rating::RatedQueue>),
+                    network::matchmaking::start_local_session.run_if(network::matchmaking::local_mode),
+                    network::replay::start_replay_session
+                        .run_if(resource_exists::<network::replay::ReplayPlayback>),
                 )
                     .run_if(in_state(core::states::GameState::Matchmaking)),
                 // InGame systems
-                game::camera::camera_follow.run_if(in_state(core::states::GameState::InGame)),
-                ui::score::update_score_ui.run_if(in_state(core::states::GameState::InGame)),
+                game::camera::camera_follow
+                    .run_if(not(network::spectator::spectating))
+                    .run_if(in_state(core::states::GameState::InGame)),
+                ui::score::update_score_ui
+                    .run_if(not(network::spectator::spectating))
+                    .run_if(in_state(core::states::GameState::InGame)),
+                // Spectators get their own camera and a scoreboard with names
+                (
+                    network::spectator::spectator_camera,
+                    ui::score::spectator_score_ui,
+                    network::spectator::follow_live_feed,
+                    network::spectator::drive_live_playback,
+                )
+                    .run_if(network::spectator::spectating)
+                    .run_if(in_state(core::states::GameState::InGame)),
+                ui::disconnect::render_disconnect_overlay.run_if(in_state(core::states::GameState::InGame)),
+                ui::network_stats::toggle_network_stats.run_if(in_state(core::states::GameState::InGame)),
                 ui::auth::ui::update_wallet_display.run_if(in_state(core::states::GameState::InGame)),
                 network::session::handle_ggrs_events.run_if(in_state(core::states::GameState::InGame)),
-                game::leaderboard::check_game_end.run_if(in_state(core::states::GameState::InGame)),
+                network::stats::count_desync_checks
+                    .run_if(resource_exists::<bevy_ggrs::Session<Config>>)
+                    .run_if(in_state(core::states::GameState::InGame)),
+                game::leaderboard::check_game_end
+                    .after(network::replay::confirm_replay_inputs)
+                    .run_if(in_state(core::states::GameState::InGame)),
+                game::animation::update_animation_state.run_if(in_state(core::states::GameState::InGame)),
+                network::replay::drive_replay_playback
+                    .run_if(resource_exists::<network::replay::ReplayPlayback>)
+                    .run_if(in_state(core::states::GameState::InGame)),
                 // GameEnd systems
                 game::leaderboard::game_end_timer.run_if(in_state(core::states::GameState::GameEnd)),
                 // Inventory systems during gameplay
@@ -249,43 +359,29 @@ fn run_app() {
                 )
                     .run_if(in_state(core::states::GameState::InGame)),
                 // HUD systems during gameplay
-                (
-                    ui::hud::render_diablo_hud,
-                    ui::hud::simulate_vitals_changes,
-                )
+                (ui::hud::update_player_vitals, ui::hud::render_diablo_hud)
+                    .chain()
                     .run_if(in_state(core::states::GameState::InGame)),
             ),
         )
         // Input reading
-        .add_systems(ReadInputs, read_local_inputs)
-        // Round entry
         .add_systems(
-            OnEnter(core::states::RollbackState::InRound),
-            (world::map::generate_map, game::player::spawn_players.after(world::map::generate_map)),
-        )
-        // Rollback logic
-        .add_systems(
-            RollbackUpdate,
+            ReadInputs,
             (
-                game::player::move_players,
-                world::collisions::resolve_wall_collisions.after(game::player::move_players),
-                entities::bullet::reload_bullet,
-                entities::bullet::fire_bullets
-                    .after(game::player::move_players)
-                    .after(entities::bullet::reload_bullet)
-                    .after(world::collisions::resolve_wall_collisions),
-                entities::bullet::move_bullet.after(entities::bullet::fire_bullets),
-                world::collisions::bullet_wall_collisions.after(entities::bullet::move_bullet),
-                world::collisions::kill_players.after(entities::bullet::move_bullet).after(game::player::move_players),
-            )
-                .run_if(in_state(core::states::RollbackState::InRound))
-                .after(bevy_roll_safe::apply_state_transition::<core::states::RollbackState>),
-        )
-        .add_systems(
-            RollbackUpdate,
-            game::round::round_end_timeout
-                .run_if(in_state(core::states::RollbackState::RoundEnd))
-                .ambiguous_with(world::collisions::kill_players),
+                read_local_inputs
+                    .run_if(not(network::replay::replay_mode))
+                    .run_if(not(network::spectator::spectating)),
+                game::bot::bot_inputs
+                    .after(read_local_inputs)
+                    .run_if(not(network::replay::replay_mode))
+                    .run_if(game::bot::bot_match),
+                network::conditions::condition_local_inputs
+                    .after(read_local_inputs)
+                    .after(game::bot::bot_inputs)
+                    .run_if(resource_exists::<network::conditions::ConditionedInputs>),
+                network::replay::feed_replay_inputs.run_if(network::replay::replay_mode),
+                network::spectator::feed_watch_inputs.run_if(network::spectator::spectating),
+            ),
         )
         .run();
 }
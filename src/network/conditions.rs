@@ -0,0 +1,193 @@
+// conditions.rs - Simulated latency, jitter, loss and reordering for the GGRS channel,
+// or for the input feed when every player is on this machine
+use std::{collections::HashMap, time::Duration};
+use bevy::{platform::time::Instant, prelude::*};
+use bevy_ggrs::{ggrs::{Message, NonBlockingSocket}, LocalInputs};
+use bevy_matchbox::prelude::PeerId;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+use crate::{Config, core::args::Args, core::constants::rollback_timestep, game::input::PlayerInput};
+
+/// How badly outgoing packets are treated; each peer degrades only what it sends,
+/// so give both instances the same flags for symmetric conditions
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// Added to every packet
+    pub latency: Duration,
+    /// Uniformly random extra delay, up to this much
+    pub jitter: Duration,
+    /// Chance of dropping a packet outright
+    pub loss: f32,
+    /// Chance of holding a packet back so later ones overtake it
+    pub reorder: f32,
+}
+
+impl NetworkConditions {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            latency: Duration::from_millis(args.net_latency),
+            jitter: Duration::from_millis(args.net_jitter),
+            loss: args.net_loss.clamp(0.0, 1.0),
+            reorder: args.net_reorder.clamp(0.0, 1.0),
+        }
+    }
+
+    /// No shim needed: packets go straight through
+    pub fn is_ideal(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// How long one packet takes to arrive, or `None` if it's lost
+    fn delay(&self, rng: &mut impl Rng) -> Option<Duration> {
+        if rng.random::<f32>() < self.loss {
+            return None;
+        }
+
+        let mut delay = self.latency + self.jitter.mul_f32(rng.random());
+        if rng.random::<f32>() < self.reorder {
+            delay += REORDER_HOLD;
+        }
+        Some(delay)
+    }
+}
+
+/// Extra hold applied to reordered packets, on top of their normal delay
+const REORDER_HOLD: Duration = Duration::from_millis(50);
+
+struct DelayedMessage {
+    release_at: Instant,
+    addr: PeerId,
+    msg: Message,
+}
+
+/// Wraps a GGRS socket and degrades what it sends according to `NetworkConditions`
+pub struct ConditionedSocket<S> {
+    inner: S,
+    conditions: NetworkConditions,
+    rng: Xoshiro256PlusPlus,
+    in_flight: Vec<DelayedMessage>,
+}
+
+impl<S: NonBlockingSocket<PeerId>> ConditionedSocket<S> {
+    pub fn new(inner: S, conditions: NetworkConditions) -> Self {
+        info!("Simulating network conditions on the GGRS channel: {conditions:?}");
+        Self {
+            inner,
+            conditions,
+            rng: Xoshiro256PlusPlus::seed_from_u64(rand::rng().random()),
+            in_flight: Vec::new(),
+        }
+    }
+
+    /// Hands every packet whose delay has elapsed to the real socket
+    fn flush(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition::<Vec<_>, _>(|delayed| delayed.release_at <= now);
+        self.in_flight = pending;
+        for delayed in due {
+            self.inner.send_to(&delayed.msg, &delayed.addr);
+        }
+    }
+}
+
+impl<S: NonBlockingSocket<PeerId>> NonBlockingSocket<PeerId> for ConditionedSocket<S> {
+    fn send_to(&mut self, msg: &Message, addr: &PeerId) {
+        let Some(delay) = self.conditions.delay(&mut self.rng) else {
+            return;
+        };
+
+        self.in_flight.push(DelayedMessage {
+            release_at: Instant::now() + delay,
+            addr: *addr,
+            msg: msg.clone(),
+        });
+        self.flush();
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        // GGRS polls every frame, which keeps delayed packets moving even when nothing new is sent
+        self.flush();
+        self.inner.receive_all_messages()
+    }
+}
+
+struct DelayedInput {
+    /// Frame the input was read on
+    sent: u32,
+    arrives: u32,
+    handle: usize,
+    input: PlayerInput,
+}
+
+/// A SyncTest session has no socket to degrade, so `--net-*` degrades the other players'
+/// inputs instead: each one shows up late or not at all, and until a newer one arrives
+/// the last one keeps playing, the way GGRS predicts a remote player
+#[derive(Resource)]
+pub struct ConditionedInputs {
+    conditions: NetworkConditions,
+    rng: Xoshiro256PlusPlus,
+    /// Played as read, like the player at this machine
+    local_handle: usize,
+    /// Frames read so far
+    frame: u32,
+    in_flight: Vec<DelayedInput>,
+    /// Newest input to have arrived from each other handle, and the frame it was read on
+    arrived: HashMap<usize, (u32, PlayerInput)>,
+}
+
+impl ConditionedInputs {
+    pub fn new(conditions: NetworkConditions, local_handle: usize) -> Self {
+        info!("Simulating network conditions on the input feed: {conditions:?}");
+        Self {
+            conditions,
+            rng: Xoshiro256PlusPlus::seed_from_u64(rand::rng().random()),
+            local_handle,
+            frame: 0,
+            in_flight: Vec::new(),
+            arrived: HashMap::new(),
+        }
+    }
+
+    /// Sends `input` off and returns what `handle` plays this frame
+    fn relay(&mut self, handle: usize, input: PlayerInput) -> PlayerInput {
+        if let Some(delay) = self.conditions.delay(&mut self.rng) {
+            let frames = delay.as_nanos().div_ceil(rollback_timestep().as_nanos()) as u32;
+            self.in_flight.push(DelayedInput {
+                sent: self.frame,
+                arrives: self.frame + frames,
+                handle,
+                input,
+            });
+        }
+
+        let frame = self.frame;
+        let (due, pending) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition::<Vec<_>, _>(|delayed| delayed.arrives <= frame && delayed.handle == handle);
+        self.in_flight = pending;
+        for delayed in due {
+            // One overtaken by a later input is stale by the time it arrives
+            let newest = self.arrived.entry(handle).or_insert((delayed.sent, delayed.input));
+            if delayed.sent >= newest.0 {
+                *newest = (delayed.sent, delayed.input);
+            }
+        }
+        self.arrived.get(&handle).map_or_else(PlayerInput::default, |&(_, input)| input)
+    }
+}
+
+/// Runs after everything that fills `LocalInputs`, and swaps each other handle's input
+/// for the one that has made it through the conditions
+pub fn condition_local_inputs(mut local_inputs: ResMut<LocalInputs<Config>>, mut feed: ResMut<ConditionedInputs>) {
+    let mut handles: Vec<usize> = local_inputs.0.keys().copied().collect();
+    handles.sort();
+    for handle in handles {
+        if handle != feed.local_handle {
+            let input = local_inputs.0[&handle];
+            local_inputs.0.insert(handle, feed.relay(handle, input));
+        }
+    }
+    feed.frame += 1;
+}
@@ -0,0 +1,177 @@
+// desync.rs - Ring buffer of rollback state, dumped to disk when checksums disagree
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use bevy_ggrs::{Rollback, RollbackFrameCount, RollbackOrdered};
+use serde::Serialize;
+use crate::{
+    core::resources::{RoundEndTimer, Scores},
+    entities::components::{
+        AttackBox, Bullet, BulletReady, CombatState, Health, Invincible, MoveDir, Player, Position, Stamina,
+    },
+    utils::helpers::save_json_file,
+};
+
+/// Frames of state kept around; must cover the prediction window plus the desync detection delay
+pub const DESYNC_HISTORY_FRAMES: usize = 120;
+
+/// Frames on either side of the offending frame written to the dump
+pub const DESYNC_DUMP_RADIUS: i32 = 10;
+
+/// One rollback entity, keyed by its rollback order so both clients agree on identity
+#[derive(Serialize, Clone, Debug)]
+pub struct EntityState {
+    pub rollback_order: u64,
+    /// Raw Q16.16 bits, so the dump shows exactly what the simulation saw
+    pub position: Option<[i32; 2]>,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub player_handle: Option<usize>,
+    pub bullet_owner: Option<usize>,
+    pub move_dir: Option<[f32; 2]>,
+    pub bullet_ready: Option<bool>,
+    pub health: Option<i32>,
+    /// Debug form of the player's `CombatState`
+    pub combat: Option<String>,
+    /// Raw fixed-point bits, like `position`
+    pub stamina: Option<i32>,
+    pub invincible_frames: Option<u32>,
+    pub attack_owner: Option<usize>,
+}
+
+/// Everything that feeds the checksum, as it stood at the end of `frame`
+#[derive(Serialize, Clone, Debug)]
+pub struct FrameState {
+    pub frame: i32,
+    pub rounds_played: u32,
+    pub scores: Vec<u32>,
+    pub round_end_timer_elapsed: f32,
+    pub entities: Vec<EntityState>,
+}
+
+#[derive(Serialize)]
+struct DesyncDump<'a> {
+    desync_frame: i32,
+    local_handle: Option<usize>,
+    local_checksum: String,
+    remote_checksum: String,
+    frames: Vec<&'a FrameState>,
+}
+
+/// Recent simulated frames; deliberately not a rollback resource so it survives resimulation
+#[derive(Resource, Default)]
+pub struct DesyncLog {
+    pub frames: VecDeque<FrameState>,
+    /// Set after the first dump so a persistent desync doesn't write a file every frame
+    pub dumped: bool,
+}
+
+impl DesyncLog {
+    pub fn record(&mut self, state: FrameState) {
+        // A rollback resimulates from an earlier frame, so later predictions are stale
+        while self.frames.back().is_some_and(|last| last.frame >= state.frame) {
+            self.frames.pop_back();
+        }
+        self.frames.push_back(state);
+        while self.frames.len() > DESYNC_HISTORY_FRAMES {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Writes the frames around `frame` as JSON, once per match
+    pub fn dump(
+        &mut self,
+        frame: i32,
+        local_handle: Option<usize>,
+        local_checksum: u128,
+        remote_checksum: u128,
+        session_seed: u64,
+    ) {
+        if self.dumped {
+            return;
+        }
+        self.dumped = true;
+
+        let dump = DesyncDump {
+            desync_frame: frame,
+            local_handle,
+            local_checksum: format!("{local_checksum:X}"),
+            remote_checksum: format!("{remote_checksum:X}"),
+            frames: self
+                .frames
+                .iter()
+                .filter(|state| (state.frame - frame).abs() <= DESYNC_DUMP_RADIUS)
+                .collect(),
+        };
+
+        let json = match serde_json::to_string_pretty(&dump) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialize desync dump: {e}");
+                return;
+            }
+        };
+
+        let handle = local_handle.map_or("x".to_string(), |h| h.to_string());
+        let file_name = format!("desync_{session_seed:x}_f{frame}_p{handle}.json");
+        match save_json_file(&file_name, &json) {
+            Ok(()) => warn!("Wrote desync dump {file_name} ({} frames)", dump.frames.len()),
+            Err(e) => error!("Failed to write desync dump {file_name}: {e}"),
+        }
+    }
+}
+
+/// Every checksummed component a rollback entity may carry
+type DesyncComponents = (
+    &'static Rollback,
+    &'static Transform,
+    Option<&'static Position>,
+    Option<&'static Player>,
+    Option<&'static Bullet>,
+    Option<&'static MoveDir>,
+    Option<&'static BulletReady>,
+    Option<&'static Health>,
+    Option<&'static CombatState>,
+    Option<&'static Stamina>,
+    Option<&'static Invincible>,
+    Option<&'static AttackBox>,
+);
+
+/// Snapshots checksummed state after every simulated frame, including resimulated ones
+pub fn record_desync_state(
+    mut log: ResMut<DesyncLog>,
+    frame: Res<RollbackFrameCount>,
+    order: Res<RollbackOrdered>,
+    scores: Res<Scores>,
+    round_end_timer: Res<RoundEndTimer>,
+    entities: Query<DesyncComponents>,
+) {
+    let mut entities: Vec<EntityState> = entities
+        .iter()
+        .map(|(rollback, transform, position, player, bullet, move_dir, bullet_ready, health, combat, stamina, invincible, attack)| EntityState {
+            rollback_order: order.order(*rollback),
+            position: position.map(|p| [p.0.x.0, p.0.y.0]),
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            scale: transform.scale.to_array(),
+            player_handle: player.map(|p| p.handle),
+            bullet_owner: bullet.map(|b| b.owner),
+            move_dir: move_dir.map(|d| d.0.to_vec2().to_array()),
+            bullet_ready: bullet_ready.map(|r| r.0),
+            health: health.map(|h| h.current),
+            combat: combat.map(|c| format!("{c:?}")),
+            stamina: stamina.map(|s| s.current.0),
+            invincible_frames: invincible.map(|i| i.frames_left),
+            attack_owner: attack.map(|a| a.owner),
+        })
+        .collect();
+    entities.sort_by_key(|state| state.rollback_order);
+
+    log.record(FrameState {
+        frame: frame.0,
+        rounds_played: scores.rounds_played,
+        scores: scores.by_handle.to_vec(),
+        round_end_timer_elapsed: round_end_timer.elapsed_secs(),
+        entities,
+    });
+}
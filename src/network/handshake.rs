@@ -0,0 +1,239 @@
+// handshake.rs - Pre-session player info exchange over a reliable matchbox channel
+use std::time::Duration;
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_matchbox::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Bump whenever `HandshakeMessage` changes shape
+pub const HANDSHAKE_VERSION: u16 = 4;
+
+/// Channel 0 is the unreliable GGRS channel, channel 1 carries the handshake
+pub const GGRS_CHANNEL: usize = 0;
+pub const HANDSHAKE_CHANNEL: usize = 1;
+
+/// How long we wait for every peer's handshake before giving up
+pub const HANDSHAKE_TIMEOUT_SECS: f32 = 10.0;
+
+/// Peers are pinged this often while the room fills, to pick an input delay
+pub const PING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Round trips measured per peer before the session may start
+pub const RTT_SAMPLES: usize = 5;
+
+/// Once everyone has introduced themselves, how long to keep measuring before starting anyway
+pub const RTT_PROBE_TIMEOUT_SECS: f32 = 2.0;
+
+/// Identifies the client build so mismatched peers can be flagged before they desync
+pub const BUILD_HASH: &str = match option_env!("BUILD_HASH") {
+    Some(hash) => hash,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+/// Player info exchanged with every peer before the GGRS session starts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HandshakeMessage {
+    pub version: u16,
+    pub address: String,
+    pub display_name: String,
+    /// Equipped item ids, sorted so both sides see the same order
+    pub loadout: Vec<String>,
+    pub build_hash: String,
+    /// Asks every player to relay the match to its watch room
+    pub watchable: bool,
+}
+
+impl HandshakeMessage {
+    pub fn new(address: String, display_name: String, mut loadout: Vec<String>, watchable: bool) -> Self {
+        loadout.sort();
+        Self {
+            version: HANDSHAKE_VERSION,
+            address,
+            display_name,
+            loadout,
+            build_hash: BUILD_HASH.to_string(),
+            watchable,
+        }
+    }
+}
+
+/// Round-trip probes sharing the handshake channel; the payload is the sender's clock, echoed back
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum RttProbe {
+    Ping(Duration),
+    Pong(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    VersionMismatch { peer: PeerId, version: u16 },
+    TimedOut,
+    /// More players than the match has room for introduced themselves
+    RoomFull,
+}
+
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::VersionMismatch { peer, version } => write!(
+                f,
+                "peer {peer} speaks handshake v{version}, we speak v{HANDSHAKE_VERSION}"
+            ),
+            HandshakeError::TimedOut => write!(f, "timed out waiting for opponent info"),
+            HandshakeError::RoomFull => write!(f, "the room already has enough players"),
+        }
+    }
+}
+
+/// Progress of the handshake for the current matchmaking attempt
+#[derive(Resource)]
+pub struct HandshakeState {
+    pub sent_to: Vec<PeerId>,
+    pub received: HashMap<PeerId, HandshakeMessage>,
+    pub timer: Timer,
+    /// Measured round-trip times per peer, oldest first
+    pub rtt_samples: HashMap<PeerId, Vec<Duration>>,
+    pub last_ping: Option<Duration>,
+    pub rtt_probe_timer: Timer,
+}
+
+impl Default for HandshakeState {
+    fn default() -> Self {
+        Self {
+            sent_to: Vec::new(),
+            received: HashMap::default(),
+            timer: Timer::from_seconds(HANDSHAKE_TIMEOUT_SECS, TimerMode::Once),
+            rtt_samples: HashMap::default(),
+            last_ping: None,
+            rtt_probe_timer: Timer::from_seconds(RTT_PROBE_TIMEOUT_SECS, TimerMode::Once),
+        }
+    }
+}
+
+impl HandshakeState {
+    /// Sends our info to any peer that hasn't had it yet and collects replies, pinging
+    /// everyone along the way; `now` is any clock that keeps running while we wait
+    pub fn exchange(
+        &mut self,
+        socket: &mut MatchboxSocket,
+        local: &HandshakeMessage,
+        peers: &[PeerId],
+        now: Duration,
+    ) -> Result<(), HandshakeError> {
+        let Ok(channel) = socket.get_channel_mut(HANDSHAKE_CHANNEL) else {
+            return Ok(());
+        };
+
+        for peer in peers {
+            if self.sent_to.contains(peer) {
+                continue;
+            }
+            match serde_json::to_vec(local) {
+                Ok(serialized) => {
+                    channel.send(serialized.into_boxed_slice(), *peer);
+                    self.sent_to.push(*peer);
+                }
+                Err(e) => error!("Failed to serialize handshake: {e}"),
+            }
+        }
+
+        if self.last_ping.is_none_or(|last| now >= last + PING_INTERVAL) {
+            self.last_ping = Some(now);
+            let ping = serde_json::to_vec(&RttProbe::Ping(now)).expect("probes always serialize");
+            for peer in peers {
+                channel.send(ping.clone().into_boxed_slice(), *peer);
+            }
+        }
+
+        for (peer, packet) in channel.receive() {
+            if let Ok(probe) = serde_json::from_slice::<RttProbe>(&packet) {
+                match probe {
+                    RttProbe::Ping(sent_at) => {
+                        let pong = serde_json::to_vec(&RttProbe::Pong(sent_at)).expect("probes always serialize");
+                        channel.send(pong.into_boxed_slice(), peer);
+                    }
+                    RttProbe::Pong(sent_at) => {
+                        self.rtt_samples.entry(peer).or_default().push(now.saturating_sub(sent_at));
+                    }
+                }
+                continue;
+            }
+            match serde_json::from_slice::<HandshakeMessage>(&packet) {
+                Ok(msg) => {
+                    if msg.version != HANDSHAKE_VERSION {
+                        return Err(HandshakeError::VersionMismatch {
+                            peer,
+                            version: msg.version,
+                        });
+                    }
+                    if msg.build_hash != local.build_hash {
+                        warn!(
+                            "Peer {peer} runs build {} but we run {} - expect desyncs",
+                            msg.build_hash, local.build_hash
+                        );
+                    }
+                    info!("Handshake from {peer}: {} ({})", msg.display_name, msg.address);
+                    self.received.insert(peer, msg);
+                }
+                Err(e) => warn!("Failed to deserialize handshake from {peer}: {e}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_complete(&self, peers: &[PeerId]) -> bool {
+        peers.iter().all(|peer| self.received.contains_key(peer))
+    }
+
+    /// Advances the timeout; only call this once the room is full
+    pub fn tick_timeout(&mut self, delta: std::time::Duration) -> Result<(), HandshakeError> {
+        self.timer.tick(delta);
+        if self.timer.is_finished() {
+            return Err(HandshakeError::TimedOut);
+        }
+        Ok(())
+    }
+
+    /// Median measured round-trip time to `peer`
+    pub fn rtt_to(&self, peer: &PeerId) -> Option<Duration> {
+        let mut samples = self.rtt_samples.get(peer)?.clone();
+        samples.sort();
+        samples.get(samples.len() / 2).copied()
+    }
+
+    /// Worst median round-trip time among `peers`, if any were measured
+    pub fn worst_rtt(&self, peers: &[PeerId]) -> Option<Duration> {
+        peers.iter().filter_map(|peer| self.rtt_to(peer)).max()
+    }
+
+    /// Whether every peer has been pinged enough, or we've waited long enough; advances the wait
+    pub fn rtt_settled(&mut self, peers: &[PeerId], delta: Duration) -> bool {
+        let measured = peers
+            .iter()
+            .all(|peer| self.rtt_samples.get(peer).is_some_and(|samples| samples.len() >= RTT_SAMPLES));
+        if measured {
+            return true;
+        }
+        self.rtt_probe_timer.tick(delta);
+        if self.rtt_probe_timer.is_finished() {
+            warn!("Starting without {RTT_SAMPLES} round trips to every peer");
+            return true;
+        }
+        false
+    }
+
+    /// Peers that introduced themselves, sorted so every peer derives the same handles
+    pub fn players_among(&self, peers: &[PeerId]) -> Vec<PeerId> {
+        let mut players: Vec<PeerId> = peers
+            .iter()
+            .copied()
+            .filter(|peer| self.received.contains_key(peer))
+            .collect();
+        players.sort();
+        players
+    }
+
+    pub fn display_name(&self, peer: &PeerId) -> Option<&str> {
+        self.received.get(peer).map(|msg| msg.display_name.as_str())
+    }
+}
@@ -1,155 +1,384 @@
-// matchmaking.rs - CORRECT VERSION (no dual channels, keep original)
-use bevy::prelude::*;
-use bevy_ggrs::{ggrs::{DesyncDetection, PlayerType}, *};
+// matchmaking.rs - GGRS on channel 0, handshake on channel 1
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_ggrs::{ggrs::{DesyncDetection, Message, NonBlockingSocket, PlayerType}, *};
 use bevy_matchbox::prelude::*;
 use rand::{rng, Rng};
 use crate::{
     core::args::Args,
+    core::constants::{DEFAULT_INPUT_DELAY, DESYNC_CHECK_INTERVAL, ROLLBACK_FPS},
     Config,
     core::states::GameState,
-    core::resources::{SessionSeed, PlayerAddressMapping},
+    game::bot::{BotBrain, BOT_HANDLE},
+    game::round::reset_round_state,
+    core::resources::{BotOpponent, CouchMatch, InputDelay, InputDelayTable, SessionSeed, PlayerAddressMapping, MatchRules, MatchRoom, RatingBucket, ServerSettings, WatchRequest, WatchedMatch},
+    network::conditions::{ConditionedInputs, ConditionedSocket, NetworkConditions},
+    network::handshake::{HandshakeError, HandshakeMessage, HandshakeState, GGRS_CHANNEL},
+    network::rating::{PlayerRating, RatedQueue},
+    network::replay::ReplayRecorder,
+    network::session::{disconnect_notify_delay, disconnect_timeout},
     ui::auth::system::WalletInfo,
-    ui::lobby::PlayerProfile,
+    ui::inventory::InventorySystem,
+    ui::lobby::{PlayerProfile, LobbyNotifications},
 };
 
-pub fn synctest_mode(args: Res<Args>) -> bool {
-    args.synctest
+/// Who the next match is played against when nobody needs to be matched with
+#[derive(SystemParam)]
+pub struct LocalOpponents<'w> {
+    pub bot: Res<'w, BotOpponent>,
+    pub couch: Res<'w, CouchMatch>,
 }
 
-pub fn p2p_mode(args: Res<Args>) -> bool {
-    !args.synctest
+impl LocalOpponents<'_> {
+    pub fn any(&self) -> bool {
+        self.bot.0.is_some() || self.couch.0
+    }
+}
+
+/// A session on this machine only: `--synctest`, a couch match, or a match against the bot
+pub fn local_mode(args: Res<Args>, opponents: LocalOpponents) -> bool {
+    args.replay.is_none() && (args.synctest || opponents.any())
+}
+
+/// A match with players on other machines, found through the matchbox server
+pub fn p2p_mode(args: Res<Args>, opponents: LocalOpponents) -> bool {
+    args.replay.is_none() && !args.synctest && !opponents.any()
+}
+
+/// Letters and digits that can't be confused with each other when read aloud or typed
+const ROOM_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+pub const ROOM_CODE_LEN: usize = 6;
+
+pub fn generate_room_code() -> String {
+    let mut rng = rng();
+    (0..ROOM_CODE_LEN)
+        .map(|_| ROOM_CODE_ALPHABET[rng.random_range(0..ROOM_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// The same code on every peer that shares `seed`
+pub fn room_code_from_seed(seed: u64) -> String {
+    (0..ROOM_CODE_LEN)
+        .map(|i| ROOM_CODE_ALPHABET[(seed >> (i * 5)) as usize % ROOM_CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+/// Upper-cases and trims a typed code, rejecting anything `generate_room_code` couldn't produce
+pub fn parse_room_code(input: &str) -> Option<String> {
+    let code = input.trim().to_ascii_uppercase();
+    let valid = code.len() == ROOM_CODE_LEN
+        && code.bytes().all(|c| ROOM_CODE_ALPHABET.contains(&c));
+    valid.then_some(code)
 }
 
-pub fn start_matchbox_socket(mut commands: Commands) {
-    // Use public matchbox demo server for P2P matchmaking
-    // For production, you should deploy your own matchbox server at match.nft.cx
-    let room_url = "wss://match.helsing.studio/dagknights?next=2";
+pub fn start_matchbox_socket(
+    mut commands: Commands,
+    rules: Res<MatchRules>,
+    servers: Res<ServerSettings>,
+    mut room: ResMut<MatchRoom>,
+    rating: Res<PlayerRating>,
+) {
+    // Rated players start in their narrowest bucket; `widen_rating_bucket` takes it from there
+    commands.remove_resource::<RatedQueue>();
+    if *room == MatchRoom::Public {
+        if let Some(bucket) = rating.rating.and_then(|r| RatingBucket::for_rating(r, 0)) {
+            *room = MatchRoom::Rated(bucket);
+            commands.insert_resource(RatedQueue::new(rating.rating.unwrap_or_default()));
+        }
+    }
+
+    open_matchbox_socket(&mut commands, servers.matchmaking_url(&rules, &room));
+}
+
+/// (Re)connects to `room_url`, replacing any previous socket and handshake progress
+pub fn open_matchbox_socket(commands: &mut Commands, room_url: String) {
     info!("connecting to matchbox server: {room_url}");
-    commands.insert_resource(MatchboxSocket::new_unreliable(room_url));
+
+    // GGRS gets the unreliable channel, the handshake rides on a reliable one
+    let socket: MatchboxSocket = WebRtcSocketBuilder::new(room_url)
+        .add_channel(ChannelConfig::unreliable())
+        .add_channel(ChannelConfig::reliable())
+        .into();
+    commands.insert_resource(socket);
+    commands.insert_resource(HandshakeState::default());
+}
+
+/// Moves matchmaking on to the match, or back to the lobby with the reason
+#[derive(SystemParam)]
+pub struct MatchmakingControl<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub next_state: ResMut<'w, NextState<GameState>>,
+    notifications: ResMut<'w, LobbyNotifications>,
+    pub time: Res<'w, Time>,
+}
+
+impl MatchmakingControl<'_, '_> {
+    pub fn abort(&mut self, error: HandshakeError) {
+        warn!("Handshake failed, returning to lobby: {error}");
+        self.notifications
+            .add(format!("Matchmaking failed: {error}"), self.time.elapsed_secs_f64());
+        self.commands.remove_resource::<MatchboxSocket>();
+        self.commands.remove_resource::<HandshakeState>();
+        self.commands.remove_resource::<RatedQueue>();
+        self.next_state.set(GameState::Lobby);
+    }
+}
+
+/// What this player tells the others in the handshake
+#[derive(SystemParam)]
+pub struct LocalIdentity<'w> {
+    wallet_info: Res<'w, WalletInfo>,
+    profile: Res<'w, PlayerProfile>,
+    inventory: Res<'w, InventorySystem>,
+    watch_request: Res<'w, WatchRequest>,
+}
+
+impl LocalIdentity<'_> {
+    fn handshake_message(&self, local_peer_id: PeerId) -> HandshakeMessage {
+        let local_address = if self.wallet_info.connected {
+            self.wallet_info.address.clone()
+        } else {
+            format!("guest_{}", local_peer_id.0.as_u128())
+        };
+        HandshakeMessage::new(
+            local_address,
+            self.profile.display_name.clone(),
+            self.inventory.equipped_item_ids(),
+            self.watch_request.0,
+        )
+    }
+}
+
+/// How the session is set up once everyone is in
+#[derive(SystemParam)]
+pub struct SessionOptions<'w> {
+    args: Res<'w, Args>,
+    rules: Res<'w, MatchRules>,
+    input_delay: Res<'w, InputDelay>,
+    input_delay_table: Res<'w, InputDelayTable>,
 }
 
 pub fn wait_for_players(
-    mut commands: Commands,
+    mut control: MatchmakingControl,
     mut socket: ResMut<MatchboxSocket>,
-    mut next_state: ResMut<NextState<GameState>>,
-    args: Res<Args>,
-    wallet_info: Res<WalletInfo>,
-    profile: Res<PlayerProfile>,
+    mut handshake: ResMut<HandshakeState>,
+    identity: LocalIdentity,
+    options: SessionOptions,
+    mut recorder: ResMut<ReplayRecorder>,
 ) {
-    if socket.get_channel(0).is_err() {
+    if socket.get_channel(GGRS_CHANNEL).is_err() {
         return;
     }
 
     socket.update_peers();
-    let players = socket.players();
+    let Some(local_peer_id) = socket.id() else {
+        // still waiting for the signalling server to assign us an id
+        return;
+    };
+    let local_info = identity.handshake_message(local_peer_id);
+    let SessionOptions { args, rules, input_delay, input_delay_table } = options;
+
+    let remote_peers: Vec<PeerId> = socket.connected_peers().collect();
+
+    // Swap addresses, names and loadouts as peers arrive, so the room list can show them
+    let mut result = handshake.exchange(&mut socket, &local_info, &remote_peers, control.time.elapsed());
 
-    let num_players = 2;
-    if players.len() < num_players {
+    let num_players = rules.num_players;
+    if remote_peers.len() + 1 < num_players {
+        if let Err(e) = result {
+            control.abort(e);
+        }
         return;
     }
 
-    info!("All peers have joined, going in-game");
-
-    let id = socket.id().expect("no peer id assigned").0.as_u64_pair();
-    let mut seed = id.0 ^ id.1;
-    for peer in socket.connected_peers() {
-        let peer_id = peer.0.as_u64_pair();
-        seed ^= peer_id.0 ^ peer_id.1;
-    }
-    commands.insert_resource(SessionSeed(seed));
-
-    // Determine local player handle by finding our peer ID in the players list
-    let local_peer_id = socket.id().expect("no peer id assigned");
-    let mut local_handle = None;
-
-    for (i, player) in players.iter().enumerate() {
-        if let PlayerType::Remote(peer_id) = player {
-            if *peer_id == local_peer_id {
-                local_handle = Some(i);
-                break;
-            }
-        } else if let PlayerType::Local = player {
-            local_handle = Some(i);
-            break;
+    if result.is_ok() && !handshake.is_complete(&remote_peers) {
+        result = handshake.tick_timeout(control.time.delta());
+        if result.is_ok() {
+            return;
         }
     }
 
-    // Create player address mapping with local player's info
-    let mut address_mapping = PlayerAddressMapping::default();
-    if let Some(handle) = local_handle {
-        address_mapping.local_player_handle = Some(handle);
-        let local_address = if wallet_info.connected {
-            Some(wallet_info.address.clone())
-        } else {
-            Some(format!("guest_{}", local_peer_id.0.as_u128()))
-        };
+    let mut player_ids = handshake.players_among(&remote_peers);
+    player_ids.push(local_peer_id);
+    player_ids.sort();
+    if result.is_ok() && player_ids.len() < num_players {
+        return;
+    }
+    if result.is_ok() && player_ids.len() > num_players {
+        result = Err(HandshakeError::RoomFull);
+    }
 
-        // Set local player's address and display name
-        if handle == 0 {
-            address_mapping.player0_address = local_address;
-            address_mapping.player0_display_name = Some(profile.display_name.clone());
-        } else {
-            address_mapping.player1_address = local_address;
-            address_mapping.player1_display_name = Some(profile.display_name.clone());
-        }
+    if let Err(e) = result {
+        control.abort(e);
+        return;
+    }
 
-        info!("Local player assigned handle {} with address {:?} and name '{}'",
-              handle,
-              address_mapping.get_local_address(),
-              profile.display_name);
+    let remote_players: Vec<PeerId> = player_ids.iter().copied().filter(|id| *id != local_peer_id).collect();
+    if !handshake.rtt_settled(&remote_players, control.time.delta()) {
+        return;
     }
 
-    // TODO: Exchange opponent's Kaspa address and display name via custom message protocol
-    // For now, opponent address and name will be unknown
-    commands.insert_resource(address_mapping);
+    info!("All peers have joined, going in-game");
+
+    let worst_rtt = handshake.worst_rtt(&remote_players);
+    let input_delay = match *input_delay {
+        InputDelay::Fixed(frames) => frames,
+        InputDelay::Auto => worst_rtt.map_or(DEFAULT_INPUT_DELAY, |rtt| input_delay_table.delay_for(rtt)),
+    };
+    info!("Using {input_delay} frames of input delay (worst round trip {worst_rtt:?})");
+
+    control.commands.insert_resource(SessionSeed(session_seed(&player_ids)));
+
+    let address_mapping = address_mapping_for(&player_ids, local_peer_id, &local_info, &handshake);
+    let watched = local_info.watchable
+        || remote_players
+            .iter()
+            .filter_map(|peer| handshake.received.get(peer))
+            .any(|info| info.watchable);
+    control.commands.insert_resource(WatchedMatch(watched));
+
+    info!("Local player assigned handle {:?} with address {:?} and name {:?}",
+          address_mapping.local_player_handle,
+          address_mapping.get_local_address(),
+          address_mapping.get_local_display_name());
+    for (handle, info) in address_mapping.players.iter().enumerate() {
+        info!("Player {handle}: {:?} ({:?}) equipped with {:?}", info.display_name, info.address, info.loadout);
+    }
 
     let mut session_builder = ggrs::SessionBuilder::<Config>::new()
         .with_num_players(num_players)
-        .with_desync_detection_mode(DesyncDetection::On { interval: 1 })
-        .with_input_delay(args.input_delay);
+        .with_fps(ROLLBACK_FPS)
+        .expect("invalid rollback fps")
+        .with_desync_detection_mode(DesyncDetection::On { interval: DESYNC_CHECK_INTERVAL })
+        .with_input_delay(input_delay)
+        .with_disconnect_timeout(disconnect_timeout())
+        .with_disconnect_notify_delay(disconnect_notify_delay());
 
-    for (i, player) in players.into_iter().enumerate() {
+    for (handle, peer_id) in player_ids.iter().enumerate() {
+        let player = if *peer_id == local_peer_id {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(*peer_id)
+        };
         session_builder = session_builder
-            .add_player(player, i)
+            .add_player(player, handle)
             .expect("failed to add player");
     }
 
-    let socket = socket.take_channel(0).unwrap();
+    control.commands.insert_resource(address_mapping);
 
-    let ggrs_session = session_builder
-        .start_p2p_session(socket)
-        .expect("failed to start session");
+    let socket = socket.take_channel(GGRS_CHANNEL).unwrap();
+    let conditions = NetworkConditions::from_args(&args);
 
-    commands.insert_resource(bevy_ggrs::Session::P2P(ggrs_session));
-    next_state.set(GameState::InGame);
+    let ggrs_session = if conditions.is_ideal() {
+        session_builder.start_p2p_session(socket)
+    } else {
+        session_builder.start_p2p_session(ConditionedSocket::new(socket, conditions))
+    }
+    .expect("failed to start session");
+
+    reset_round_state(&mut control.commands);
+    recorder.start();
+    control.commands.insert_resource(bevy_ggrs::Session::P2P(ggrs_session));
+    control.next_state.set(GameState::InGame);
+}
+
+/// Every peer derives the same seed from the same set of player ids
+pub fn session_seed(player_ids: &[PeerId]) -> u64 {
+    player_ids.iter().fold(0, |seed, peer_id| {
+        let (high, low) = peer_id.0.as_u64_pair();
+        seed ^ high ^ low
+    })
 }
 
-pub fn start_synctest_session(
+/// Handles follow the order of the sorted `player_ids`, so fill the mapping from it
+pub fn address_mapping_for(
+    player_ids: &[PeerId],
+    local_peer_id: PeerId,
+    local_info: &HandshakeMessage,
+    handshake: &HandshakeState,
+) -> PlayerAddressMapping {
+    let mut address_mapping = PlayerAddressMapping::default();
+    for (handle, peer_id) in player_ids.iter().enumerate() {
+        let info = if *peer_id == local_peer_id {
+            address_mapping.local_player_handle = Some(handle);
+            Some(local_info)
+        } else {
+            handshake.received.get(peer_id)
+        };
+        if let Some(info) = info {
+            address_mapping.set_player(
+                handle,
+                info.address.clone(),
+                info.display_name.clone(),
+                info.loadout.clone(),
+            );
+        }
+    }
+    address_mapping
+}
+
+/// Couch sessions have no remote players, so GGRS never has anything to send or receive
+struct NoRemotes;
+
+impl NonBlockingSocket<PeerId> for NoRemotes {
+    fn send_to(&mut self, _msg: &Message, _addr: &PeerId) {}
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        Vec::new()
+    }
+}
+
+/// Starts a match with every player on this machine. Couch matches run a P2P session of
+/// local players only, so nothing is resimulated; `--synctest` and bot matches run a SyncTest
+/// session that checks every frame for determinism, with `--net-*` applied to the opponents' inputs
+pub fn start_local_session(
     mut commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
     wallet_info: Res<WalletInfo>,
     profile: Res<PlayerProfile>,
+    options: SessionOptions,
+    opponents: LocalOpponents,
+    mut recorder: ResMut<ReplayRecorder>,
 ) {
-    info!("Starting synctest session");
-    let num_players = 2;
+    let couch = opponents.couch.0;
+    info!("Starting {} session", if couch { "couch" } else { "synctest" });
+    let num_players = options.rules.num_players;
 
     // In synctest mode, create mock player addresses and display names
-    let mut address_mapping = PlayerAddressMapping::default();
-    address_mapping.local_player_handle = Some(0);
-    address_mapping.player0_address = Some(
-        if wallet_info.connected {
-            wallet_info.address.clone()
+    let mut address_mapping = PlayerAddressMapping {
+        local_player_handle: Some(0),
+        ..default()
+    };
+    let local_address = if wallet_info.connected {
+        wallet_info.address.clone()
+    } else {
+        "synctest_player0".to_string()
+    };
+    address_mapping.set_player(0, local_address, profile.display_name.clone(), Vec::new());
+    for handle in 1..num_players {
+        // Couch players are real people on the other controllers, numbered the way the pads are
+        let display_name = if couch {
+            format!("Player {}", handle + 1)
         } else {
-            "synctest_player0".to_string()
-        }
-    );
-    address_mapping.player1_address = Some("synctest_player1".to_string());
-    address_mapping.player0_display_name = Some(profile.display_name.clone());
-    address_mapping.player1_display_name = Some("Test Opponent".to_string());
+            format!("Test Opponent {handle}")
+        };
+        address_mapping.set_player(handle, format!("synctest_player{handle}"), display_name, Vec::new());
+    }
+    if let Some(difficulty) = opponents.bot.0 {
+        address_mapping.set_player(
+            BOT_HANDLE,
+            format!("bot_{}", difficulty.label().to_lowercase()),
+            format!("Bot ({})", difficulty.label()),
+            Vec::new(),
+        );
+        commands.insert_resource(BotBrain::default());
+    }
     commands.insert_resource(address_mapping);
 
     let mut session_builder = ggrs::SessionBuilder::<Config>::new()
-        .with_num_players(num_players);
+        .with_num_players(num_players)
+        .with_fps(ROLLBACK_FPS)
+        .expect("invalid rollback fps");
 
     for i in 0..num_players {
         session_builder = session_builder
@@ -157,11 +386,27 @@ pub fn start_synctest_session(
             .expect("failed to add player");
     }
 
-    let ggrs_session = session_builder
-        .start_synctest_session()
-        .expect("failed to start session");
+    let conditions = NetworkConditions::from_args(&options.args);
+    if couch || conditions.is_ideal() {
+        commands.remove_resource::<ConditionedInputs>();
+    } else {
+        commands.insert_resource(ConditionedInputs::new(conditions, 0));
+    }
 
-    commands.insert_resource(bevy_ggrs::Session::SyncTest(ggrs_session));
+    let session = if couch {
+        session_builder
+            .start_p2p_session(NoRemotes)
+            .map(bevy_ggrs::Session::P2P)
+    } else {
+        session_builder
+            .start_synctest_session()
+            .map(bevy_ggrs::Session::SyncTest)
+    }
+    .expect("failed to start session");
+
+    reset_round_state(&mut commands);
+    recorder.start();
+    commands.insert_resource(session);
     commands.insert_resource(SessionSeed(rng().random()));
     next_state.set(GameState::InGame);
-}
\ No newline at end of file
+}
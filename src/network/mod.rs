@@ -1,7 +1,17 @@
 // network/mod.rs - Networking and multiplayer systems
 
 pub mod matchmaking;
+pub mod handshake;
 pub mod session;
+pub mod rating;
+pub mod desync;
+pub mod replay;
+pub mod spectator;
+pub mod watch;
+pub mod conditions;
+pub mod stats;
+#[cfg(feature = "validator")]
+pub mod validator;
 
 // Re-export commonly used items
 pub use matchmaking::*;
@@ -0,0 +1,198 @@
+// rating.rs - Elo rating lookup and rating-bucketed public queue
+use bevy::prelude::*;
+use bevy_matchbox::prelude::*;
+use crate::{
+    core::resources::{MatchRoom, MatchRules, RatingBucket, ServerSettings},
+    network::matchmaking::open_matchbox_socket,
+    ui::auth::system::WalletInfo,
+};
+
+/// Seconds spent alone in a rating bucket before the search widens
+pub const QUEUE_WIDEN_SECS: f32 = 20.0;
+
+/// Local player's matchmaking rating, fetched from the stats API for connected wallets
+#[derive(Resource, Default, Clone, Debug)]
+pub struct PlayerRating {
+    pub rating: Option<u32>,
+    pub rated_games: u32,
+    pub is_loading: bool,
+    /// Address the current rating belongs to, so a wallet switch triggers a refetch
+    pub fetched_for: Option<String>,
+}
+
+impl PlayerRating {
+    pub fn force_refresh(&mut self) {
+        self.fetched_for = None;
+        self.is_loading = false;
+    }
+}
+
+/// Tracks how far the current public search has widened
+#[derive(Resource)]
+pub struct RatedQueue {
+    pub rating: u32,
+    pub tier: usize,
+    pub timer: Timer,
+}
+
+impl RatedQueue {
+    pub fn new(rating: u32) -> Self {
+        Self {
+            rating,
+            tier: 0,
+            timer: Timer::from_seconds(QUEUE_WIDEN_SECS, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Refetch on every lobby entry so the rating reflects the match just played
+pub fn refresh_player_rating(mut rating: ResMut<PlayerRating>) {
+    rating.force_refresh();
+}
+
+#[cfg(target_arch = "wasm32")]
+#[derive(serde::Deserialize)]
+struct RatingResponse {
+    rating: u32,
+    rated_games: u32,
+}
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static PENDING_RATING: std::cell::RefCell<Option<(String, Result<RatingResponse, String>)>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Fetch the local player's rating from the API
+#[cfg(target_arch = "wasm32")]
+pub fn fetch_player_rating(
+    mut rating: ResMut<PlayerRating>,
+    wallet_info: Res<WalletInfo>,
+) {
+    use wasm_bindgen_futures::spawn_local;
+
+    let pending_result = PENDING_RATING.with(|p| p.borrow_mut().take());
+    if let Some((address, result)) = pending_result {
+        rating.is_loading = false;
+        rating.fetched_for = Some(address);
+        match result {
+            Ok(response) => {
+                info!("Matchmaking rating: {} ({} rated games)", response.rating, response.rated_games);
+                rating.rating = Some(response.rating);
+                rating.rated_games = response.rated_games;
+            }
+            Err(e) => {
+                // Without a rating we simply queue in the open room
+                rating.rating = None;
+                error!("Failed to fetch rating: {}", e);
+            }
+        }
+        return;
+    }
+
+    // Guests have no stats history to rate
+    if !wallet_info.connected {
+        rating.rating = None;
+        return;
+    }
+
+    if !rating.is_loading && rating.fetched_for.as_ref() != Some(&wallet_info.address) {
+        rating.is_loading = true;
+        let address = wallet_info.address.clone();
+
+        spawn_local(async move {
+            let result = fetch_rating_async(&address).await;
+            PENDING_RATING.with(|p| {
+                *p.borrow_mut() = Some((address, result));
+            });
+        });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn fetch_player_rating(
+    mut rating: ResMut<PlayerRating>,
+    wallet_info: Res<WalletInfo>,
+) {
+    // Desktop mode: no stats API, always use the open room
+    if rating.fetched_for.as_ref() != Some(&wallet_info.address) {
+        rating.rating = None;
+        rating.fetched_for = Some(wallet_info.address.clone());
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_rating_async(address: &str) -> Result<RatingResponse, String> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{Request, RequestInit, RequestMode, Response};
+
+    let api_url = crate::core::constants::api_url(&format!(
+        "/api/rating/{}",
+        String::from(js_sys::encode_uri_component(address))
+    ));
+
+    let opts = RequestInit::new();
+    opts.set_method("GET");
+    opts.set_mode(RequestMode::Cors);
+
+    let request = Request::new_with_str_and_init(&api_url, &opts)
+        .map_err(|e| format!("Request creation error: {:?}", e))?;
+
+    let window = web_sys::window().ok_or("No window object")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request))
+        .await
+        .map_err(|e| format!("Fetch error: {:?}", e))?;
+
+    let resp: Response = resp_value.dyn_into()
+        .map_err(|_| "Response cast error")?;
+
+    if !resp.ok() {
+        return Err(format!("HTTP error: {}", resp.status()));
+    }
+
+    let json = JsFuture::from(resp.json().map_err(|e| format!("JSON parse error: {:?}", e))?)
+        .await
+        .map_err(|e| format!("JSON await error: {:?}", e))?;
+
+    let json_str = js_sys::JSON::stringify(&json)
+        .map_err(|_| "Stringify error")?
+        .as_string()
+        .ok_or("String conversion error")?;
+
+    serde_json::from_str(&json_str)
+        .map_err(|e| format!("Deserialization error: {}", e))
+}
+
+/// Moves a lonely rated search into the next wider bucket, and finally into the open room
+pub fn widen_rating_bucket(
+    mut commands: Commands,
+    mut queue: ResMut<RatedQueue>,
+    mut room: ResMut<MatchRoom>,
+    socket: Res<MatchboxSocket>,
+    rules: Res<MatchRules>,
+    servers: Res<ServerSettings>,
+    time: Res<Time>,
+) {
+    // Someone already joined this bucket - stay put and let the handshake run
+    if socket.connected_peers().next().is_some() {
+        queue.timer.reset();
+        return;
+    }
+
+    queue.timer.tick(time.delta());
+    if !queue.timer.just_finished() {
+        return;
+    }
+
+    queue.tier += 1;
+    *room = match RatingBucket::for_rating(queue.rating, queue.tier) {
+        Some(bucket) => MatchRoom::Rated(bucket),
+        None => {
+            commands.remove_resource::<RatedQueue>();
+            MatchRoom::Public
+        }
+    };
+    info!("No opponents found, widening search to {:?}", *room);
+
+    open_matchbox_socket(&mut commands, servers.matchmaking_url(&rules, &room));
+}
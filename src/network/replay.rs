@@ -0,0 +1,516 @@
+// replay.rs - Match recording and `--replay` playback through the regular rollback systems
+use std::cell::RefCell;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_ggrs::{ggrs::PlayerType, *};
+use serde::{Deserialize, Serialize};
+use crate::{
+    Config,
+    core::args::{Args, GameMode},
+    core::constants::{MAX_PLAYERS, ROLLBACK_FPS},
+    core::resources::{MatchRules, PlayerAddressMapping, SessionSeed},
+    core::states::GameState,
+    game::input::PlayerInput,
+    game::round::reset_round_state,
+    ui::lobby::LobbyNotifications,
+    utils::helpers::save_json_file,
+};
+
+/// Bumped whenever the simulation changes in a way that makes old input streams play out differently.
+/// 3: combat, stamina, sprint and aim have all changed since the v1 format; replays now hold
+/// confirmed frames only
+pub const REPLAY_VERSION: u32 = 3;
+
+/// Playback speeds offered by the controls
+pub const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Fastest relative speed used to fast-forward towards a seek target
+const SEEK_SPEED: f32 = 32.0;
+
+/// Frames skipped by the rewind/forward buttons
+const SKIP_FRAMES: i32 = 5 * ROLLBACK_FPS as i32;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ReplayPlayer {
+    pub address: Option<String>,
+    pub display_name: Option<String>,
+    pub loadout: Vec<String>,
+}
+
+impl ReplayPlayer {
+    /// Every handle's entry in `address_mapping`, in handle order
+    pub fn all_from(address_mapping: &PlayerAddressMapping, num_players: usize) -> Vec<Self> {
+        (0..num_players)
+            .map(|handle| {
+                let info = address_mapping.players.get(handle).cloned().unwrap_or_default();
+                ReplayPlayer {
+                    address: info.address,
+                    display_name: info.display_name,
+                    loadout: info.loadout,
+                }
+            })
+            .collect()
+    }
+
+    /// The mapping `all_from` was built from, seen from `local_player_handle`
+    pub fn address_mapping(players: &[Self], local_player_handle: Option<usize>) -> PlayerAddressMapping {
+        let mut address_mapping = PlayerAddressMapping {
+            local_player_handle,
+            ..default()
+        };
+        for (handle, player) in players.iter().enumerate() {
+            address_mapping.set_player(
+                handle,
+                player.address.clone().unwrap_or_default(),
+                player.display_name.clone().unwrap_or_default(),
+                player.loadout.clone(),
+            );
+        }
+        address_mapping
+    }
+}
+
+/// Everything needed to re-simulate a match: its seed, its players and every frame's input
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replay {
+    pub version: u32,
+    pub session_seed: u64,
+    pub num_players: usize,
+    pub mode: GameMode,
+    /// Handle of the player who recorded the match
+    pub recorded_by: Option<usize>,
+    pub players: Vec<ReplayPlayer>,
+    /// Confirmed input per player handle, one entry per GGRS frame starting at frame 1
+    pub inputs: Vec<Vec<PlayerInput>>,
+}
+
+impl Replay {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let replay: Replay = serde_json::from_str(json).map_err(|e| format!("Invalid replay: {e}"))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "Replay version {} is not supported (expected {REPLAY_VERSION})",
+                replay.version
+            ));
+        }
+        if !(2..=MAX_PLAYERS).contains(&replay.num_players) {
+            return Err(format!("Replay has {} players", replay.num_players));
+        }
+        if let Some(frame) = replay.inputs.iter().position(|row| row.len() != replay.num_players) {
+            return Err(format!("Replay input for frame {} has the wrong player count", frame + 1));
+        }
+        Ok(replay)
+    }
+
+    pub fn rules(&self) -> MatchRules {
+        MatchRules {
+            num_players: self.num_players,
+            mode: self.mode,
+        }
+    }
+
+    pub fn frame_count(&self) -> i32 {
+        self.inputs.len() as i32
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("replay_{:x}.json", self.session_seed)
+    }
+
+    /// Writes the replay to disk, or downloads it in the browser; returns the file name
+    pub fn save(&self) -> Result<String, String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let file_name = self.file_name();
+        save_json_file(&file_name, &json)?;
+        Ok(file_name)
+    }
+}
+
+/// Inputs of the match in progress; not a rollback resource, resimulated frames overwrite their predictions
+#[derive(Resource, Default)]
+pub struct ReplayRecorder {
+    pub recording: bool,
+    pub inputs: Vec<Vec<PlayerInput>>,
+    /// Leading frames of `inputs` GGRS will never roll back; the rest may still hold predictions
+    pub confirmed: usize,
+    /// The last finished match, ready to be saved from the end screen
+    pub finished: Option<Replay>,
+}
+
+impl ReplayRecorder {
+    /// Starts a fresh recording; call together with `reset_round_state` when a session starts
+    pub fn start(&mut self) {
+        *self = Self {
+            recording: true,
+            ..default()
+        };
+    }
+
+    pub fn record(&mut self, frame: i32, inputs: Vec<PlayerInput>) {
+        // After a rollback GGRS resimulates from the first mispredicted frame, so anything
+        // from here on was a prediction and gets replaced by what actually happened
+        let index = (frame - 1).max(0) as usize;
+        self.inputs.resize(index, vec![PlayerInput::default(); inputs.len()]);
+        self.inputs.push(inputs);
+    }
+
+    /// Inputs of every frame that can no longer change
+    pub fn confirmed_inputs(&self) -> &[Vec<PlayerInput>] {
+        &self.inputs[..self.confirmed.min(self.inputs.len())]
+    }
+}
+
+pub fn replay_mode(args: Res<Args>) -> bool {
+    args.replay.is_some()
+}
+
+/// Stores each simulated frame's inputs while a match is being recorded
+pub fn record_replay_inputs(
+    mut recorder: ResMut<ReplayRecorder>,
+    inputs: Res<PlayerInputs<Config>>,
+    frame: Res<RollbackFrameCount>,
+) {
+    if !recorder.recording {
+        return;
+    }
+    recorder.record(frame.0, inputs.iter().map(|(input, _)| *input).collect());
+}
+
+/// Marks the recorded frames GGRS has received every input for; runs after the GGRS schedule
+pub fn confirm_replay_inputs(
+    mut recorder: ResMut<ReplayRecorder>,
+    session: Option<Res<Session<Config>>>,
+    confirmed_frame: Res<ConfirmedFrameCount>,
+) {
+    let simulated = recorder.inputs.len();
+    recorder.confirmed = match session.as_deref() {
+        // GGRS frame `n` is recorded as row `n`, so confirming it settles `n + 1` rows
+        Some(Session::P2P(_)) => usize::try_from(i32::from(*confirmed_frame) + 1).unwrap_or(0).min(simulated),
+        // Every other session only ever simulates inputs it already has
+        _ => simulated,
+    };
+}
+
+/// Freezes the recording when the match ends so the end screen can offer it for download
+pub fn finish_replay_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    session_seed: Res<SessionSeed>,
+    rules: Res<MatchRules>,
+    address_mapping: Res<PlayerAddressMapping>,
+) {
+    if !recorder.recording {
+        return;
+    }
+    recorder.recording = false;
+
+    let players = ReplayPlayer::all_from(&address_mapping, rules.num_players);
+
+    let replay = Replay {
+        version: REPLAY_VERSION,
+        session_seed: session_seed.0,
+        num_players: rules.num_players,
+        mode: rules.mode,
+        recorded_by: address_mapping.local_player_handle,
+        players,
+        inputs: recorder.confirmed_inputs().to_vec(),
+    };
+    recorder.inputs.clear();
+    info!("Recorded {} frames for replay {}", replay.frame_count(), replay.file_name());
+    recorder.finished = Some(replay);
+}
+
+/// The replay being watched, plus the state of its playback controls
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub paused: bool,
+    pub speed: f32,
+    /// Frame to fast-forward (or rewind and fast-forward) to
+    pub seek_target: Option<i32>,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            paused: false,
+            speed: 1.0,
+            seek_target: None,
+        }
+    }
+
+    pub fn seek(&mut self, frame: i32) {
+        self.seek_target = Some(frame.clamp(0, self.replay.frame_count()));
+    }
+}
+
+thread_local! {
+    static PENDING_REPLAY: RefCell<Option<Result<String, String>>> = const { RefCell::new(None) };
+}
+
+/// Loads the `--replay` file (or URL on the web) once, then keeps it for every playthrough
+pub fn load_replay(
+    mut commands: Commands,
+    mut requested: Local<bool>,
+    mut notifications: ResMut<LobbyNotifications>,
+    args: Res<Args>,
+    time: Res<Time>,
+) {
+    let Some(source) = args.replay.clone() else {
+        return;
+    };
+
+    if !*requested {
+        *requested = true;
+        info!("Loading replay from {source}");
+        request_replay(source);
+    }
+
+    let Some(result) = PENDING_REPLAY.with(|p| p.borrow_mut().take()) else {
+        return;
+    };
+    match result.and_then(|json| Replay::from_json(&json)) {
+        Ok(replay) => {
+            info!(
+                "Loaded replay {} ({} players, {} frames)",
+                replay.file_name(),
+                replay.num_players,
+                replay.frame_count()
+            );
+            commands.insert_resource(ReplayPlayback::new(replay));
+        }
+        Err(e) => {
+            error!("Failed to load replay: {e}");
+            notifications.add(format!("Failed to load replay: {e}"), time.elapsed_secs_f64());
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn request_replay(path: String) {
+    let result = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"));
+    PENDING_REPLAY.with(|p| *p.borrow_mut() = Some(result));
+}
+
+#[cfg(target_arch = "wasm32")]
+fn request_replay(url: String) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = fetch_replay_async(&url).await;
+        PENDING_REPLAY.with(|p| *p.borrow_mut() = Some(result));
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_replay_async(url: &str) -> Result<String, String> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{Request, RequestInit, RequestMode, Response};
+
+    let opts = RequestInit::new();
+    opts.set_method("GET");
+    opts.set_mode(RequestMode::Cors);
+
+    let request = Request::new_with_str_and_init(url, &opts)
+        .map_err(|e| format!("Request creation error: {:?}", e))?;
+
+    let window = web_sys::window().ok_or("No window object")?;
+    let resp_value = JsFuture::from(window.fetch_with_request(&request))
+        .await
+        .map_err(|e| format!("Fetch error: {:?}", e))?;
+
+    let resp: Response = resp_value.dyn_into()
+        .map_err(|_| "Response cast error")?;
+
+    if !resp.ok() {
+        return Err(format!("HTTP error: {}", resp.status()));
+    }
+
+    JsFuture::from(resp.text().map_err(|e| format!("Text error: {:?}", e))?)
+        .await
+        .map_err(|e| format!("Text await error: {:?}", e))?
+        .as_string()
+        .ok_or_else(|| "String conversion error".to_string())
+}
+
+/// Skips the lobby once the replay is loaded; the replay restarts from here after the end screen
+pub fn start_replay(
+    playback: Res<ReplayPlayback>,
+    mut rules: ResMut<MatchRules>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    *rules = playback.replay.rules();
+    next_state.set(GameState::Matchmaking);
+}
+
+/// Same address mapping, seed and rules as the recorded match, every player fed from the file
+pub fn start_replay_session(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let replay = &playback.replay;
+    info!("Starting replay {}", replay.file_name());
+
+    commands.insert_resource(ReplayPlayer::address_mapping(&replay.players, replay.recorded_by));
+    commands.insert_resource(replay.rules());
+    commands.insert_resource(SessionSeed(replay.session_seed));
+
+    start_playback_session(&mut commands, replay.num_players);
+    playback.paused = false;
+    playback.seek_target = None;
+    next_state.set(GameState::InGame);
+}
+
+/// A sync test session without rollback checks, so every frame is simulated exactly once;
+/// replays and spectators feed it inputs that are already final
+pub fn start_playback_session(commands: &mut Commands, num_players: usize) {
+    let mut session_builder = ggrs::SessionBuilder::<Config>::new()
+        .with_num_players(num_players)
+        .with_fps(ROLLBACK_FPS)
+        .expect("invalid rollback fps")
+        .with_check_distance(0);
+
+    for i in 0..num_players {
+        session_builder = session_builder
+            .add_player(PlayerType::Local, i)
+            .expect("failed to add player");
+    }
+
+    let ggrs_session = session_builder
+        .start_synctest_session()
+        .expect("failed to start session");
+
+    reset_round_state(commands);
+    commands.insert_resource(Session::SyncTest(ggrs_session));
+}
+
+/// Feeds the recorded input for the frame about to be simulated; past the end everyone idles
+pub fn feed_replay_inputs(
+    mut commands: Commands,
+    playback: Res<ReplayPlayback>,
+    frame: Res<RollbackFrameCount>,
+    local_players: Res<LocalPlayers>,
+) {
+    let recorded = playback.replay.inputs.get(frame.0.max(0) as usize);
+    let local_inputs = local_players
+        .0
+        .iter()
+        .map(|&handle| (handle, recorded.and_then(|row| row.get(handle)).copied().unwrap_or_default()))
+        .collect();
+
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
+
+/// Applies pause, speed and seeking by driving virtual time, which paces the GGRS schedule
+pub fn drive_replay_playback(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut time: ResMut<Time<Virtual>>,
+    frame: Res<RollbackFrameCount>,
+) {
+    if let Some(target) = playback.seek_target {
+        if target < frame.0 {
+            // Frames can't be unsimulated, so rewinding replays from the start. Hold time
+            // until the new session is in place, or it could overshoot a target of 0 again
+            start_playback_session(&mut commands, playback.replay.num_players);
+            time.pause();
+            return;
+        }
+        if frame.0 < target {
+            time.unpause();
+            time.set_relative_speed(((target - frame.0) as f32).clamp(1.0, SEEK_SPEED));
+            return;
+        }
+        playback.seek_target = None;
+    }
+
+    if frame.0 >= playback.replay.frame_count() {
+        playback.paused = true;
+    }
+
+    time.set_relative_speed(playback.speed);
+    if playback.paused {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+}
+
+/// Leaves virtual time running at normal speed for the end screen and lobby
+pub fn reset_replay_time(mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed(1.0);
+    time.unpause();
+}
+
+pub fn replay_controls_ui(
+    mut contexts: EguiContexts,
+    mut playback: ResMut<ReplayPlayback>,
+    frame: Res<RollbackFrameCount>,
+) {
+    let ctx = match contexts.ctx_mut() {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    let screen_rect = ctx.viewport_rect();
+    let total = playback.replay.frame_count();
+    let current = playback.seek_target.unwrap_or(frame.0).clamp(0, total);
+
+    egui::Window::new("replay_controls")
+        .title_bar(false)
+        .resizable(false)
+        .fixed_pos([screen_rect.center().x - 260.0, screen_rect.height() - 90.0])
+        .fixed_size([520.0, 60.0])
+        .frame(egui::Frame::default()
+            .fill(egui::Color32::from_rgba_unmultiplied(20, 15, 10, 230))
+            .stroke(egui::Stroke::new(2.0, egui::Color32::from_rgb(80, 60, 40)))
+            .inner_margin(10.0))
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new("REPLAY")
+                    .size(14.0)
+                    .color(egui::Color32::from_rgb(200, 180, 140))
+                    .strong());
+
+                if ui.button("⏪").on_hover_text("Back 5s").clicked() {
+                    playback.seek(current - SKIP_FRAMES);
+                }
+                let play_label = if playback.paused { "▶" } else { "⏸" };
+                if ui.button(play_label).clicked() {
+                    playback.paused = !playback.paused;
+                }
+                if ui.button("⏩").on_hover_text("Forward 5s").clicked() {
+                    playback.seek(current + SKIP_FRAMES);
+                }
+
+                ui.label(format!(
+                    "{} / {}",
+                    format_frame_time(current),
+                    format_frame_time(total)
+                ));
+
+                for speed in REPLAY_SPEEDS {
+                    let selected = (playback.speed - speed).abs() < f32::EPSILON;
+                    if ui.selectable_label(selected, format!("{speed}x")).clicked() {
+                        playback.speed = speed;
+                    }
+                }
+            });
+
+            let mut position = current;
+            let slider = ui.add(egui::Slider::new(&mut position, 0..=total).show_value(false));
+            if slider.drag_stopped() || (slider.changed() && !slider.dragged()) {
+                playback.seek(position);
+            }
+            if playback.seek_target.is_some() {
+                ui.label(egui::RichText::new("Seeking...")
+                    .size(12.0)
+                    .color(egui::Color32::from_rgb(150, 130, 100)));
+            }
+        });
+}
+
+fn format_frame_time(frame: i32) -> String {
+    let secs = frame / ROLLBACK_FPS as i32;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
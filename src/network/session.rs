@@ -1,27 +1,153 @@
-// networking.rs (fixed)
-use bevy::prelude::*;
+// session.rs - GGRS session events, disconnect grace window and forfeits
+use std::time::Duration;
+use bevy::{ecs::system::SystemParam, platform::collections::HashMap, prelude::*};
 use bevy_ggrs::{ggrs::GgrsEvent, Session};
-use crate::Config;
-
-pub fn handle_ggrs_events(mut session: ResMut<Session<Config>>) {
-    if let Session::P2P(s) = session.as_mut() {
-        for event in s.events() {
-            match event {
-                GgrsEvent::Disconnected { .. } | GgrsEvent::NetworkInterrupted { .. } => {
-                    warn!("GGRS event: {event:?}")
+use crate::{
+    Config,
+    core::resources::{MatchRules, PlayerAddressMapping, SessionSeed},
+    core::states::GameState,
+    network::desync::DesyncLog,
+    network::stats::RollbackStats,
+};
+
+/// How long an interrupted peer has to come back before GGRS drops them
+pub const DISCONNECT_GRACE_SECS: u64 = 10;
+
+/// Silence before GGRS reports a peer as interrupted and the countdown appears
+pub const DISCONNECT_NOTIFY_DELAY_MS: u64 = 500;
+
+pub fn disconnect_timeout() -> Duration {
+    Duration::from_secs(DISCONNECT_GRACE_SECS)
+}
+
+pub fn disconnect_notify_delay() -> Duration {
+    Duration::from_millis(DISCONNECT_NOTIFY_DELAY_MS)
+}
+
+/// A P2P session with at least one remote player; couch, synctest, bot, replay and
+/// spectator sessions are all local and never count towards the leaderboard
+pub fn online_session(session: Option<Res<Session<Config>>>) -> bool {
+    matches!(session.as_deref(), Some(Session::P2P(s)) if !s.remote_player_handles().is_empty())
+}
+
+/// Remote players whose connection is interrupted or gone for the current match
+#[derive(Resource, Default, Debug)]
+pub struct PeerConnections {
+    /// Handles inside the grace window, with the time left before they forfeit
+    pub interrupted: HashMap<usize, Timer>,
+    /// Handles that never came back and forfeited the match
+    pub forfeited: Vec<usize>,
+}
+
+impl PeerConnections {
+    pub fn has_forfeit(&self) -> bool {
+        !self.forfeited.is_empty()
+    }
+
+    /// The only team still connected, once every other team has forfeited
+    pub fn surviving_team(&self, rules: &MatchRules) -> Option<usize> {
+        let mut teams = (0..rules.num_players)
+            .filter(|handle| !self.forfeited.contains(handle))
+            .map(|handle| rules.team_of(handle));
+        let team = teams.next()?;
+        teams.all(|other| other == team).then_some(team)
+    }
+
+    /// Shortest remaining grace window, for the on-screen countdown
+    pub fn grace_remaining(&self) -> Option<f32> {
+        self.interrupted
+            .values()
+            .map(|timer| timer.remaining_secs())
+            .min_by(f32::total_cmp)
+    }
+}
+
+/// Where a detected desync is counted and dumped
+#[derive(SystemParam)]
+pub struct DesyncReport<'w> {
+    desync_log: ResMut<'w, DesyncLog>,
+    rollback_stats: ResMut<'w, RollbackStats>,
+    address_mapping: Res<'w, PlayerAddressMapping>,
+    session_seed: Res<'w, SessionSeed>,
+}
+
+impl DesyncReport<'_> {
+    fn record(&mut self, frame: i32, local_checksum: u128, remote_checksum: u128) {
+        self.rollback_stats.desyncs += 1;
+        self.desync_log.dump(
+            frame,
+            self.address_mapping.local_player_handle,
+            local_checksum,
+            remote_checksum,
+            self.session_seed.0,
+        );
+    }
+}
+
+pub fn handle_ggrs_events(
+    mut session: ResMut<Session<Config>>,
+    mut connections: ResMut<PeerConnections>,
+    mut desyncs: DesyncReport,
+    mut next_state: ResMut<NextState<GameState>>,
+    rules: Res<MatchRules>,
+    time: Res<Time>,
+) {
+    for timer in connections.interrupted.values_mut() {
+        timer.tick(time.delta());
+    }
+
+    let Session::P2P(s) = session.as_mut() else {
+        return;
+    };
+
+    let mut forfeited_now = false;
+    for event in s.events().collect::<Vec<_>>() {
+        match event {
+            GgrsEvent::NetworkInterrupted { addr, disconnect_timeout } => {
+                warn!("Connection to {addr} interrupted, {disconnect_timeout}ms until disconnect");
+                let grace = Duration::from_millis(disconnect_timeout as u64);
+                for handle in s.handles_by_address(addr) {
+                    connections
+                        .interrupted
+                        .insert(handle, Timer::new(grace, TimerMode::Once));
                 }
-                GgrsEvent::DesyncDetected {
-                    local_checksum,
-                    remote_checksum,
-                    frame,
-                    ..
-                } => {
-                    error!(
-                        "Desync on frame {frame}. Local checksum: {local_checksum:X}, remote checksum: {remote_checksum:X}"
-                    );
+            }
+            GgrsEvent::NetworkResumed { addr } => {
+                info!("Connection to {addr} resumed");
+                for handle in s.handles_by_address(addr) {
+                    connections.interrupted.remove(&handle);
                 }
-                _ => info!("GGRS event: {event:?}"),
             }
+            GgrsEvent::Disconnected { addr } => {
+                warn!("Peer {addr} disconnected");
+                for handle in s.handles_by_address(addr) {
+                    connections.interrupted.remove(&handle);
+                    if !connections.forfeited.contains(&handle) {
+                        connections.forfeited.push(handle);
+                        forfeited_now = true;
+                    }
+                }
+            }
+            GgrsEvent::DesyncDetected {
+                local_checksum,
+                remote_checksum,
+                frame,
+                ..
+            } => {
+                error!(
+                    "Desync on frame {frame}. Local checksum: {local_checksum:X}, remote checksum: {remote_checksum:X}"
+                );
+                desyncs.record(frame, local_checksum, remote_checksum);
+            }
+            _ => info!("GGRS event: {event:?}"),
+        }
+    }
+
+    // Keep playing while more than one team is still connected
+    if forfeited_now {
+        if let Some(team) = connections.surviving_team(&rules) {
+            info!("Handles {:?} forfeited, team {team} wins", connections.forfeited);
+            next_state.set(GameState::GameEnd);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,259 @@
+// spectator.rs - Watching a match, from any point, through its players' watch relays
+use std::time::Duration;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_ggrs::*;
+use crate::{
+    Config,
+    core::constants::{MAP_SIZE, ROLLBACK_FPS},
+    core::resources::{MatchRole, MatchRoom, MatchRules, ServerSettings, SessionSeed},
+    core::states::GameState,
+    entities::components::Player,
+    game::leaderboard::DecidingFrame,
+    network::replay::{start_playback_session, ReplayPlayer, ReplayRecorder},
+    network::session::PeerConnections,
+    network::watch::{LiveFeed, WatchSocket},
+    ui::lobby::LobbyNotifications,
+};
+
+/// Free camera pan speed, in world units per second
+pub const FREE_CAMERA_SPEED: f32 = 20.0;
+
+/// Same framing as `camera_follow` uses in-game
+const CAMERA_OFFSET: Vec3 = Vec3::new(0.0, 15.0, 15.0);
+
+/// Frames a spectator may trail the players by before fast-forwarding
+const LIVE_DELAY_FRAMES: usize = ROLLBACK_FPS / 2;
+
+/// Fastest relative speed used to catch up with the players
+const CATCH_UP_SPEED: f32 = 8.0;
+
+/// Bevy's default cap on a single virtual time step, restored after spectating
+const DEFAULT_MAX_DELTA: Duration = Duration::from_millis(250);
+
+pub fn spectating(role: Res<MatchRole>) -> bool {
+    *role == MatchRole::Spectator
+}
+
+/// What the spectator camera is looking at
+#[derive(Resource, Debug, Default)]
+pub struct SpectatorCamera {
+    /// Player handle being followed, or `None` for the free camera
+    pub target: Option<usize>,
+    /// Point on the ground the camera is centred on
+    pub focus: Vec3,
+}
+
+impl SpectatorCamera {
+    /// Tab order: each player in turn, then the free camera
+    pub fn cycle(&mut self, num_players: usize) {
+        self.target = match self.target {
+            Some(handle) if handle + 1 < num_players => Some(handle + 1),
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+}
+
+/// Moves the spectator on, or back to the lobby with the reason
+#[derive(SystemParam)]
+pub struct WatchControl<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub next_state: ResMut<'w, NextState<GameState>>,
+    notifications: ResMut<'w, LobbyNotifications>,
+    time: Res<'w, Time>,
+}
+
+impl WatchControl<'_, '_> {
+    pub fn stop(&mut self, reason: &str) {
+        warn!("Spectating ended: {reason}");
+        self.notifications
+            .add(format!("Spectating ended: {reason}"), self.time.elapsed_secs_f64());
+        self.commands.remove_resource::<WatchSocket>();
+        self.commands.remove_resource::<LiveFeed>();
+        self.next_state.set(GameState::Lobby);
+    }
+}
+
+pub fn join_watch_room(mut commands: Commands, servers: Res<ServerSettings>, room: Res<MatchRoom>) {
+    let MatchRoom::Watch(code) = &*room else {
+        warn!("Spectating needs a watch code, not {:?}", *room);
+        return;
+    };
+    commands.insert_resource(WatchSocket::connect(&servers.watch_url(code)));
+    commands.insert_resource(LiveFeed::default());
+}
+
+/// Waits for every player's relay to introduce itself, then replays the match from its first
+/// frame; the relays send every confirmed frame so far, so joining mid-match just catches up
+pub fn wait_for_match(
+    mut socket: ResMut<WatchSocket>,
+    mut feed: ResMut<LiveFeed>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut control: WatchControl,
+) {
+    let hello = match feed.update(&mut socket).and_then(|()| feed.match_info()) {
+        Ok(Some(hello)) => hello,
+        Ok(None) => {
+            let delta = control.time.delta();
+            if feed.join_timer.tick(delta).just_finished() {
+                control.stop("no match is being played under this code");
+            }
+            return;
+        }
+        Err(e) => {
+            control.stop(&e);
+            return;
+        }
+    };
+
+    info!(
+        "Watching match {} ({} players, {} frames played so far)",
+        hello.session_seed,
+        hello.num_players,
+        feed.playable()
+    );
+
+    let commands = &mut control.commands;
+    commands.insert_resource(hello.rules());
+    commands.insert_resource(SessionSeed(hello.session_seed));
+    commands.insert_resource(ReplayPlayer::address_mapping(&hello.players, None));
+    start_playback_session(commands, hello.num_players);
+    recorder.start();
+    commands.insert_resource(SpectatorCamera {
+        target: Some(0),
+        ..default()
+    });
+    control.next_state.set(GameState::InGame);
+}
+
+/// Feeds the relayed input for the frame about to be simulated
+pub fn feed_watch_inputs(
+    mut commands: Commands,
+    feed: Res<LiveFeed>,
+    frame: Res<RollbackFrameCount>,
+    local_players: Res<LocalPlayers>,
+) {
+    // `drive_live_playback` never lets the session run past the rows the feed has
+    let row = feed.row(frame.0.max(0) as usize);
+    if row.is_none() {
+        error!("No relayed input for frame {}", frame.0 + 1);
+    }
+    let local_inputs = local_players
+        .0
+        .iter()
+        .map(|&handle| (handle, row.and_then(|row| row.get(handle)).copied().unwrap_or_default()))
+        .collect();
+
+    commands.insert_resource(LocalInputs::<Config>(local_inputs));
+}
+
+/// Takes in the relays' inputs as they arrive, and ends spectating once they stop without
+/// the simulation reaching a winner
+pub fn follow_live_feed(
+    mut socket: ResMut<WatchSocket>,
+    mut feed: ResMut<LiveFeed>,
+    mut connections: ResMut<PeerConnections>,
+    rules: Res<MatchRules>,
+    deciding: Res<DecidingFrame>,
+    frame: Res<RollbackFrameCount>,
+    mut control: WatchControl,
+) {
+    if let Err(e) = feed.update(&mut socket) {
+        control.stop(&e);
+        return;
+    }
+
+    // Play out every row first; a decided match ends through `check_game_end`
+    if feed.streaming() || (frame.0.max(0) as usize) < feed.playable() || deciding.0.is_some() {
+        return;
+    }
+
+    match feed.forfeits() {
+        Ok(forfeited) => {
+            connections.forfeited = forfeited;
+            if connections.surviving_team(&rules).is_some() {
+                info!("Handles {:?} forfeited", connections.forfeited);
+                control.next_state.set(GameState::GameEnd);
+            } else {
+                control.stop("the match ended without a winner");
+            }
+        }
+        Err(e) => control.stop(&e),
+    }
+}
+
+/// Runs the simulation as far as the feed goes, fast-forwarding when it falls behind. Virtual
+/// time paces the GGRS schedule, so capping each step stops it outrunning the feed
+pub fn drive_live_playback(
+    feed: Res<LiveFeed>,
+    frame: Res<RollbackFrameCount>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    // Keep a frame in hand so rounding in the time step can never run one past the feed
+    let ahead = feed.playable().saturating_sub(frame.0.max(0) as usize + 1);
+    if ahead == 0 {
+        time.pause();
+        return;
+    }
+
+    let speed = (ahead as f32 / LIVE_DELAY_FRAMES as f32).clamp(1.0, CATCH_UP_SPEED);
+    time.set_relative_speed(speed);
+    time.set_max_delta(Duration::from_secs_f64(ahead as f64 / ROLLBACK_FPS as f64 / speed as f64));
+    time.unpause();
+}
+
+/// Leaves virtual time as Bevy set it up, for the end screen and lobby
+pub fn reset_live_time(mut time: ResMut<Time<Virtual>>) {
+    time.set_relative_speed(1.0);
+    time.set_max_delta(DEFAULT_MAX_DELTA);
+    time.unpause();
+}
+
+/// Follows the chosen player (Tab to switch) or pans freely with WASD/arrows
+pub fn spectator_camera(
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    rules: Res<MatchRules>,
+    mut spectator_camera: ResMut<SpectatorCamera>,
+    players: Query<(&Player, &Transform)>,
+    mut cameras: Query<&mut Transform, (With<Camera>, Without<Player>)>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        spectator_camera.cycle(rules.num_players);
+    }
+
+    match spectator_camera.target {
+        Some(handle) => {
+            // Dead players are gone until the next round, so hold on their last position
+            if let Some((_, transform)) = players.iter().find(|(player, _)| player.handle == handle) {
+                spectator_camera.focus = transform.translation.with_y(0.0);
+            }
+        }
+        None => {
+            let mut pan = Vec3::ZERO;
+            if keys.any_pressed([KeyCode::ArrowUp, KeyCode::KeyW]) {
+                pan.z -= 1.0;
+            }
+            if keys.any_pressed([KeyCode::ArrowDown, KeyCode::KeyS]) {
+                pan.z += 1.0;
+            }
+            if keys.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]) {
+                pan.x -= 1.0;
+            }
+            if keys.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD]) {
+                pan.x += 1.0;
+            }
+
+            let half_map = MAP_SIZE as f32 / 2.0;
+            let focus = spectator_camera.focus
+                + pan.normalize_or_zero() * FREE_CAMERA_SPEED * time.delta_secs();
+            spectator_camera.focus = focus.clamp(Vec3::splat(-half_map), Vec3::splat(half_map));
+        }
+    }
+
+    let focus = spectator_camera.focus;
+    for mut camera_transform in &mut cameras {
+        camera_transform.translation = focus + CAMERA_OFFSET;
+        camera_transform.look_at(focus, Vec3::Y);
+    }
+}
@@ -0,0 +1,67 @@
+// stats.rs - Rollback and desync counters for the network stats overlay
+use bevy::prelude::*;
+use bevy_ggrs::{RollbackFrameCount, Session};
+use crate::{Config, core::constants::DESYNC_CHECK_INTERVAL};
+
+/// Counters GGRS doesn't keep itself; deliberately not a rollback resource so rollbacks can't undo them
+#[derive(Resource, Debug)]
+pub struct RollbackStats {
+    /// Furthest frame simulated so far; anything at or below it is a resimulation
+    pub highest_frame: i32,
+    /// Frame simulated last, to spot the jump back that starts a rollback
+    pub last_frame: i32,
+    pub rollbacks: u32,
+    pub resimulated_frames: u32,
+    /// Depth of the most recent rollback, in frames
+    pub last_rollback_depth: i32,
+    pub desyncs: u32,
+    /// Confirmed frames whose checksums GGRS compares with the peers'
+    pub desync_checks: u32,
+}
+
+impl Default for RollbackStats {
+    fn default() -> Self {
+        // Nothing simulated yet, so frame 0 is new rather than a resimulation
+        Self {
+            highest_frame: -1,
+            last_frame: -1,
+            rollbacks: 0,
+            resimulated_frames: 0,
+            last_rollback_depth: 0,
+            desyncs: 0,
+            desync_checks: 0,
+        }
+    }
+}
+
+impl RollbackStats {
+    pub fn desync_checks_passed(&self) -> u32 {
+        self.desync_checks.saturating_sub(self.desyncs)
+    }
+}
+
+/// Runs once per simulated frame, including every frame replayed during a rollback
+pub fn count_rollbacks(mut stats: ResMut<RollbackStats>, frame: Res<RollbackFrameCount>) {
+    let frame = frame.0;
+    if frame <= stats.last_frame {
+        stats.rollbacks += 1;
+        stats.last_rollback_depth = stats.highest_frame - frame + 1;
+    }
+    if frame <= stats.highest_frame {
+        stats.resimulated_frames += 1;
+    } else {
+        stats.highest_frame = frame;
+    }
+    stats.last_frame = frame;
+}
+
+/// Counts the checksum comparisons GGRS has had the chance to make; it only reports
+/// the ones that fail, so every checked frame without a `DesyncDetected` event passed
+pub fn count_desync_checks(session: Res<Session<Config>>, mut stats: ResMut<RollbackStats>) {
+    let Session::P2P(session) = session.as_ref() else {
+        return;
+    };
+    // Frames below the confirmed one have every peer's input, so their checksums are settled
+    let confirmed_frames = session.confirmed_frame().max(0) as u32;
+    stats.desync_checks = confirmed_frames.div_ceil(DESYNC_CHECK_INTERVAL);
+}
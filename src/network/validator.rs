@@ -0,0 +1,175 @@
+// validator.rs - Headless spectator that re-simulates a match and submits the verified result
+use bevy::{app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*, state::app::StatesPlugin};
+use bevy_ggrs::ReadInputs;
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::{
+    ModelAssets,
+    core::args::GameMode,
+    core::constants::{rollback_timestep, API_BASE_URL},
+    core::resources::{MatchRole, MatchRoom, MatchRules, PlayerAddressMapping, Scores, ServerSettings, SessionSeed},
+    core::states::GameState,
+    game::leaderboard::{check_game_end, MatchResult},
+    game::simulation::SimulationPlugin,
+    network::matchmaking::parse_room_code,
+    network::replay::confirm_replay_inputs,
+    network::session::PeerConnections,
+    network::spectator::{drive_live_playback, feed_watch_inputs, follow_live_feed, join_watch_room, wait_for_match},
+    ui::auth::system::WalletInfo,
+    ui::lobby::{LobbyNotifications, PlayerProfile},
+};
+
+/// Header carrying the hex HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "X-Validator-Signature";
+
+/// Only duels are ranked, so that's all the validator watches
+const VALIDATED_RULES: MatchRules = MatchRules { num_players: 2, mode: GameMode::FreeForAll };
+
+#[derive(Parser, Resource, Debug, Clone)]
+#[clap(name = "validator", about = "Spectates a duel and submits its verified result")]
+pub struct ValidatorArgs {
+    /// watch code of the match (a private room's code), shown to its players; join at any point
+    #[clap(long)]
+    pub room: String,
+    /// matchbox signalling server used for matchmaking
+    #[clap(long, env = "MATCHBOX_SERVER", default_value = "wss://match.helsing.studio")]
+    pub matchbox_server: String,
+    /// matchbox room players are matched in
+    #[clap(long, env = "MATCHBOX_ROOM", default_value = "dagknights")]
+    pub matchbox_room: String,
+    /// leaderboard worker the verified result is posted to, at its `/api/stats` endpoint
+    #[clap(long, env = "VALIDATOR_API_URL", default_value = API_BASE_URL)]
+    pub api_url: String,
+    /// shared secret results are signed with; must match the worker's VALIDATOR_SECRET
+    #[clap(long, env = "VALIDATOR_SECRET", hide_env_values = true)]
+    pub secret: String,
+}
+
+/// Watches one match and exits: success once the result is accepted, failure otherwise
+pub fn run_validator() {
+    let args = ValidatorArgs::parse();
+    let Some(code) = parse_room_code(&args.room) else {
+        eprintln!("Invalid room code: {}", args.room);
+        std::process::exit(2);
+    };
+
+    let servers = ServerSettings {
+        matchbox_server: args.matchbox_server.clone(),
+        matchbox_room: args.matchbox_room.clone(),
+        chat_server: String::new(),
+        chat_room: String::new(),
+    };
+
+    let exit = App::new()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(rollback_timestep())),
+            LogPlugin::default(),
+            StatesPlugin,
+            AssetPlugin::default(),
+        ))
+        // The rollback systems spawn meshes and scenes; nothing draws them, but the assets must exist
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .init_asset::<Scene>()
+        .insert_resource(ModelAssets {
+            player_1: Handle::default(),
+            player_2: Handle::default(),
+        })
+        .add_plugins(SimulationPlugin)
+        .insert_state(GameState::Matchmaking)
+        .insert_resource(WalletInfo {
+            address: "validator".to_string(),
+            connected: true,
+        })
+        .insert_resource(PlayerProfile {
+            display_name: "Validator".to_string(),
+            ..default()
+        })
+        .insert_resource(VALIDATED_RULES)
+        .insert_resource(servers)
+        .insert_resource(MatchRoom::Watch(code))
+        .insert_resource(MatchRole::Spectator)
+        .insert_resource(args)
+        .init_resource::<LobbyNotifications>()
+        .init_resource::<PlayerAddressMapping>()
+        .init_resource::<PeerConnections>()
+        .init_resource::<SessionSeed>()
+        .add_systems(OnEnter(GameState::Matchmaking), join_watch_room)
+        .add_systems(ReadInputs, feed_watch_inputs)
+        .add_systems(
+            Update,
+            (
+                wait_for_match.run_if(in_state(GameState::Matchmaking)),
+                (
+                    follow_live_feed,
+                    drive_live_playback,
+                    check_game_end.after(confirm_replay_inputs),
+                )
+                    .run_if(in_state(GameState::InGame)),
+            ),
+        )
+        .add_systems(OnEnter(GameState::GameEnd), submit_verified_result)
+        // Every failure path (no match, relays that disagree, a disputed ending) drops back to the lobby
+        .add_systems(OnEnter(GameState::Lobby), give_up)
+        .run();
+
+    if exit.is_error() {
+        std::process::exit(1);
+    }
+}
+
+/// Posts the re-simulated score, signed so the backend can tell it from a client's self-report.
+/// A forfeit only gets here once both relays agreed on who left, see `LiveFeed::forfeits`
+fn submit_verified_result(
+    args: Res<ValidatorArgs>,
+    scores: Res<Scores>,
+    rules: Res<MatchRules>,
+    session_seed: Res<SessionSeed>,
+    address_mapping: Res<PlayerAddressMapping>,
+    connections: Res<PeerConnections>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let result = if *rules == VALIDATED_RULES {
+        MatchResult::for_duel(&scores, &session_seed, &address_mapping, &connections)
+    } else {
+        Err(format!("only duels are ranked, this was {}", rules.label()))
+    }
+    .and_then(|result| submit_signed(&args, &result));
+
+    match result {
+        Ok(()) => {
+            info!("Submitted verified result {:?} for session {}", *scores, session_seed.0);
+            exit.write(AppExit::Success);
+        }
+        Err(e) => {
+            error!("Failed to submit verified result: {e}");
+            exit.write(AppExit::error());
+        }
+    }
+}
+
+fn give_up(mut exit: MessageWriter<AppExit>) {
+    error!("Stopped watching before the match finished; nothing was submitted");
+    exit.write(AppExit::error());
+}
+
+fn submit_signed(args: &ValidatorArgs, result: &MatchResult) -> Result<(), String> {
+    let body = serde_json::to_string(result).map_err(|e| format!("Serialization error: {e}"))?;
+
+    // Blocks the app, but there is nothing left to simulate once the match is over
+    ureq::post(&format!("{}/api/stats", args.api_url.trim_end_matches('/')))
+        .set("Content-Type", "application/json")
+        .set(SIGNATURE_HEADER, &sign(&args.secret, &body))
+        .send_string(&body)
+        .map_err(|e| format!("HTTP error: {e}"))?;
+    Ok(())
+}
+
+/// Hex HMAC-SHA256 of `body`, matching the worker's check
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
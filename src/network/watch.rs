@@ -0,0 +1,426 @@
+// watch.rs - Every player relays their confirmed inputs, so spectators can join at any point.
+// GGRS spectators have to be added before the session starts, so they can't join a running match
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_matchbox::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{
+    core::args::GameMode,
+    core::resources::{MatchRoom, MatchRules, PlayerAddressMapping, ServerSettings, SessionSeed, WatchedMatch},
+    game::input::PlayerInput,
+    network::matchmaking::room_code_from_seed,
+    network::replay::{ReplayPlayer, ReplayRecorder},
+    network::session::PeerConnections,
+};
+
+/// Bump whenever `WatchMessage` changes shape
+pub const WATCH_VERSION: u16 = 1;
+
+/// How long a spectator waits for every player's relay before giving up
+pub const WATCH_JOIN_TIMEOUT_SECS: f32 = 10.0;
+
+/// Input rows per `WatchMessage::Frames`
+const ROWS_PER_MESSAGE: usize = 300;
+
+/// Messages sent to one spectator per update, so catching up a late joiner is spread out
+const MESSAGES_PER_UPDATE: usize = 8;
+
+/// Code spectators enter to watch a match: a private room's own code, otherwise one derived
+/// from the session seed every player already shares
+pub fn watch_code(room: &MatchRoom, session_seed: u64) -> String {
+    match room {
+        MatchRoom::Private(code) | MatchRoom::Watch(code) => code.clone(),
+        MatchRoom::Public | MatchRoom::Rated(_) => room_code_from_seed(session_seed),
+    }
+}
+
+/// Everything a spectator needs to set up the same match; every player sends their own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WatchHello {
+    pub version: u16,
+    /// Handle of the player sending this
+    pub handle: usize,
+    pub session_seed: u64,
+    pub num_players: usize,
+    pub mode: GameMode,
+    pub players: Vec<ReplayPlayer>,
+}
+
+impl WatchHello {
+    /// Whether two players describe the same match
+    pub fn same_match(&self, other: &WatchHello) -> bool {
+        self.session_seed == other.session_seed
+            && self.num_players == other.num_players
+            && self.mode == other.mode
+            && self.players == other.players
+    }
+
+    pub fn rules(&self) -> MatchRules {
+        MatchRules {
+            num_players: self.num_players,
+            mode: self.mode,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WatchMessage {
+    /// Always sent first
+    Hello(WatchHello),
+    /// Confirmed input of every player, starting at row `start` of `ReplayRecorder::inputs`
+    Frames { start: usize, inputs: Vec<Vec<PlayerInput>> },
+    /// The sender's match is over after `frames` rows; `forfeited` are the handles it saw leave
+    End { frames: usize, forfeited: Vec<usize> },
+}
+
+/// Reliable socket to the match's watch room, kept apart from the GGRS one
+#[derive(Resource)]
+pub struct WatchSocket {
+    socket: MatchboxSocket,
+}
+
+impl WatchSocket {
+    pub fn connect(watch_room_url: &str) -> Self {
+        info!("Connecting to watch room: {watch_room_url}");
+        Self {
+            socket: MatchboxSocket::new_reliable(watch_room_url),
+        }
+    }
+
+    pub fn update_peers(&mut self) -> Vec<(PeerId, PeerState)> {
+        self.socket.update_peers()
+    }
+
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.socket.connected_peers().collect()
+    }
+
+    pub fn send(&mut self, peer: PeerId, message: &WatchMessage) {
+        let Ok(channel) = self.socket.get_channel_mut(0) else {
+            return;
+        };
+        match serde_json::to_vec(message) {
+            Ok(serialized) => channel.send(serialized.into_boxed_slice(), peer),
+            Err(e) => error!("Failed to serialize watch message: {e}"),
+        }
+    }
+
+    pub fn receive(&mut self) -> Vec<(PeerId, WatchMessage)> {
+        let Ok(channel) = self.socket.get_channel_mut(0) else {
+            return Vec::new();
+        };
+        channel
+            .receive()
+            .into_iter()
+            .filter_map(|(peer, packet)| match serde_json::from_slice(&packet) {
+                Ok(message) => Some((peer, message)),
+                Err(e) => {
+                    warn!("Failed to deserialize watch message from {peer}: {e}");
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// A player's side of the watch room: streams the confirmed inputs to every spectator,
+/// from the first frame for anyone joining late
+#[derive(Resource)]
+pub struct WatchRelay {
+    pub code: String,
+    hello: WatchHello,
+    /// Rows already sent to each spectator
+    sent: HashMap<PeerId, usize>,
+    /// Spectators already told the match is over
+    ended: Vec<PeerId>,
+    /// The other players' relays, which have every input already
+    players: Vec<PeerId>,
+    /// Handles that forfeited, once the match is over
+    forfeited: Option<Vec<usize>>,
+}
+
+pub fn watched_match(watched: Res<WatchedMatch>) -> bool {
+    watched.0
+}
+
+pub fn open_watch_relay(
+    mut commands: Commands,
+    servers: Res<ServerSettings>,
+    room: Res<MatchRoom>,
+    rules: Res<MatchRules>,
+    session_seed: Res<SessionSeed>,
+    address_mapping: Res<PlayerAddressMapping>,
+) {
+    let Some(handle) = address_mapping.local_player_handle else {
+        return;
+    };
+    let code = watch_code(&room, session_seed.0);
+    info!("Spectators can watch this match with code {code}");
+
+    commands.insert_resource(WatchSocket::connect(&servers.watch_url(&code)));
+    commands.insert_resource(WatchRelay {
+        code,
+        hello: WatchHello {
+            version: WATCH_VERSION,
+            handle,
+            session_seed: session_seed.0,
+            num_players: rules.num_players,
+            mode: rules.mode,
+            players: ReplayPlayer::all_from(&address_mapping, rules.num_players),
+        },
+        sent: HashMap::default(),
+        ended: Vec::new(),
+        players: Vec::new(),
+        forfeited: None,
+    });
+}
+
+/// Keeps every spectator caught up with the confirmed inputs, through the end screen too
+pub fn serve_watch_relay(
+    mut socket: ResMut<WatchSocket>,
+    mut relay: ResMut<WatchRelay>,
+    recorder: Res<ReplayRecorder>,
+) {
+    for (peer, state) in socket.update_peers() {
+        if state == PeerState::Disconnected {
+            relay.sent.remove(&peer);
+            relay.ended.retain(|ended| *ended != peer);
+        }
+    }
+    for (peer, message) in socket.receive() {
+        if matches!(message, WatchMessage::Hello(_)) && !relay.players.contains(&peer) {
+            relay.players.push(peer);
+        }
+    }
+
+    // The recording is cleared when the match ends, but the finished replay keeps every row
+    let rows = match &recorder.finished {
+        Some(replay) => replay.inputs.as_slice(),
+        None => recorder.confirmed_inputs(),
+    };
+
+    for peer in socket.connected_peers() {
+        if relay.players.contains(&peer) {
+            continue;
+        }
+        let mut sent = match relay.sent.get(&peer) {
+            Some(&sent) => sent,
+            None => {
+                socket.send(peer, &WatchMessage::Hello(relay.hello.clone()));
+                0
+            }
+        };
+        for _ in 0..MESSAGES_PER_UPDATE {
+            if sent >= rows.len() {
+                break;
+            }
+            let end = (sent + ROWS_PER_MESSAGE).min(rows.len());
+            socket.send(peer, &WatchMessage::Frames { start: sent, inputs: rows[sent..end].to_vec() });
+            sent = end;
+        }
+        relay.sent.insert(peer, sent);
+
+        if let Some(forfeited) = relay.forfeited.clone() {
+            if sent == rows.len() && !relay.ended.contains(&peer) {
+                socket.send(peer, &WatchMessage::End { frames: rows.len(), forfeited });
+                relay.ended.push(peer);
+            }
+        }
+    }
+}
+
+/// Tells spectators the match is over once they have every row
+pub fn finish_watch_relay(mut relay: ResMut<WatchRelay>, connections: Res<PeerConnections>) {
+    relay.forfeited = Some(connections.forfeited.clone());
+}
+
+pub fn close_watch_room(mut commands: Commands) {
+    commands.remove_resource::<WatchSocket>();
+    commands.remove_resource::<WatchRelay>();
+    commands.remove_resource::<LiveFeed>();
+}
+
+/// One player's relay as a spectator sees it
+#[derive(Debug, Default)]
+struct RelayStream {
+    hello: Option<WatchHello>,
+    rows: Vec<Vec<PlayerInput>>,
+    /// Handles the player reported as forfeited when their match ended
+    end: Option<Vec<usize>>,
+    /// The relay dropped; without an `End` first, the player left mid-match
+    left: bool,
+}
+
+impl RelayStream {
+    fn streaming(&self) -> bool {
+        self.end.is_none() && !self.left
+    }
+}
+
+/// Inputs a spectator has received from the players' relays
+///
+/// A row is only played once every relay still streaming has sent it, and relays must agree
+/// on every row they share, so no player can make up anyone else's inputs.
+#[derive(Resource)]
+pub struct LiveFeed {
+    streams: HashMap<PeerId, RelayStream>,
+    /// Counts down until every player's relay has introduced itself
+    pub join_timer: Timer,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self {
+            streams: HashMap::default(),
+            join_timer: Timer::from_seconds(WATCH_JOIN_TIMEOUT_SECS, TimerMode::Once),
+        }
+    }
+}
+
+impl LiveFeed {
+    /// Takes in everything the relays sent since the last update
+    pub fn update(&mut self, socket: &mut WatchSocket) -> Result<(), String> {
+        for (peer, state) in socket.update_peers() {
+            if state == PeerState::Disconnected {
+                self.disconnected(peer);
+            }
+        }
+        for (peer, message) in socket.receive() {
+            self.receive(peer, message)?;
+        }
+        Ok(())
+    }
+
+    pub fn disconnected(&mut self, peer: PeerId) {
+        if let Some(stream) = self.streams.get_mut(&peer) {
+            stream.left = true;
+        }
+    }
+
+    pub fn receive(&mut self, peer: PeerId, message: WatchMessage) -> Result<(), String> {
+        match message {
+            WatchMessage::Hello(hello) => {
+                if hello.version != WATCH_VERSION {
+                    return Err(format!(
+                        "a player's relay speaks watch v{}, we speak v{WATCH_VERSION}",
+                        hello.version
+                    ));
+                }
+                self.streams.entry(peer).or_default().hello = Some(hello);
+            }
+            WatchMessage::Frames { start, inputs } => {
+                let Some(stream) = self.streams.get(&peer) else {
+                    return Err("a relay sent inputs before introducing itself".to_string());
+                };
+                if start != stream.rows.len() {
+                    return Err(format!("a relay skipped from row {} to {start}", stream.rows.len()));
+                }
+                let num_players = stream.hello.as_ref().map_or(0, |hello| hello.num_players);
+                for (offset, row) in inputs.iter().enumerate() {
+                    let index = start + offset;
+                    if row.len() != num_players {
+                        return Err(format!("frame {} has the wrong player count", index + 1));
+                    }
+                    let disagrees = self
+                        .streams
+                        .iter()
+                        .any(|(other, stream)| *other != peer && stream.rows.get(index).is_some_and(|theirs| theirs != row));
+                    if disagrees {
+                        return Err(format!("the players' inputs disagree on frame {}", index + 1));
+                    }
+                }
+                if let Some(stream) = self.streams.get_mut(&peer) {
+                    stream.rows.extend(inputs);
+                }
+            }
+            WatchMessage::End { frames, forfeited } => {
+                let Some(stream) = self.streams.get_mut(&peer) else {
+                    return Err("a relay ended before introducing itself".to_string());
+                };
+                if frames != stream.rows.len() {
+                    return Err(format!("a relay ended after {frames} frames but sent {}", stream.rows.len()));
+                }
+                stream.end = Some(forfeited);
+            }
+        }
+        Ok(())
+    }
+
+    /// The match every player's relay describes, once each handle has introduced itself
+    pub fn match_info(&self) -> Result<Option<WatchHello>, String> {
+        let mut hellos = self.streams.values().filter_map(|stream| stream.hello.as_ref());
+        let Some(first) = hellos.next() else {
+            return Ok(None);
+        };
+        let mut handles = vec![false; first.num_players];
+        for hello in std::iter::once(first).chain(hellos) {
+            if !hello.same_match(first) {
+                return Err("the players' relays describe different matches".to_string());
+            }
+            match handles.get_mut(hello.handle) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err(format!("two relays claim handle {}", hello.handle)),
+            }
+        }
+        Ok(handles.iter().all(|seen| *seen).then(|| first.clone()))
+    }
+
+    /// Rows every relay still streaming has sent
+    pub fn playable(&self) -> usize {
+        let received = self.streams.values().map(|stream| stream.rows.len());
+        self.streams
+            .values()
+            .filter(|stream| stream.streaming())
+            .map(|stream| stream.rows.len())
+            .min()
+            .unwrap_or_else(|| received.max().unwrap_or(0))
+    }
+
+    pub fn row(&self, index: usize) -> Option<&Vec<PlayerInput>> {
+        if index >= self.playable() {
+            return None;
+        }
+        self.streams.values().find_map(|stream| stream.rows.get(index))
+    }
+
+    /// Whether any player's relay may still send more
+    pub fn streaming(&self) -> bool {
+        self.streams.values().any(RelayStream::streaming)
+    }
+
+    /// Handles that forfeited, once no relay is streaming. A forfeit only counts if the player's
+    /// relay dropped without an `End` and every player who finished names them; anything else
+    /// means the match ended without a result both sides agree on
+    pub fn forfeits(&self) -> Result<Vec<usize>, String> {
+        let handle = |stream: &RelayStream| stream.hello.as_ref().map(|hello| hello.handle);
+        let mut left: Vec<usize> = self
+            .streams
+            .values()
+            .filter(|stream| stream.left && stream.end.is_none())
+            .filter_map(handle)
+            .collect();
+        left.sort();
+
+        let claims: Vec<Vec<usize>> = self
+            .streams
+            .values()
+            .filter_map(|stream| stream.end.clone())
+            .map(|mut forfeited| {
+                forfeited.sort();
+                forfeited
+            })
+            .collect();
+
+        if claims.is_empty() {
+            return Err("every player left before the match finished".to_string());
+        }
+        if left.is_empty() || claims.iter().any(|claim| *claim != left) {
+            return Err("the players disagree on how the match ended".to_string());
+        }
+        Ok(left)
+    }
+
+    /// Players whose relays have introduced themselves so far
+    pub fn players_found(&self) -> usize {
+        self.streams.values().filter(|stream| stream.hello.is_some()).count()
+    }
+}
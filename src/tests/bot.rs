@@ -0,0 +1,57 @@
+// bot.rs - The computer opponent under SyncTest: it has to play, and play the same way every time
+use crate::{
+    core::args::BotDifficulty,
+    core::fixed::{Fixed, FixedVec2},
+    entities::components::{Health, Position},
+    game::bot::BOT_HANDLE,
+};
+use super::{Arena, TestMatch};
+
+/// Outside the easy bot's engage range, so it has to walk in before it shoots
+const OUT_OF_RANGE: Fixed = Fixed::from_int(12);
+
+/// Long enough for any difficulty to land a shot on a target standing still
+const ENGAGE_FRAMES: i32 = 600;
+
+/// Five seconds of play: room for many decisions and a few shots, but not for winning the match
+const REPLAY_FRAMES: i32 = 300;
+
+fn bot_position(test: &mut TestMatch) -> FixedVec2 {
+    test.component::<Position>(BOT_HANDLE).expect("the bot is dead").0
+}
+
+#[test]
+fn bot_walks_into_range_and_shoots() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(OUT_OF_RANGE));
+    test.bot(BotDifficulty::Easy);
+    test.advance(1);
+    let start = bot_position(&mut test);
+
+    assert!(
+        test.advance_until(ENGAGE_FRAMES, |test| test.health(0) < Some(Health::default().max)),
+        "the bot never hit its target"
+    );
+    assert!(
+        (bot_position(&mut test) - start).length() > Fixed::ONE,
+        "the bot shot from where it started"
+    );
+    assert!(test.resimulated_frames() > 0, "nothing was rolled back");
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn bot_plays_the_same_match_every_time() {
+    let play = || {
+        let mut test = TestMatch::duel();
+        test.bot(BotDifficulty::Hard);
+        test.advance(REPLAY_FRAMES);
+        test.assert_no_checksum_mismatches();
+        (bot_position(&mut test), test.health(0), test.scores())
+    };
+
+    let (first_position, first_health, first_scores) = play();
+    let (second_position, second_health, second_scores) = play();
+    assert_eq!(first_position, second_position, "the bot walked a different path");
+    assert_eq!(first_health, second_health, "the bot's shots landed differently");
+    assert_eq!(first_scores, second_scores);
+}
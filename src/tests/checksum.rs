@@ -0,0 +1,40 @@
+// checksum.rs - Checksums must hash the same bytes on every target, or mixed peers desync
+//
+// The expected values were computed on x86_64; a wasm32 build hashing any field at pointer
+// width, as a derived `Hash` does for `usize`, enums and arrays, would produce different ones.
+use crate::{
+    core::resources::Scores,
+    entities::components::{Bullet, CombatAction, CombatState, Player},
+    utils::checksum::{checksum_bullet, checksum_combat_state, checksum_player, checksum_scores},
+};
+
+#[test]
+fn player_checksum_is_portable() {
+    assert_eq!(checksum_player(&Player { handle: 1 }), 0x513721f7dbf44b68);
+}
+
+#[test]
+fn bullet_checksum_is_portable() {
+    assert_eq!(checksum_bullet(&Bullet { owner: 1, damage: 25 }), 0x3ecd91e1b485dc22);
+}
+
+#[test]
+fn combat_state_checksum_is_portable() {
+    let combat = CombatState {
+        current_action: CombatAction::Dodge,
+        action_frames: 3,
+        dodge_held: true,
+        dodge_cooldown: 45,
+        ..Default::default()
+    };
+    assert_eq!(checksum_combat_state(&combat), 0x9fd968899b13aeaf);
+}
+
+#[test]
+fn scores_checksum_is_portable() {
+    let scores = Scores {
+        rounds_played: 3,
+        by_handle: [2, 1, 0, 0, 0, 0, 0, 0],
+    };
+    assert_eq!(checksum_scores(&scores), 0xa4f210b3825e8459);
+}
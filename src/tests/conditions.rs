@@ -0,0 +1,66 @@
+// conditions.rs - --net-* under SyncTest: the opponent's inputs arrive late, and replay the same way
+use std::time::Duration;
+use crate::{
+    core::fixed::Fixed,
+    entities::components::Position,
+    game::input::INPUT_RIGHT,
+    network::conditions::NetworkConditions,
+};
+use super::{Arena, TestMatch};
+
+/// Fifteen frames at 60 fps
+const LATENCY: Duration = Duration::from_millis(250);
+const LATENCY_FRAMES: i32 = 15;
+
+/// Ten units apart with nothing in between
+const FACE_OFF: Fixed = Fixed::from_int(10);
+
+fn x(test: &mut TestMatch, handle: usize) -> Fixed {
+    test.component::<Position>(handle).expect("the player is dead").0.x
+}
+
+#[test]
+fn opponent_inputs_arrive_after_the_latency() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+    test.conditions(NetworkConditions {
+        latency: LATENCY,
+        ..Default::default()
+    });
+    test.advance(1);
+    let (start_0, start_1) = (x(&mut test, 0), x(&mut test, 1));
+
+    test.press(0, INPUT_RIGHT);
+    test.press(1, INPUT_RIGHT);
+    test.advance(LATENCY_FRAMES - 1);
+    assert!(x(&mut test, 0) > start_0, "the local player waited on the conditions");
+    assert_eq!(x(&mut test, 1), start_1, "the opponent moved before its input arrived");
+
+    test.advance(2);
+    assert!(x(&mut test, 1) > start_1, "the opponent's input never arrived");
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn opponent_plays_through_loss_and_reordering() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+    test.conditions(NetworkConditions {
+        jitter: LATENCY,
+        loss: 0.5,
+        reorder: 0.5,
+        ..Default::default()
+    });
+    test.advance(1);
+    let start = x(&mut test, 1);
+
+    test.press(1, INPUT_RIGHT);
+    test.advance(LATENCY_FRAMES * 4);
+    assert!(x(&mut test, 1) > start, "none of the opponent's inputs got through");
+
+    // Once the release gets through, a late or stale press mustn't start it walking again
+    test.release(1);
+    test.advance(LATENCY_FRAMES * 4);
+    let stopped = x(&mut test, 1);
+    test.advance(LATENCY_FRAMES);
+    assert_eq!(x(&mut test, 1), stopped, "the opponent kept walking after letting go");
+    test.assert_no_checksum_mismatches();
+}
@@ -0,0 +1,313 @@
+// tests/mod.rs - Headless SyncTest harness for the rollback simulation
+//
+// The crate builds for wasm by default, so run these against the host target:
+//   cargo test --target x86_64-unknown-linux-gnu
+use std::collections::HashMap;
+use bevy::{prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy};
+use bevy_ggrs::{prelude::*, Checksum, LocalInputs, LocalPlayers, RollbackFrameCount, SaveWorld, SaveWorldSystems};
+use crate::{
+    Config,
+    ModelAssets,
+    core::constants::{rollback_timestep, ROLLBACK_FPS},
+    core::fixed::{Fixed, FixedVec2},
+    core::args::BotDifficulty,
+    core::resources::{BotOpponent, MatchRules, PlayerAddressMapping, Scores, SessionSeed},
+    core::states::{GameState, RollbackState},
+    entities::components::{Aim, Health, MoveDir, Player, Position, Wall},
+    game::bot::{bot_inputs, bot_match, BotBrain},
+    game::input::{quantize_aim, PlayerInput},
+    game::leaderboard::check_game_end,
+    game::round::reset_round_state,
+    game::simulation::SimulationPlugin,
+    network::conditions::{condition_local_inputs, ConditionedInputs, NetworkConditions},
+    network::replay::confirm_replay_inputs,
+    network::stats::RollbackStats,
+};
+
+mod bot;
+mod checksum;
+mod conditions;
+mod rollback;
+mod watch;
+
+/// Deepest rollback a SyncTest session can check; every frame is resimulated this many times
+pub const CHECK_DISTANCE: usize = 7;
+
+/// Fixed so a failing run can be replayed exactly
+pub const TEST_SEED: u64 = 0x5EED;
+
+/// Input each handle sends on every frame until changed
+#[derive(Resource, Default)]
+struct ScriptedInputs(HashMap<usize, PlayerInput>);
+
+/// Where each handle starts every round, facing which way; walls are cleared when set
+#[derive(Resource, Clone)]
+pub struct Arena(pub Vec<(FixedVec2, FixedVec2)>);
+
+impl Arena {
+    /// A duel `spacing` apart along the x axis, centred on the origin and facing each other
+    pub fn with_spacing(spacing: Fixed) -> Self {
+        let half = spacing * Fixed::HALF;
+        Self(vec![
+            (FixedVec2::new(-half, Fixed::ZERO), FixedVec2::new(Fixed::ONE, Fixed::ZERO)),
+            (FixedVec2::new(half, Fixed::ZERO), FixedVec2::new(-Fixed::ONE, Fixed::ZERO)),
+        ])
+    }
+}
+
+/// The checksum first saved for each frame, and every frame a resimulation disagreed on
+#[derive(Resource, Default)]
+struct ChecksumHistory {
+    by_frame: HashMap<i32, u128>,
+    mismatches: Vec<(i32, u128, u128)>,
+}
+
+/// A duel running in a SyncTest session without a window or renderer
+pub struct TestMatch {
+    app: App,
+}
+
+impl TestMatch {
+    /// Starts on the seeded map, with players on their usual spawn ring
+    pub fn duel() -> Self {
+        Self::new(None)
+    }
+
+    /// Starts every round with no walls and the players placed as `arena` says
+    pub fn in_arena(arena: Arena) -> Self {
+        Self::new(Some(arena))
+    }
+
+    fn new(arena: Option<Arena>) -> Self {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, AssetPlugin::default()))
+            // Nothing draws the meshes and scenes the rollback systems spawn, but the assets must exist
+            .init_asset::<Mesh>()
+            .init_asset::<StandardMaterial>()
+            .init_asset::<Scene>()
+            .insert_resource(ModelAssets {
+                player_1: Handle::default(),
+                player_2: Handle::default(),
+            })
+            .add_plugins(SimulationPlugin)
+            .insert_state(GameState::InGame)
+            .insert_resource(MatchRules::default())
+            .insert_resource(SessionSeed(TEST_SEED))
+            // One rollback frame of time per update; `advance` counts frames, not updates
+            .insert_resource(TimeUpdateStrategy::ManualDuration(rollback_timestep()))
+            .init_resource::<ScriptedInputs>()
+            .init_resource::<ChecksumHistory>()
+            .init_resource::<BotOpponent>()
+            .init_resource::<BotBrain>()
+            .add_systems(
+                ReadInputs,
+                (
+                    scripted_inputs,
+                    bot_inputs.after(scripted_inputs).run_if(bot_match),
+                    condition_local_inputs
+                        .after(scripted_inputs)
+                        .after(bot_inputs)
+                        .run_if(resource_exists::<ConditionedInputs>),
+                ),
+            )
+            .add_systems(SaveWorld, record_checksum.after(SaveWorldSystems::Snapshot))
+            .add_systems(
+                Update,
+                check_game_end
+                    .after(confirm_replay_inputs)
+                    .run_if(in_state(GameState::InGame)),
+            );
+
+        if let Some(arena) = arena {
+            app.insert_resource(arena).add_systems(
+                OnEnter(RollbackState::InRound),
+                stage_arena.after(crate::game::player::spawn_players),
+            );
+        }
+
+        let mut session_builder = SessionBuilder::<Config>::new()
+            .with_num_players(2)
+            .with_fps(ROLLBACK_FPS)
+            .expect("invalid rollback fps")
+            .with_check_distance(CHECK_DISTANCE);
+        for handle in 0..2 {
+            session_builder = session_builder
+                .add_player(PlayerType::Local, handle)
+                .expect("failed to add player");
+        }
+        let session = session_builder
+            .start_synctest_session()
+            .expect("failed to start session");
+
+        let world = app.world_mut();
+        reset_round_state(&mut world.commands());
+        world.flush();
+        world.insert_resource(Session::SyncTest(session));
+
+        app.finish();
+        app.cleanup();
+        Self { app }
+    }
+
+    /// Gives `handle` the loadout its handshake would carry; takes effect when the next round spawns
+    pub fn equip(&mut self, handle: usize, loadout: &[&str]) {
+        let loadout = loadout.iter().map(|id| id.to_string()).collect();
+        self.app
+            .world_mut()
+            .get_resource_or_init::<PlayerAddressMapping>()
+            .set_player(handle, format!("test_player{handle}"), format!("Player {handle}"), loadout);
+    }
+
+    /// Hands the bot's handle to the computer opponent, as `--bot` does; scripted input for it is ignored
+    pub fn bot(&mut self, difficulty: BotDifficulty) {
+        self.app.world_mut().insert_resource(BotOpponent(Some(difficulty)));
+    }
+
+    /// Puts every handle but 0 behind `conditions`, as `--net-*` does under `--synctest`
+    pub fn conditions(&mut self, conditions: NetworkConditions) {
+        self.app.world_mut().insert_resource(ConditionedInputs::new(conditions, 0));
+    }
+
+    /// Held from the next frame on, like a key kept down
+    pub fn press(&mut self, handle: usize, input: u8) {
+        self.scripted(handle).buttons = input;
+    }
+
+    /// Aims along `direction` from the next frame on, like a cursor left in place
+    pub fn aim(&mut self, handle: usize, direction: Vec2) {
+        self.scripted(handle).aim = quantize_aim(direction);
+    }
+
+    fn scripted(&mut self, handle: usize) -> Mut<'_, PlayerInput> {
+        self.app
+            .world_mut()
+            .resource_mut::<ScriptedInputs>()
+            .map_unchanged(|script| script.0.entry(handle).or_default())
+    }
+
+    pub fn release(&mut self, handle: usize) {
+        self.press(handle, 0);
+    }
+
+    pub fn frame(&self) -> i32 {
+        self.app.world().resource::<RollbackFrameCount>().0
+    }
+
+    /// Runs exactly `frames` rollback frames
+    pub fn advance(&mut self, frames: i32) {
+        let target = self.frame() + frames;
+        // The first update only fills the GGRS time accumulator
+        for _ in 0..=frames * 2 {
+            if self.frame() >= target {
+                return;
+            }
+            self.app.update();
+        }
+        panic!("stalled at frame {} on the way to {target}", self.frame());
+    }
+
+    /// Advances a frame at a time until `done` holds; false if it never did within `frames`
+    pub fn advance_until(&mut self, frames: i32, done: impl Fn(&mut Self) -> bool) -> bool {
+        for _ in 0..frames {
+            if done(self) {
+                return true;
+            }
+            self.advance(1);
+        }
+        done(self)
+    }
+
+    pub fn scores(&self) -> Scores {
+        *self.app.world().resource::<Scores>()
+    }
+
+    pub fn rollback_state(&self) -> RollbackState {
+        self.app.world().resource::<State<RollbackState>>().get().clone()
+    }
+
+    pub fn game_state(&self) -> GameState {
+        self.app.world().resource::<State<GameState>>().get().clone()
+    }
+
+    /// Handles of the players still standing this round
+    pub fn alive(&mut self) -> Vec<usize> {
+        let world = self.app.world_mut();
+        let mut handles: Vec<usize> = world.query::<&Player>().iter(world).map(|player| player.handle).collect();
+        handles.sort();
+        handles
+    }
+
+    /// A copy of `handle`'s `T`; `None` once the player is dead
+    pub fn component<T: Component + Clone>(&mut self, handle: usize) -> Option<T> {
+        let world = self.app.world_mut();
+        world
+            .query::<(&Player, &T)>()
+            .iter(world)
+            .find(|(player, _)| player.handle == handle)
+            .map(|(_, component)| component.clone())
+    }
+
+    /// `None` once the player is dead
+    pub fn health(&mut self, handle: usize) -> Option<i32> {
+        self.component::<Health>(handle).map(|health| health.current)
+    }
+
+    /// Frames simulated more than once, i.e. how much rolling back actually happened
+    pub fn resimulated_frames(&self) -> u32 {
+        self.app.world().resource::<RollbackStats>().resimulated_frames
+    }
+
+    /// Fails the test if any resimulated frame saved a different checksum than the first time
+    pub fn assert_no_checksum_mismatches(&self) {
+        let history = self.app.world().resource::<ChecksumHistory>();
+        assert!(!history.by_frame.is_empty(), "no frames were checksummed");
+        assert!(
+            history.mismatches.is_empty(),
+            "checksums changed on resimulation (frame, first, resimulated): {:?}",
+            history.mismatches
+        );
+    }
+}
+
+fn scripted_inputs(mut commands: Commands, script: Res<ScriptedInputs>, local_players: Res<LocalPlayers>) {
+    let inputs = local_players
+        .0
+        .iter()
+        .map(|&handle| (handle, script.0.get(&handle).copied().unwrap_or_default()))
+        .collect();
+    commands.insert_resource(LocalInputs::<Config>(inputs));
+}
+
+/// Runs inside the rollback schedule, so the arranged round is part of every snapshot
+fn stage_arena(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    walls: Query<Entity, With<Wall>>,
+    mut players: Query<(&Player, &mut Position, &mut MoveDir, &mut Aim)>,
+) {
+    for wall in &walls {
+        commands.entity(wall).despawn();
+    }
+    for (player, mut position, mut move_dir, mut aim) in &mut players {
+        if let Some(&(spawn, facing)) = arena.0.get(player.handle) {
+            position.0 = spawn;
+            move_dir.0 = facing;
+            aim.0 = facing;
+        }
+    }
+}
+
+/// GGRS only logs a mismatch, so keep our own record of what each frame hashed to
+fn record_checksum(
+    frame: Res<RollbackFrameCount>,
+    checksum: Option<Res<Checksum>>,
+    mut history: ResMut<ChecksumHistory>,
+) {
+    let Some(checksum) = checksum else {
+        return;
+    };
+    let first = *history.by_frame.entry(frame.0).or_insert(checksum.0);
+    if first != checksum.0 {
+        history.mismatches.push((frame.0, first, checksum.0));
+    }
+}
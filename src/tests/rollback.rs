@@ -0,0 +1,294 @@
+// rollback.rs - Round flow and determinism of the rollback simulation under SyncTest
+use bevy::prelude::*;
+use crate::{
+    core::constants::BULLET_DAMAGE,
+    core::fixed::Fixed,
+    core::states::{GameState, RollbackState},
+    game::input::{INPUT_BLOCK, INPUT_DODGE, INPUT_DOWN, INPUT_FIRE, INPUT_LEFT, INPUT_RIGHT, INPUT_SPRINT, INPUT_UP},
+    game::combat::{guard_for, DODGE_FRAMES, SWINGS},
+    entities::components::{Health, Position, Stamina, ATTACK_STAMINA_COST, DODGE_STAMINA_COST},
+    game::leaderboard::WINNING_SCORE,
+};
+use super::{Arena, TestMatch};
+
+/// Round end plus the round end timer, with room to spare
+const ROUND_RESTART_FRAMES: i32 = 90;
+
+/// A bullet crosses the arena in well under a second
+const SHOT_FRAMES: i32 = 60;
+
+/// Ten units apart with nothing in between
+const FACE_OFF: Fixed = Fixed::from_int(10);
+
+/// Within sword reach of each other
+const CLOSE_QUARTERS: Fixed = Fixed::from_ratio(3, 2);
+
+/// Out of sword reach, but close enough that a shot lands within a roll's i-frames
+const POINT_BLANK: Fixed = Fixed::from_int(4);
+
+/// Pulls handle 0's trigger for one frame, which is all a shot takes
+fn shoot(test: &mut TestMatch) {
+    test.press(0, INPUT_FIRE);
+    test.advance(1);
+    test.release(0);
+}
+
+/// Enough shots, a frame apart, to take a full-health player down
+fn shoot_to_kill(test: &mut TestMatch) {
+    let full_health = Health::default().max;
+    for _ in 0..(full_health + BULLET_DAMAGE - 1) / BULLET_DAMAGE {
+        shoot(test);
+        test.advance(1);
+    }
+}
+
+#[test]
+fn bullets_wear_down_health_and_kill() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+    test.advance(1);
+    assert_eq!(test.alive(), vec![0, 1]);
+
+    shoot(&mut test);
+    let wounded = Health::default().max - BULLET_DAMAGE;
+    assert!(
+        test.advance_until(SHOT_FRAMES, |test| test.health(1) == Some(wounded)),
+        "the shot never landed"
+    );
+    assert_eq!(test.alive(), vec![0, 1], "one bullet isn't a kill");
+
+    shoot_to_kill(&mut test);
+    assert!(test.advance_until(SHOT_FRAMES, |test| test.alive() == vec![0]), "the shots never killed");
+
+    let scores = test.scores();
+    assert_eq!(scores.get(0), 1);
+    assert_eq!(scores.get(1), 0);
+    assert_eq!(test.rollback_state(), RollbackState::RoundEnd);
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn round_end_restarts_the_round() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+    test.advance(1);
+    shoot_to_kill(&mut test);
+    assert!(test.advance_until(SHOT_FRAMES, |test| test.rollback_state() == RollbackState::RoundEnd));
+
+    assert!(
+        test.advance_until(ROUND_RESTART_FRAMES, |test| test.rollback_state() == RollbackState::InRound),
+        "the round end timer never fired"
+    );
+    test.advance(1);
+    assert_eq!(test.alive(), vec![0, 1], "both players respawn for the next round");
+    assert_eq!(test.scores().get(0), 1, "scores carry over between rounds");
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn game_ends_at_winning_score() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+
+    for round in 1..=WINNING_SCORE {
+        assert!(test.advance_until(ROUND_RESTART_FRAMES, |test| {
+            test.rollback_state() == RollbackState::InRound && test.alive() == vec![0, 1]
+        }));
+        assert_eq!(test.game_state(), GameState::InGame, "the match ended early, after {} rounds", round - 1);
+
+        shoot_to_kill(&mut test);
+        assert!(
+            test.advance_until(SHOT_FRAMES, |test| test.scores().get(0) == round),
+            "round {round} was never won"
+        );
+    }
+
+    assert!(
+        test.advance_until(2, |test| test.game_state() == GameState::GameEnd),
+        "check_game_end never ended the match"
+    );
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn melee_swings_instead_of_shooting_up_close() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(CLOSE_QUARTERS));
+    test.advance(1);
+
+    shoot(&mut test);
+    let first_swing = SWINGS[0].startup as i32 + 2;
+    assert!(
+        test.advance_until(first_swing, |test| test.health(1) == Some(100 - SWINGS[0].damage)),
+        "the first swing never landed"
+    );
+    assert_eq!(test.component::<Stamina>(0).map(|stamina| stamina.current), Some(Stamina::default().max - ATTACK_STAMINA_COST), "the swing was free");
+
+    // Keep swinging; hit stun and invulnerability pace the damage, but never stop it
+    for _ in 0..20 {
+        if test.alive() == vec![0] {
+            break;
+        }
+        shoot(&mut test);
+        test.advance(20);
+    }
+
+    assert_eq!(test.alive(), vec![0], "melee damage never killed");
+    assert_eq!(test.scores().get(0), 1);
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn dodge_rolls_through_a_bullet() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(POINT_BLANK));
+    test.advance(1);
+
+    // Handle 1 rolls straight at the shooter on the frame the shot goes off
+    test.press(0, INPUT_FIRE);
+    test.press(1, INPUT_DODGE);
+    test.advance(1);
+    test.release(0);
+    test.release(1);
+
+    let after_roll = Some(Stamina::default().max - DODGE_STAMINA_COST);
+    assert_eq!(test.component::<Stamina>(1).map(|stamina| stamina.current), after_roll, "the roll was free");
+
+    // The roll is over but still on cooldown, so a second press does nothing
+    test.advance(DODGE_FRAMES as i32);
+    test.press(1, INPUT_DODGE);
+    test.advance(1);
+    test.release(1);
+    assert_eq!(test.component::<Stamina>(1).map(|stamina| stamina.current), after_roll, "rolled again during the cooldown");
+
+    test.advance(SHOT_FRAMES);
+    assert_eq!(test.health(1), Some(Health::default().max), "the bullet hit through the i-frames");
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn block_cuts_frontal_damage() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(POINT_BLANK));
+    test.advance(1);
+
+    // Guard up well before the shot, so the parry window is long gone when it lands
+    test.press(1, INPUT_BLOCK);
+    test.advance(20);
+    shoot(&mut test);
+
+    let blocked = BULLET_DAMAGE * (100 - guard_for(&[]).reduction) / 100;
+    assert!(
+        test.advance_until(SHOT_FRAMES, |test| test.health(1) == Some(Health::default().max - blocked)),
+        "the blocked shot didn't land for reduced damage"
+    );
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn shield_from_the_loadout_guards_better_than_the_weapon() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(POINT_BLANK));
+    test.equip(1, &["longsword", "kite_shield"]);
+    test.advance(1);
+
+    test.press(1, INPUT_BLOCK);
+    test.advance(20);
+    shoot(&mut test);
+
+    let shield = guard_for(&["kite_shield".to_string()]);
+    assert!(shield.reduction > guard_for(&[]).reduction);
+    let blocked = BULLET_DAMAGE * (100 - shield.reduction) / 100;
+    assert!(
+        test.advance_until(SHOT_FRAMES, |test| test.health(1) == Some(Health::default().max - blocked)),
+        "the shield didn't cut the shot by its own guard"
+    );
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn parry_reflects_a_bullet_back_at_the_shooter() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(POINT_BLANK));
+    test.advance(1);
+
+    // The shot takes about ten frames to arrive, so this block's parry window is open when it does
+    shoot(&mut test);
+    test.advance(5);
+    test.press(1, INPUT_BLOCK);
+
+    let wounded = Health::default().max - BULLET_DAMAGE;
+    assert!(
+        test.advance_until(SHOT_FRAMES, |test| test.health(0) == Some(wounded)),
+        "the parried bullet never came back"
+    );
+    assert_eq!(test.health(1), Some(Health::default().max));
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn parry_staggers_a_swing() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(CLOSE_QUARTERS));
+    test.advance(1);
+
+    // Raised a few frames before the hitbox comes out
+    shoot(&mut test);
+    test.advance(SWINGS[0].startup as i32 - 4);
+    test.press(1, INPUT_BLOCK);
+    test.advance(SWINGS[0].frames() as i32);
+
+    assert_eq!(test.health(1), Some(Health::default().max), "the swing got through the parry");
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn sprint_outruns_walking_and_costs_stamina() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+    test.advance(1);
+    let start = [test.component::<Position>(0).unwrap().0, test.component::<Position>(1).unwrap().0];
+
+    test.press(0, INPUT_UP | INPUT_SPRINT);
+    test.press(1, INPUT_UP);
+    test.advance(30);
+
+    let sprinted = (test.component::<Position>(0).unwrap().0 - start[0]).length();
+    let walked = (test.component::<Position>(1).unwrap().0 - start[1]).length();
+    assert!(sprinted > walked, "sprinted {sprinted:?}, walked {walked:?}");
+    assert!(test.component::<Stamina>(0).map(|stamina| stamina.current) < Some(Stamina::default().max), "the sprint was free");
+    assert_eq!(test.component::<Stamina>(1).map(|stamina| stamina.current), Some(Stamina::default().max), "walking cost stamina");
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn aim_shoots_across_the_direction_of_travel() {
+    let mut test = TestMatch::in_arena(Arena::with_spacing(FACE_OFF));
+    test.advance(1);
+
+    // Strafing up the screen while aiming at the other player
+    test.aim(0, Vec2::X);
+    test.press(0, INPUT_UP | INPUT_FIRE);
+    test.advance(1);
+    test.press(0, INPUT_UP);
+
+    let wounded = Health::default().max - BULLET_DAMAGE;
+    assert!(
+        test.advance_until(SHOT_FRAMES, |test| test.health(1) == Some(wounded)),
+        "the shot followed the movement instead of the aim"
+    );
+    test.assert_no_checksum_mismatches();
+}
+
+#[test]
+fn resimulation_matches_on_the_real_map() {
+    // Both players wander the seeded map shooting, so walls, bullets and round ends all roll back
+    const PATTERN: [u8; 6] = [
+        INPUT_UP | INPUT_FIRE,
+        INPUT_RIGHT,
+        INPUT_DOWN | INPUT_LEFT | INPUT_FIRE,
+        INPUT_LEFT,
+        0,
+        INPUT_DOWN | INPUT_RIGHT | INPUT_FIRE,
+    ];
+
+    let mut test = TestMatch::duel();
+    for step in 0..60 {
+        test.press(0, PATTERN[step % PATTERN.len()]);
+        test.press(1, PATTERN[(step + 3) % PATTERN.len()]);
+        test.advance(10);
+    }
+
+    assert!(test.resimulated_frames() > 0, "SyncTest never rolled back");
+    test.assert_no_checksum_mismatches();
+}
@@ -0,0 +1,101 @@
+// watch.rs - What a spectator accepts from the players' relays
+use bevy_matchbox::prelude::PeerId;
+use crate::{
+    core::args::GameMode,
+    core::resources::MatchRoom,
+    game::input::PlayerInput,
+    network::matchmaking::parse_room_code,
+    network::replay::ReplayPlayer,
+    network::watch::{watch_code, LiveFeed, WatchHello, WatchMessage, WATCH_VERSION},
+};
+
+fn peer(n: u8) -> PeerId {
+    serde_json::from_value(serde_json::json!(format!("00000000-0000-0000-0000-{n:012}"))).unwrap()
+}
+
+fn hello(handle: usize) -> WatchMessage {
+    WatchMessage::Hello(WatchHello {
+        version: WATCH_VERSION,
+        handle,
+        session_seed: 42,
+        num_players: 2,
+        mode: GameMode::FreeForAll,
+        players: vec![ReplayPlayer::default(); 2],
+    })
+}
+
+fn rows(buttons: &[u8]) -> Vec<Vec<PlayerInput>> {
+    buttons.iter().map(|&b| vec![PlayerInput::from_buttons(b); 2]).collect()
+}
+
+/// Both relays introduced, as a spectator sees them once the match is found
+fn duel_feed() -> LiveFeed {
+    let mut feed = LiveFeed::default();
+    feed.receive(peer(1), hello(0)).unwrap();
+    feed.receive(peer(2), hello(1)).unwrap();
+    feed
+}
+
+#[test]
+fn match_starts_once_every_handle_has_a_relay() {
+    let mut feed = LiveFeed::default();
+    feed.receive(peer(1), hello(0)).unwrap();
+    assert_eq!(feed.match_info(), Ok(None));
+
+    feed.receive(peer(2), hello(1)).unwrap();
+    assert!(feed.match_info().unwrap().is_some());
+
+    feed.receive(peer(3), hello(1)).unwrap();
+    assert!(feed.match_info().is_err(), "two relays claiming one handle");
+}
+
+#[test]
+fn rows_wait_for_every_relay_still_streaming() {
+    let mut feed = duel_feed();
+    feed.receive(peer(1), WatchMessage::Frames { start: 0, inputs: rows(&[1, 2, 3]) }).unwrap();
+    feed.receive(peer(2), WatchMessage::Frames { start: 0, inputs: rows(&[1]) }).unwrap();
+
+    assert_eq!(feed.playable(), 1);
+    assert!(feed.row(0).is_some());
+    assert!(feed.row(1).is_none(), "only one player has confirmed frame 2");
+}
+
+#[test]
+fn relays_that_disagree_are_rejected() {
+    let mut feed = duel_feed();
+    feed.receive(peer(1), WatchMessage::Frames { start: 0, inputs: rows(&[1, 2]) }).unwrap();
+
+    let forged = WatchMessage::Frames { start: 0, inputs: rows(&[1, 4]) };
+    assert!(feed.receive(peer(2), forged).is_err());
+}
+
+#[test]
+fn forfeit_needs_the_leaver_to_drop_and_the_others_to_name_them() {
+    let mut feed = duel_feed();
+    feed.receive(peer(1), WatchMessage::Frames { start: 0, inputs: rows(&[1, 2]) }).unwrap();
+    feed.receive(peer(2), WatchMessage::Frames { start: 0, inputs: rows(&[1]) }).unwrap();
+    feed.receive(peer(1), WatchMessage::End { frames: 2, forfeited: vec![1] }).unwrap();
+    assert!(feed.streaming(), "handle 1's relay is still up");
+
+    feed.disconnected(peer(2));
+    assert!(!feed.streaming());
+    assert_eq!(feed.playable(), 2, "the survivor's rows play out");
+    assert_eq!(feed.forfeits(), Ok(vec![1]));
+}
+
+#[test]
+fn both_sides_claiming_a_forfeit_is_no_result() {
+    let mut feed = duel_feed();
+    feed.receive(peer(1), WatchMessage::End { frames: 0, forfeited: vec![1] }).unwrap();
+    feed.receive(peer(2), WatchMessage::End { frames: 0, forfeited: vec![0] }).unwrap();
+
+    assert!(!feed.streaming());
+    assert!(feed.forfeits().is_err());
+}
+
+#[test]
+fn watch_codes_are_room_codes() {
+    let code = watch_code(&MatchRoom::Public, 0x0123_4567_89AB_CDEF);
+    assert_eq!(parse_room_code(&code), Some(code.clone()));
+    assert_eq!(watch_code(&MatchRoom::Private("K7QX2M".into()), 1), "K7QX2M");
+}
@@ -4,5 +4,5 @@ pub mod network;
 pub mod ui;
 
 // Re-export plugins and main types
-pub use network::{ChatPlugin, ChatMessages, ChatInput, setup_chat_socket};
+pub use network::{ChatPlugin, ChatMessages, ChatInput, ChatSocket, setup_chat_socket};
 pub use ui::ChatUIPlugin;
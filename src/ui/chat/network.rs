@@ -4,6 +4,7 @@ use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::input::ButtonState;
 use bevy_matchbox::prelude::*;
 use serde::{Deserialize, Serialize};
+use crate::core::resources::ServerSettings;
 
 #[derive(Resource)]
 pub struct ChatSocket {
@@ -57,12 +58,18 @@ impl Plugin for ChatPlugin {
     }
 }
 
-pub fn setup_chat_socket(mut commands: Commands) {
+impl ChatSocket {
+    pub fn connect(chat_room_url: &str) -> Self {
+        info!("Connecting to chat server: {chat_room_url}");
+        Self {
+            socket: MatchboxSocket::new_reliable(chat_room_url),
+        }
+    }
+}
+
+pub fn setup_chat_socket(mut commands: Commands, servers: Res<ServerSettings>) {
     // Use a different room for chat to avoid conflicts with GGRS
-    let chat_room_url = "ws://127.0.0.1:3536/extreme_bevy_chat?next=2";
-    info!("Connecting to chat server: {chat_room_url}");
-    let socket = MatchboxSocket::new_reliable(chat_room_url);
-    commands.insert_resource(ChatSocket { socket });
+    commands.insert_resource(ChatSocket::connect(&servers.chat_url()));
 }
 
 fn handle_chat_input(
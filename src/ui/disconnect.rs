@@ -0,0 +1,51 @@
+// disconnect.rs - Countdown shown while a peer's connection is interrupted
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui::{self, Align2, Color32, FontId, RichText}};
+use crate::{
+    core::resources::PlayerAddressMapping,
+    network::session::PeerConnections,
+};
+
+pub fn render_disconnect_overlay(
+    mut contexts: EguiContexts,
+    connections: Res<PeerConnections>,
+    address_mapping: Res<PlayerAddressMapping>,
+) {
+    let Some(remaining) = connections.grace_remaining() else {
+        return;
+    };
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    let mut handles: Vec<usize> = connections.interrupted.keys().copied().collect();
+    handles.sort_unstable();
+    let names = handles
+        .iter()
+        .map(|&handle| address_mapping.name_or_default(handle))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    egui::Area::new("disconnect_overlay".into())
+        .anchor(Align2::CENTER_CENTER, (0., -120.))
+        .show(ctx, |ui| {
+            egui::Frame::default()
+                .fill(Color32::from_rgba_unmultiplied(20, 15, 10, 230))
+                .stroke(egui::Stroke::new(2.0, Color32::from_rgb(200, 50, 50)))
+                .inner_margin(15.0)
+                .show(ui, |ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.label(
+                            RichText::new(format!("{names} lost connection"))
+                                .color(Color32::from_rgb(255, 200, 100))
+                                .font(FontId::proportional(22.0)),
+                        );
+                        ui.label(
+                            RichText::new(format!("Forfeit in {:.0}s", remaining.ceil()))
+                                .color(Color32::WHITE)
+                                .font(FontId::proportional(36.0)),
+                        );
+                    });
+                });
+        });
+}
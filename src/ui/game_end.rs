@@ -4,6 +4,7 @@ use bevy_egui::{egui, EguiContexts};
 use crate::{
     game::leaderboard::{GameEndData, GameEndTimer},
     core::states::GameState,
+    network::replay::ReplayRecorder,
 };
 
 /// Render the game end screen with results
@@ -12,6 +13,8 @@ pub fn render_game_end_ui(
     game_end_data: Option<Res<GameEndData>>,
     timer: Option<Res<GameEndTimer>>,
     mut next_state: ResMut<NextState<GameState>>,
+    recorder: Res<ReplayRecorder>,
+    mut replay_status: Local<Option<Result<String, String>>>,
 ) {
     let ctx = match contexts.ctx_mut() {
         Ok(c) => c,
@@ -20,6 +23,11 @@ pub fn render_game_end_ui(
 
     let screen_rect = ctx.viewport_rect();
 
+    // A new match was recorded since the last end screen
+    if recorder.is_changed() {
+        *replay_status = None;
+    }
+
     // Get game end data or use defaults
     let data = game_end_data.map(|d| d.clone()).unwrap_or_default();
     let time_remaining = timer.map(|t| t.timer.remaining_secs()).unwrap_or(0.0);
@@ -61,10 +69,12 @@ pub fn render_game_end_ui(
                 ui.add_space(20.0);
 
                 // Determine if local player won
-                let did_local_player_win = data.local_player_handle == data.winner_handle;
+                let did_local_player_win = data
+                    .local_player_handle
+                    .is_some_and(|handle| data.is_winner(handle));
 
                 // Victory/Defeat banner
-                if let Some(winner) = data.winner_handle {
+                if let Some(winning_team) = data.winning_team {
                     let (title_text, title_color) = if did_local_player_win {
                         ("⚔ VICTORY ⚔", egui::Color32::from_rgb(255, 215, 0))
                     } else {
@@ -80,7 +90,16 @@ pub fn render_game_end_ui(
 
                     ui.add_space(10.0);
 
-                    let winner_text = format!("Player {} Wins!", winner + 1);
+                    let winner = if data.team_mode {
+                        GameEndData::team_name(winning_team)
+                    } else {
+                        data.names.get(winning_team).cloned().unwrap_or_default()
+                    };
+                    let winner_text = if data.is_forfeit() {
+                        format!("{winner} Wins by Forfeit!")
+                    } else {
+                        format!("{winner} Wins!")
+                    };
                     ui.label(
                         egui::RichText::new(winner_text)
                             .size(24.0)
@@ -121,7 +140,7 @@ pub fn render_game_end_ui(
                                 .strong(),
                         );
                         ui.label(
-                            egui::RichText::new("KILLS")
+                            egui::RichText::new("ROUNDS")
                                 .size(16.0)
                                 .color(egui::Color32::from_rgb(150, 130, 100))
                                 .strong(),
@@ -139,102 +158,61 @@ pub fn render_game_end_ui(
                         ui.add_space(5.0);
                         ui.end_row();
 
-                        // Player 1 (handle 0)
-                        let p1_is_winner = data.winner_handle == Some(0);
-                        let p1_is_local = data.local_player_handle == Some(0);
-                        let p1_label = if p1_is_local {
-                            "Player 1 (You)"
-                        } else {
-                            "Player 1"
-                        };
-
-                        ui.label(
-                            egui::RichText::new(p1_label)
-                                .size(18.0)
-                                .color(if p1_is_local {
-                                    egui::Color32::from_rgb(100, 200, 255)
-                                } else {
-                                    egui::Color32::WHITE
-                                })
-                                .strong(),
-                        );
-
-                        ui.label(
-                            egui::RichText::new(format!("{}", data.player0_score))
-                                .size(24.0)
-                                .color(if p1_is_winner {
-                                    egui::Color32::from_rgb(255, 215, 0)
-                                } else {
-                                    egui::Color32::WHITE
-                                })
-                                .strong(),
-                        );
+                        for (handle, score) in data.scores.iter().enumerate() {
+                            let is_winner = data.is_winner(handle);
+                            let is_local = data.local_player_handle == Some(handle);
+                            let mut label = data.names.get(handle).cloned().unwrap_or_default();
+                            if data.team_mode {
+                                label = format!("{label} ({})", GameEndData::team_name(data.teams[handle]));
+                            }
+                            if is_local {
+                                label.push_str(" (You)");
+                            }
 
-                        if p1_is_winner {
                             ui.label(
-                                egui::RichText::new("👑 WINNER")
-                                    .size(16.0)
-                                    .color(egui::Color32::from_rgb(255, 215, 0))
+                                egui::RichText::new(label)
+                                    .size(18.0)
+                                    .color(if is_local {
+                                        egui::Color32::from_rgb(100, 200, 255)
+                                    } else {
+                                        egui::Color32::WHITE
+                                    })
                                     .strong(),
                             );
-                        } else {
-                            ui.label(
-                                egui::RichText::new("")
-                                    .size(16.0),
-                            );
-                        }
-                        ui.end_row();
-
-                        ui.add_space(5.0);
-                        ui.add_space(5.0);
-                        ui.add_space(5.0);
-                        ui.end_row();
-
-                        // Player 2 (handle 1)
-                        let p2_is_winner = data.winner_handle == Some(1);
-                        let p2_is_local = data.local_player_handle == Some(1);
-                        let p2_label = if p2_is_local {
-                            "Player 2 (You)"
-                        } else {
-                            "Player 2"
-                        };
-
-                        ui.label(
-                            egui::RichText::new(p2_label)
-                                .size(18.0)
-                                .color(if p2_is_local {
-                                    egui::Color32::from_rgb(100, 200, 255)
-                                } else {
-                                    egui::Color32::WHITE
-                                })
-                                .strong(),
-                        );
-
-                        ui.label(
-                            egui::RichText::new(format!("{}", data.player1_score))
-                                .size(24.0)
-                                .color(if p2_is_winner {
-                                    egui::Color32::from_rgb(255, 215, 0)
-                                } else {
-                                    egui::Color32::WHITE
-                                })
-                                .strong(),
-                        );
 
-                        if p2_is_winner {
                             ui.label(
-                                egui::RichText::new("👑 WINNER")
-                                    .size(16.0)
-                                    .color(egui::Color32::from_rgb(255, 215, 0))
+                                egui::RichText::new(format!("{score}"))
+                                    .size(24.0)
+                                    .color(if is_winner {
+                                        egui::Color32::from_rgb(255, 215, 0)
+                                    } else {
+                                        egui::Color32::WHITE
+                                    })
                                     .strong(),
                             );
-                        } else {
-                            ui.label(
-                                egui::RichText::new("")
-                                    .size(16.0),
-                            );
+
+                            if is_winner {
+                                ui.label(
+                                    egui::RichText::new("👑 WINNER")
+                                        .size(16.0)
+                                        .color(egui::Color32::from_rgb(255, 215, 0))
+                                        .strong(),
+                                );
+                            } else if data.forfeited.contains(&handle) {
+                                ui.label(
+                                    egui::RichText::new("⚠ FORFEIT")
+                                        .size(16.0)
+                                        .color(egui::Color32::from_rgb(200, 50, 50))
+                                        .strong(),
+                                );
+                            } else {
+                                ui.label(
+                                    egui::RichText::new("")
+                                        .size(16.0),
+                                );
+                            }
+                            ui.end_row();
                         }
-                        ui.end_row();
                     });
 
                 ui.add_space(30.0);
@@ -267,6 +245,46 @@ pub fn render_game_end_ui(
                     info!("Manual return to lobby clicked");
                     next_state.set(GameState::Lobby);
                 }
+
+                // Recorded matches can be saved for `--replay`
+                if let Some(replay) = &recorder.finished {
+                    ui.add_space(10.0);
+
+                    let button = egui::Button::new(
+                        egui::RichText::new("Save Replay")
+                            .size(14.0)
+                            .color(egui::Color32::WHITE),
+                    )
+                    .fill(egui::Color32::from_rgb(60, 60, 100))
+                    .min_size(egui::vec2(250.0, 32.0));
+
+                    if ui.add(button).clicked() {
+                        let result = replay.save();
+                        match &result {
+                            Ok(file_name) => info!("Saved replay {file_name}"),
+                            Err(e) => error!("Failed to save replay: {e}"),
+                        }
+                        *replay_status = Some(result);
+                    }
+
+                    match replay_status.as_ref() {
+                        Some(Ok(file_name)) => {
+                            ui.label(
+                                egui::RichText::new(format!("Saved {file_name}"))
+                                    .size(12.0)
+                                    .color(egui::Color32::from_rgb(100, 200, 100)),
+                            );
+                        }
+                        Some(Err(e)) => {
+                            ui.label(
+                                egui::RichText::new(format!("Save failed: {e}"))
+                                    .size(12.0)
+                                    .color(egui::Color32::from_rgb(200, 100, 100)),
+                            );
+                        }
+                        None => {}
+                    }
+                }
             });
         });
 }
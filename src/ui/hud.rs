@@ -1,6 +1,10 @@
 // Save as: examples/might/src/hud_system.rs
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
+use crate::{
+    core::resources::PlayerAddressMapping,
+    entities::components::{Health, Player, Stamina},
+};
 
 #[derive(Resource)]
 pub struct PlayerVitals {
@@ -16,10 +20,10 @@ pub struct PlayerVitals {
 impl Default for PlayerVitals {
     fn default() -> Self {
         Self {
-            health: 180.0,
-            max_health: 250.0,
-            energy: 120.0,
-            max_energy: 150.0,
+            health: Health::default().max as f32,
+            max_health: Health::default().max as f32,
+            energy: Stamina::default().max.to_f32(),
+            max_energy: Stamina::default().max.to_f32(),
             level: 12,
             experience: 2400.0,
             max_experience: 5000.0,
@@ -31,6 +35,28 @@ pub fn setup_player_vitals(mut commands: Commands) {
     commands.insert_resource(PlayerVitals::default());
 }
 
+/// Fills the orbs from the local player's rollback `Health` and `Stamina`; empty while they're dead
+pub fn update_player_vitals(
+    mut vitals: ResMut<PlayerVitals>,
+    address_mapping: Option<Res<PlayerAddressMapping>>,
+    players: Query<(&Player, &Health, &Stamina)>,
+) {
+    // Spectators have no player of their own
+    let Some(local_handle) = address_mapping.and_then(|mapping| mapping.local_player_handle) else {
+        return;
+    };
+
+    match players.iter().find(|(player, ..)| player.handle == local_handle) {
+        Some((_, health, stamina)) => {
+            vitals.health = health.current as f32;
+            vitals.max_health = health.max as f32;
+            vitals.energy = stamina.current.to_f32();
+            vitals.max_energy = stamina.max.to_f32();
+        }
+        None => vitals.health = 0.0,
+    }
+}
+
 pub fn render_diablo_hud(mut contexts: EguiContexts, vitals: Res<PlayerVitals>) {
     let Ok(ctx) = contexts.ctx_mut() else { return };
 let screen_rect = ctx.viewport_rect();    
@@ -390,41 +416,3 @@ fn lerp_color(a: egui::Color32, b: egui::Color32, t: f32) -> egui::Color32 {
         (a.b() as f32 + (b.b() as f32 - a.g() as f32) * t) as u8,
     )
 }
-
-pub fn simulate_vitals_changes(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut vitals: ResMut<PlayerVitals>,
-    time: Res<Time>,
-    chat_input: Option<Res<crate::ui::chat::ChatInput>>,
-) {
-    // Don't process hotkeys if chat is focused
-    let chat_is_focused = chat_input.as_ref().map(|c| c.is_focused).unwrap_or(false);
-
-    if !chat_is_focused {
-        if keyboard.just_pressed(KeyCode::KeyH) {
-            vitals.health = (vitals.health - 25.0).max(0.0);
-        }
-
-        if keyboard.just_pressed(KeyCode::KeyJ) {
-            vitals.health = (vitals.health + 25.0).min(vitals.max_health);
-        }
-
-        if keyboard.just_pressed(KeyCode::KeyE) {
-            vitals.energy = (vitals.energy - 20.0).max(0.0);
-        }
-
-        if keyboard.just_pressed(KeyCode::KeyR) {
-            vitals.energy = (vitals.energy + 20.0).min(vitals.max_energy);
-        }
-    }
-
-    // Always regenerate energy and experience (passive effects)
-    vitals.energy = (vitals.energy + 5.0 * time.delta_secs()).min(vitals.max_energy);
-
-    vitals.experience += 10.0 * time.delta_secs();
-    if vitals.experience >= vitals.max_experience {
-        vitals.experience = 0.0;
-        vitals.level += 1;
-        vitals.max_experience *= 1.2;
-    }
-}
\ No newline at end of file
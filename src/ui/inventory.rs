@@ -228,6 +228,13 @@ impl Default for InventorySystem {
     }
 }
 
+impl InventorySystem {
+    /// Ids of every currently equipped item, in no particular order
+    pub fn equipped_item_ids(&self) -> Vec<String> {
+        self.equipped.values().flatten().cloned().collect()
+    }
+}
+
 /// Component to mark equipped item entities
 #[derive(Component)]
 pub struct EquippedItem {
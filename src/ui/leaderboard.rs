@@ -158,13 +158,13 @@ async fn fetch_leaderboard_async() -> Result<Vec<LeaderboardEntry>, String> {
     use wasm_bindgen_futures::JsFuture;
     use web_sys::{Request, RequestInit, RequestMode, Response};
 
-    let api_url = "https://dk-leaderboard-api.dagknights.workers.dev/api/leaderboard?sort=kd&limit=10";
+    let api_url = crate::core::constants::api_url("/api/leaderboard?sort=kd&limit=10");
 
     let mut opts = RequestInit::new();
     opts.method("GET");
     opts.mode(RequestMode::Cors);
 
-    let request = Request::new_with_str_and_init(api_url, &opts)
+    let request = Request::new_with_str_and_init(&api_url, &opts)
         .map_err(|e| format!("Request creation error: {:?}", e))?;
 
     let window = web_sys::window().ok_or("No window object")?;
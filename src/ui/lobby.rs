@@ -1,13 +1,26 @@
 // lobby.rs - Lobby system for knight preview and gear selection
 use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
 use bevy::math::primitives::Cylinder;
 use bevy::light::NotShadowCaster;
 use bevy_egui::{egui, EguiContexts};
+use bevy_matchbox::prelude::*;
 use crate::{
     ModelAssets,
     materials::aura::{AuraMaterial, EFFECT_FIRE},
     systems::aura_effects::AuraDisc,
     core::states::GameState,
+    core::args::{Args, BotDifficulty},
+    core::constants::MAX_INPUT_DELAY,
+    core::resources::{BotOpponent, CouchMatch, InputDelay, MatchRole, MatchRules, MatchRoom, ServerSettings, WatchRequest},
+    game::input::GamepadSlots,
+    network::{
+        handshake::HandshakeState,
+        matchmaking::{generate_room_code, parse_room_code, ROOM_CODE_LEN},
+        rating::{PlayerRating, RatedQueue},
+        watch::{LiveFeed, WatchSocket},
+    },
+    ui::chat::ChatSocket,
     ui::inventory::KnightCharacter,
     ui::auth::system::WalletInfo,
 };
@@ -194,14 +207,69 @@ pub fn cleanup_lobby(
     }
 }
 
+/// Everything the lobby buttons decide about the next match
+#[derive(SystemParam)]
+pub struct NextMatch<'w> {
+    next_state: ResMut<'w, NextState<GameState>>,
+    room: ResMut<'w, MatchRoom>,
+    role: ResMut<'w, MatchRole>,
+    bot: ResMut<'w, BotOpponent>,
+    couch: ResMut<'w, CouchMatch>,
+    rules: ResMut<'w, MatchRules>,
+    watch_request: ResMut<'w, WatchRequest>,
+}
+
+/// Who is sitting at this machine: their profile and rating, and the gamepads they brought
+#[derive(SystemParam)]
+pub struct LobbyPlayer<'w> {
+    profile: ResMut<'w, PlayerProfile>,
+    wallet_info: Res<'w, WalletInfo>,
+    rating: Res<'w, PlayerRating>,
+    gamepads: Res<'w, GamepadSlots>,
+}
+
+/// What's typed and picked in the lobby but not acted on yet
+#[derive(Default)]
+pub struct LobbyForm {
+    join_code: String,
+    bot_difficulty: BotDifficulty,
+}
+
+impl NextMatch<'_> {
+    /// Matchmakes with people in `room`
+    fn play(&mut self, room: MatchRoom) {
+        self.start(room, MatchRole::Player, BotOpponent::default(), false);
+    }
+
+    fn spectate(&mut self, code: String) {
+        self.start(MatchRoom::Watch(code), MatchRole::Spectator, BotOpponent::default(), false);
+    }
+
+    fn practise(&mut self, difficulty: BotDifficulty) {
+        self.start(MatchRoom::Public, MatchRole::Player, BotOpponent(Some(difficulty)), false);
+    }
+
+    fn couch(&mut self) {
+        self.start(MatchRoom::Public, MatchRole::Player, BotOpponent::default(), true);
+    }
+
+    fn start(&mut self, room: MatchRoom, role: MatchRole, bot: BotOpponent, couch: bool) {
+        *self.room = room;
+        *self.role = role;
+        *self.bot = bot;
+        self.couch.0 = couch;
+        self.next_state.set(GameState::Matchmaking);
+    }
+}
+
 /// Lobby UI with player profile, notifications, and matchmaking button
 pub fn lobby_ui(
     mut contexts: EguiContexts,
-    mut next_state: ResMut<NextState<GameState>>,
+    mut next_match: NextMatch,
     current_state: Res<State<GameState>>,
-    mut profile: ResMut<PlayerProfile>,
+    mut player: LobbyPlayer,
     notifications: Res<LobbyNotifications>,
-    wallet_info: Res<WalletInfo>,
+    mut form: Local<LobbyForm>,
     time: Res<Time>,
 ) {
     // Debug: Log current state
@@ -223,7 +291,7 @@ pub fn lobby_ui(
         .title_bar(false)
         .resizable(false)
         .fixed_pos([screen_rect.center().x - 250.0, 20.0])
-        .fixed_size([500.0, 220.0])
+        .fixed_size([500.0, 290.0])
         .frame(egui::Frame::default()
             .fill(egui::Color32::from_rgba_unmultiplied(20, 15, 10, 250))
             .stroke(egui::Stroke::new(3.0, egui::Color32::from_rgb(100, 80, 50)))
@@ -245,13 +313,13 @@ pub fn lobby_ui(
                         .color(egui::Color32::from_rgb(180, 160, 120)));
 
                     let name_response = ui.add(
-                        egui::TextEdit::singleline(&mut profile.display_name)
+                        egui::TextEdit::singleline(&mut player.profile.display_name)
                             .desired_width(200.0)
                             .char_limit(20)
                     );
 
                     if name_response.changed() {
-                        info!("Display name changed to: {}", profile.display_name);
+                        info!("Display name changed to: {}", player.profile.display_name);
 
                         // Save display name to localStorage (WASM only)
                         #[cfg(target_arch = "wasm32")]
@@ -260,11 +328,11 @@ pub fn lobby_ui(
                                 .and_then(|w| w.local_storage().ok())
                                 .flatten()
                             {
-                                let key = format!("display_name_{}", wallet_info.address);
-                                if let Err(e) = storage.set_item(&key, &profile.display_name) {
+                                let key = format!("display_name_{}", player.wallet_info.address);
+                                if let Err(e) = storage.set_item(&key, &player.profile.display_name) {
                                     warn!("Failed to save display name to localStorage: {:?}", e);
                                 } else {
-                                    info!("Saved display name '{}' for address {}", profile.display_name, wallet_info.address);
+                                    info!("Saved display name '{}' for address {}", player.profile.display_name, player.wallet_info.address);
                                 }
                             }
                         }
@@ -279,21 +347,45 @@ pub fn lobby_ui(
                         .size(14.0)
                         .color(egui::Color32::from_rgb(180, 160, 120)));
 
-                    let status_color = if profile.is_ready {
+                    let status_color = if player.profile.is_ready {
                         egui::Color32::from_rgb(100, 255, 100)
                     } else {
                         egui::Color32::from_rgb(255, 200, 100)
                     };
 
-                    ui.label(egui::RichText::new(if profile.is_ready { "✓ Ready" } else { "⚙ Equipping" })
+                    ui.label(egui::RichText::new(if player.profile.is_ready { "✓ Ready" } else { "⚙ Equipping" })
                         .size(14.0)
                         .color(status_color));
 
                     ui.add_space(10.0);
 
-                    if ui.button(if profile.is_ready { "Not Ready" } else { "Ready Up" }).clicked() {
-                        profile.is_ready = !profile.is_ready;
-                        info!("Player ready status: {}", profile.is_ready);
+                    if ui.button(if player.profile.is_ready { "Not Ready" } else { "Ready Up" }).clicked() {
+                        player.profile.is_ready = !player.profile.is_ready;
+                        info!("Player ready status: {}", player.profile.is_ready);
+                    }
+                });
+
+                ui.add_space(8.0);
+
+                // Match mode
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Mode:")
+                        .size(14.0)
+                        .color(egui::Color32::from_rgb(180, 160, 120)));
+
+                    egui::ComboBox::from_id_salt("match_mode")
+                        .selected_text(next_match.rules.label())
+                        .show_ui(ui, |ui| {
+                            for preset in MatchRules::PRESETS {
+                                ui.selectable_value(&mut *next_match.rules, preset, preset.label());
+                            }
+                        });
+
+                    if let Some(rating) = player.rating.rating {
+                        ui.add_space(10.0);
+                        ui.label(egui::RichText::new(format!("Rating: {rating}"))
+                            .size(14.0)
+                            .color(egui::Color32::from_rgb(255, 215, 0)));
                     }
                 });
 
@@ -319,9 +411,90 @@ pub fn lobby_ui(
 
                 if ui.add(button).clicked() {
                     info!("🎮 START MATCHMAKING BUTTON CLICKED!");
-                    info!("Player: {} | Ready: {}", profile.display_name, profile.is_ready);
-                    next_state.set(GameState::Matchmaking);
+                    info!("Player: {} | Ready: {}", player.profile.display_name, player.profile.is_ready);
+                    next_match.play(MatchRoom::Public);
                 }
+
+                ui.add_space(8.0);
+
+                // Private rooms for scrims
+                ui.horizontal(|ui| {
+                    if ui.button("Create Private Room").clicked() {
+                        let code = generate_room_code();
+                        info!("Creating private room {code}");
+                        next_match.play(MatchRoom::Private(code));
+                    }
+
+                    ui.add_space(10.0);
+
+                    ui.add(
+                        egui::TextEdit::singleline(&mut form.join_code)
+                            .hint_text("Room code")
+                            .desired_width(90.0)
+                            .char_limit(ROOM_CODE_LEN)
+                    );
+
+                    let code = parse_room_code(&form.join_code);
+                    if ui.add_enabled(code.is_some(), egui::Button::new("Join")).clicked() {
+                        if let Some(code) = code.clone() {
+                            info!("Joining private room {code}");
+                            next_match.play(MatchRoom::Private(code));
+                            form.join_code.clear();
+                        }
+                    }
+                    if ui
+                        .add_enabled(code.is_some(), egui::Button::new("Spectate"))
+                        .on_hover_text("Watch the match playing under this room or watch code, from its first frame")
+                        .clicked()
+                    {
+                        if let Some(code) = code {
+                            info!("Spectating match {code}");
+                            next_match.spectate(code);
+                            form.join_code.clear();
+                        }
+                    }
+                });
+
+                ui.checkbox(&mut next_match.watch_request.0, "Open my matches to spectators")
+                    .on_hover_text("Relays online matches to a watch room, so anyone with the watch code can follow them");
+
+                ui.add_space(8.0);
+
+                // Offline play: the bot as player 2, or everyone on this machine
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("bot_difficulty")
+                        .selected_text(form.bot_difficulty.label())
+                        .width(90.0)
+                        .show_ui(ui, |ui| {
+                            for difficulty in BotDifficulty::ALL {
+                                ui.selectable_value(&mut form.bot_difficulty, difficulty, difficulty.label());
+                            }
+                        });
+
+                    let duel = next_match.rules.num_players == 2;
+                    if ui
+                        .add_enabled(duel, egui::Button::new("Practice vs Bot"))
+                        .on_disabled_hover_text("The bot only plays duels")
+                        .clicked()
+                    {
+                        info!("Practising against a {} bot", form.bot_difficulty.label());
+                        next_match.practise(form.bot_difficulty);
+                    }
+
+                    ui.add_space(10.0);
+
+                    // Everyone after the first player needs a pad; the first can use the keyboard
+                    let pads_needed = next_match.rules.num_players - 1;
+                    if ui
+                        .add_enabled(player.gamepads.connected() >= pads_needed, egui::Button::new("Couch Match"))
+                        .on_hover_text("Every player on this machine, one gamepad each")
+                        .on_disabled_hover_text(format!("Connect {pads_needed} gamepad(s) for the other players"))
+                        .clicked()
+                    {
+                        info!("Starting a couch match for {} players", next_match.rules.num_players);
+                        next_match.couch();
+                    }
+                });
             });
         });
 
@@ -399,7 +572,7 @@ pub fn lobby_ui(
                 ui.label(egui::RichText::new("Name:")
                     .size(12.0)
                     .color(egui::Color32::from_rgb(150, 130, 100)));
-                ui.label(egui::RichText::new(&profile.display_name)
+                ui.label(egui::RichText::new(&player.profile.display_name)
                     .size(12.0)
                     .color(egui::Color32::WHITE)
                     .strong());
@@ -422,9 +595,9 @@ pub fn lobby_ui(
                 ui.label(egui::RichText::new("Ready:")
                     .size(12.0)
                     .color(egui::Color32::from_rgb(150, 130, 100)));
-                ui.label(egui::RichText::new(if profile.is_ready { "Yes" } else { "No" })
+                ui.label(egui::RichText::new(if player.profile.is_ready { "Yes" } else { "No" })
                     .size(12.0)
-                    .color(if profile.is_ready {
+                    .color(if player.profile.is_ready {
                         egui::Color32::from_rgb(100, 255, 100)
                     } else {
                         egui::Color32::from_rgb(255, 200, 100)
@@ -443,3 +616,241 @@ pub fn lobby_ui(
             });
         });
 }
+
+/// Collapsible panel for pointing matchmaking and chat at another signalling server
+pub fn server_settings_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut servers: ResMut<ServerSettings>,
+    mut input_delay: ResMut<InputDelay>,
+    args: Res<Args>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let screen_rect = ctx.viewport_rect();
+
+    egui::Window::new("Server Settings")
+        .default_open(false)
+        .resizable(false)
+        .default_pos([screen_rect.width() - 370.0, 180.0])
+        .default_width(350.0)
+        .frame(egui::Frame::default()
+            .fill(egui::Color32::from_rgba_unmultiplied(20, 15, 10, 230))
+            .stroke(egui::Stroke::new(2.0, egui::Color32::from_rgb(80, 60, 40)))
+            .inner_margin(10.0))
+        .show(ctx, |ui| {
+            egui::Grid::new("server_settings_grid")
+                .num_columns(2)
+                .spacing([10.0, 6.0])
+                .show(ui, |ui| {
+                    let settings = &mut *servers;
+                    for (label, value) in [
+                        ("Matchmaking server:", &mut settings.matchbox_server),
+                        ("Matchmaking room:", &mut settings.matchbox_room),
+                        ("Chat server:", &mut settings.chat_server),
+                        ("Chat room:", &mut settings.chat_room),
+                    ] {
+                        ui.label(egui::RichText::new(label)
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(180, 160, 120)));
+                        ui.add(egui::TextEdit::singleline(value).desired_width(200.0));
+                        ui.end_row();
+                    }
+
+                    // Overrides the delay picked from the measured ping, for players who prefer
+                    // fewer rollbacks (more delay) or snappier controls (less)
+                    ui.label(egui::RichText::new("Input delay:")
+                        .size(12.0)
+                        .color(egui::Color32::from_rgb(180, 160, 120)));
+                    egui::ComboBox::from_id_salt("input_delay")
+                        .selected_text(input_delay.label())
+                        .width(200.0)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut *input_delay, InputDelay::Auto, InputDelay::Auto.label());
+                            for frames in 0..=MAX_INPUT_DELAY {
+                                let option = InputDelay::Fixed(frames);
+                                ui.selectable_value(&mut *input_delay, option, option.label());
+                            }
+                        });
+                    ui.end_row();
+                });
+
+            ui.add_space(6.0);
+
+            ui.horizontal(|ui| {
+                if ui.button("Reconnect Chat").clicked() {
+                    commands.insert_resource(ChatSocket::connect(&servers.chat_url()));
+                }
+                if ui.button("Reset").clicked() {
+                    *servers = ServerSettings::from_args(&args);
+                    *input_delay = InputDelay::from_args(&args);
+                    commands.insert_resource(ChatSocket::connect(&servers.chat_url()));
+                }
+            });
+        });
+}
+
+/// How far filling the room has got: who is connected, what their handshakes said, and the rated search
+#[derive(SystemParam)]
+pub struct RoomProgress<'w> {
+    socket: Option<Res<'w, MatchboxSocket>>,
+    handshake: Option<Res<'w, HandshakeState>>,
+    queue: Option<Res<'w, RatedQueue>>,
+}
+
+/// Room code and the players gathered so far, shown until the session starts
+pub fn matchmaking_room_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameState>>,
+    progress: RoomProgress,
+    room: Res<MatchRoom>,
+    rules: Res<MatchRules>,
+    profile: Res<PlayerProfile>,
+) {
+    let RoomProgress { socket, handshake, queue } = progress;
+    let Some(socket) = socket else { return };
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let screen_rect = ctx.viewport_rect();
+
+    let peers: Vec<PeerId> = socket.connected_peers().collect();
+    let joined = peers.len() + 1;
+
+    egui::Window::new("matchmaking_room")
+        .title_bar(false)
+        .resizable(false)
+        .fixed_pos([screen_rect.center().x - 200.0, 20.0])
+        .fixed_size([400.0, 0.0])
+        .frame(egui::Frame::default()
+            .fill(egui::Color32::from_rgba_unmultiplied(20, 15, 10, 250))
+            .stroke(egui::Stroke::new(3.0, egui::Color32::from_rgb(100, 80, 50)))
+            .inner_margin(20.0))
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                match &*room {
+                    MatchRoom::Public => {
+                        ui.label(egui::RichText::new("SEARCHING FOR MATCH")
+                            .size(22.0)
+                            .color(egui::Color32::from_rgb(200, 180, 140))
+                            .strong());
+                    }
+                    MatchRoom::Rated(bucket) => {
+                        ui.label(egui::RichText::new("SEARCHING FOR MATCH")
+                            .size(22.0)
+                            .color(egui::Color32::from_rgb(200, 180, 140))
+                            .strong());
+                        ui.label(egui::RichText::new(format!("Rating {}-{}", bucket.lower(), bucket.upper()))
+                            .size(14.0)
+                            .color(egui::Color32::from_rgb(255, 215, 0)));
+                        if let Some(queue) = queue.as_ref().filter(|_| peers.is_empty()) {
+                            ui.label(egui::RichText::new(format!(
+                                "Widening search in {:.0}s",
+                                queue.timer.remaining_secs()
+                            ))
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(150, 130, 100)));
+                        }
+                    }
+                    MatchRoom::Private(code) | MatchRoom::Watch(code) => {
+                        ui.label(egui::RichText::new("PRIVATE ROOM")
+                            .size(22.0)
+                            .color(egui::Color32::from_rgb(200, 180, 140))
+                            .strong());
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(code)
+                                .size(28.0)
+                                .monospace()
+                                .color(egui::Color32::from_rgb(255, 215, 0)));
+                            if ui.button("Copy").clicked() {
+                                ui.ctx().copy_text(code.clone());
+                            }
+                        });
+                    }
+                }
+
+                ui.label(egui::RichText::new(format!("{} · {joined}/{} joined", rules.label(), rules.num_players))
+                    .size(13.0)
+                    .color(egui::Color32::from_rgb(150, 130, 100)));
+
+                ui.add_space(8.0);
+                ui.separator();
+                ui.add_space(8.0);
+
+                ui.label(egui::RichText::new(format!("{} (You)", profile.display_name))
+                    .size(14.0)
+                    .color(egui::Color32::from_rgb(100, 200, 255)));
+                for peer in &peers {
+                    let name = handshake
+                        .as_ref()
+                        .and_then(|h| h.display_name(peer))
+                        .unwrap_or("Connecting...");
+                    let ping = handshake
+                        .as_ref()
+                        .and_then(|h| h.rtt_to(peer))
+                        .map(|rtt| format!(" · {} ms", rtt.as_millis()))
+                        .unwrap_or_default();
+                    ui.label(egui::RichText::new(format!("{name}{ping}"))
+                        .size(14.0)
+                        .color(egui::Color32::WHITE));
+                }
+
+                ui.add_space(10.0);
+
+                if ui.button("Cancel").clicked() {
+                    commands.remove_resource::<MatchboxSocket>();
+                    commands.remove_resource::<HandshakeState>();
+                    commands.remove_resource::<RatedQueue>();
+                    next_state.set(GameState::Lobby);
+                }
+            });
+        });
+}
+
+/// Shown while a spectator waits for the players' relays to introduce themselves
+pub fn watch_join_ui(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameState>>,
+    room: Res<MatchRoom>,
+    feed: Option<Res<LiveFeed>>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else { return };
+    let screen_rect = ctx.viewport_rect();
+    let code = match &*room {
+        MatchRoom::Watch(code) => code.as_str(),
+        _ => "",
+    };
+    let found = feed.as_ref().map_or(0, |feed| feed.players_found());
+
+    egui::Window::new("watch_join")
+        .title_bar(false)
+        .resizable(false)
+        .fixed_pos([screen_rect.center().x - 200.0, 20.0])
+        .fixed_size([400.0, 0.0])
+        .frame(egui::Frame::default()
+            .fill(egui::Color32::from_rgba_unmultiplied(20, 15, 10, 250))
+            .stroke(egui::Stroke::new(3.0, egui::Color32::from_rgb(100, 80, 50)))
+            .inner_margin(20.0))
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(egui::RichText::new("JOINING MATCH")
+                    .size(22.0)
+                    .color(egui::Color32::from_rgb(200, 180, 140))
+                    .strong());
+                ui.label(egui::RichText::new(code)
+                    .size(28.0)
+                    .monospace()
+                    .color(egui::Color32::from_rgb(255, 215, 0)));
+                ui.label(egui::RichText::new(format!("{found} players found"))
+                    .size(13.0)
+                    .color(egui::Color32::from_rgb(150, 130, 100)));
+
+                ui.add_space(10.0);
+
+                if ui.button("Cancel").clicked() {
+                    commands.remove_resource::<WatchSocket>();
+                    commands.remove_resource::<LiveFeed>();
+                    next_state.set(GameState::Lobby);
+                }
+            });
+        });
+}
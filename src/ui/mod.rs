@@ -9,6 +9,8 @@ pub mod auth;
 pub mod skills;
 pub mod leaderboard;
 pub mod game_end;
+pub mod disconnect;
+pub mod network_stats;
 
 // Re-export commonly used items
 pub use hud::*;
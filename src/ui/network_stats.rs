@@ -0,0 +1,113 @@
+// network_stats.rs - Toggleable overlay with GGRS connection and rollback stats (F3)
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui::{self, Align2, Color32, FontId, RichText}};
+use bevy_ggrs::{ggrs::DesyncDetection, Session};
+use crate::{
+    Config,
+    core::resources::PlayerAddressMapping,
+    network::stats::RollbackStats,
+};
+
+pub const NETWORK_STATS_KEY: KeyCode = KeyCode::F3;
+
+#[derive(Resource, Default)]
+pub struct NetworkStatsOverlay {
+    pub visible: bool,
+}
+
+pub fn toggle_network_stats(keys: Res<ButtonInput<KeyCode>>, mut overlay: ResMut<NetworkStatsOverlay>) {
+    if keys.just_pressed(NETWORK_STATS_KEY) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+pub fn network_stats_ui(
+    mut contexts: EguiContexts,
+    overlay: Res<NetworkStatsOverlay>,
+    session: Option<Res<Session<Config>>>,
+    stats: Res<RollbackStats>,
+    address_mapping: Res<PlayerAddressMapping>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    // Synctest, spectator and replay sessions have no peers to measure
+    let Some(Session::P2P(session)) = session.as_deref() else {
+        return;
+    };
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    let confirmed_frame = session.confirmed_frame();
+    let predicted_frames = (session.current_frame() - confirmed_frame).max(0);
+
+    let remote_handles = session.remote_player_handles();
+    let desync_detection = session.desync_detection() != DesyncDetection::Off;
+
+    let stat_line = |ui: &mut egui::Ui, label: &str, value: String| {
+        ui.label(
+            RichText::new(format!("{label}: {value}"))
+                .color(Color32::WHITE)
+                .font(FontId::monospace(13.0)),
+        );
+    };
+
+    egui::Area::new("network_stats_overlay".into())
+        .anchor(Align2::RIGHT_TOP, (-10., 10.))
+        .show(ctx, |ui| {
+            egui::Frame::default()
+                .fill(Color32::from_rgba_unmultiplied(10, 10, 15, 200))
+                .inner_margin(10.0)
+                .show(ui, |ui| {
+                    ui.label(
+                        RichText::new("Network (F3)")
+                            .color(Color32::from_rgb(255, 200, 100))
+                            .font(FontId::proportional(15.0)),
+                    );
+                    stat_line(ui, "Frame", format!("{} (confirmed {confirmed_frame})", session.current_frame()));
+                    stat_line(ui, "Predicted frames", predicted_frames.to_string());
+                    stat_line(
+                        ui,
+                        "Rollbacks",
+                        format!(
+                            "{} ({} frames resimulated, last {} deep)",
+                            stats.rollbacks, stats.resimulated_frames, stats.last_rollback_depth
+                        ),
+                    );
+                    stat_line(
+                        ui,
+                        "Desync checks",
+                        if desync_detection {
+                            format!("{} passed, {} failed", stats.desync_checks_passed(), stats.desyncs)
+                        } else {
+                            "off".to_string()
+                        },
+                    );
+
+                    for handle in remote_handles {
+                        ui.separator();
+                        ui.label(
+                            RichText::new(address_mapping.name_or_default(handle))
+                                .color(Color32::from_rgb(150, 200, 255))
+                                .font(FontId::proportional(14.0)),
+                        );
+                        match session.network_stats(handle) {
+                            Ok(net) => {
+                                stat_line(ui, "Ping", format!("{} ms", net.ping));
+                                // Positive advantage means we're ahead and GGRS will slow us down
+                                stat_line(
+                                    ui,
+                                    "Frame advantage",
+                                    format!("local {:+}, remote {:+}", -net.local_frames_behind, -net.remote_frames_behind),
+                                );
+                                stat_line(ui, "Send queue", net.send_queue_len.to_string());
+                                stat_line(ui, "Bandwidth", format!("{} kbps", net.kbps_sent));
+                            }
+                            // Not enough round trips yet, or the peer is gone
+                            Err(_) => stat_line(ui, "Status", "no stats yet".to_string()),
+                        }
+                    }
+                });
+        });
+}
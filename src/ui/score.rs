@@ -1,18 +1,99 @@
 // ui.rs
 use bevy::prelude::*;
 use bevy_egui::{EguiContexts, egui::{self, Align2, Color32, FontId, RichText}};
-use crate::core::resources::Scores;
+use crate::{
+    core::resources::{MatchRules, PlayerAddressMapping, Scores},
+    network::spectator::SpectatorCamera,
+    network::watch::WatchRelay,
+};
 
-pub fn update_score_ui(mut contexts: EguiContexts, scores: Res<Scores>) {
-    let Scores(p1_score, p2_score) = *scores;
+pub fn update_score_ui(
+    mut contexts: EguiContexts,
+    scores: Res<Scores>,
+    rules: Res<MatchRules>,
+    relay: Option<Res<WatchRelay>>,
+) {
+    // One number per team, so a duel still reads "1 - 0"
+    let text = (0..rules.num_teams())
+        .map(|team| rules.team_score(&scores, team).to_string())
+        .collect::<Vec<_>>()
+        .join(" - ");
 
     egui::Area::new("score".into())
         .anchor(Align2::CENTER_TOP, (0., 25.))
         .show(contexts.ctx_mut().unwrap(), |ui| {
-            ui.label(
-                RichText::new(format!("{p1_score} - {p2_score}"))
-                    .color(Color32::WHITE)
-                    .font(FontId::proportional(72.0)),
-            );
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    RichText::new(text)
+                        .color(Color32::WHITE)
+                        .font(FontId::proportional(72.0)),
+                );
+                // Anyone can join with this code and watch from the first frame
+                if let Some(relay) = relay {
+                    ui.label(
+                        RichText::new(format!("Watch code {}", relay.code))
+                            .color(Color32::from_rgb(200, 180, 140))
+                            .font(FontId::monospace(14.0)),
+                    );
+                }
+            });
         });
-}
\ No newline at end of file
+}
+
+/// Names next to the scores, plus what the spectator camera is doing
+pub fn spectator_score_ui(
+    mut contexts: EguiContexts,
+    scores: Res<Scores>,
+    rules: Res<MatchRules>,
+    address_mapping: Res<PlayerAddressMapping>,
+    spectator_camera: Res<SpectatorCamera>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    let teams: Vec<(String, u32)> = (0..rules.num_teams())
+        .map(|team| {
+            let names = rules
+                .team_members(team)
+                .map(|handle| address_mapping.name_or_default(handle))
+                .collect::<Vec<_>>()
+                .join(" & ");
+            (names, rules.team_score(&scores, team))
+        })
+        .collect();
+
+    // A duel reads "Alice 1 - 0 Bob"; bigger matches list every team with its score
+    let text = match teams.as_slice() {
+        [(left, left_score), (right, right_score)] => {
+            format!("{left}  {left_score} - {right_score}  {right}")
+        }
+        _ => teams
+            .iter()
+            .map(|(names, score)| format!("{names} {score}"))
+            .collect::<Vec<_>>()
+            .join("   |   "),
+    };
+
+    let camera_hint = match spectator_camera.target {
+        Some(handle) => format!("Following {} · Tab to switch", address_mapping.name_or_default(handle)),
+        None => "Free camera · WASD to pan · Tab to switch".to_string(),
+    };
+
+    egui::Area::new("spectator_score".into())
+        .anchor(Align2::CENTER_TOP, (0., 25.))
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    RichText::new(text)
+                        .color(Color32::WHITE)
+                        .font(FontId::proportional(40.0)),
+                );
+                ui.label(
+                    RichText::new(format!("SPECTATING · {camera_hint}"))
+                        .color(Color32::from_rgb(200, 180, 140))
+                        .font(FontId::proportional(16.0)),
+                );
+            });
+        });
+}
@@ -1,9 +1,112 @@
-// checksum.rs
+// checksum.rs - Desync checksums for every rollback component and resource
+use std::hash::{Hash, Hasher};
 use bevy::prelude::*;
+use bevy_ggrs::{checksum_hasher, RollbackApp};
+use crate::{
+    core::resources::{RoundEndTimer, Scores},
+    entities::components::{
+        Aim, AttackBox, Bullet, BulletReady, CombatState, DistanceTraveled, Guard, Health, Invincible, Locomotion,
+        MoveDir, Player, Position, Stamina,
+    },
+};
+
+/// Registers a checksum for each piece of rollback state, so a desync anywhere is caught
+/// on the frame it happens rather than when it finally moves a `Transform`
+pub struct ChecksumPlugin;
+
+impl Plugin for ChecksumPlugin {
+    fn build(&self, app: &mut App) {
+        app.checksum_component::<Transform>(checksum_transform)
+            .checksum_component_with_hash::<MoveDir>()
+            .checksum_component_with_hash::<Position>()
+            .checksum_component_with_hash::<Aim>()
+            .checksum_component_with_hash::<Locomotion>()
+            .checksum_component_with_hash::<DistanceTraveled>()
+            .checksum_component_with_hash::<BulletReady>()
+            .checksum_component::<Player>(checksum_player)
+            .checksum_component::<Bullet>(checksum_bullet)
+            .checksum_component_with_hash::<Health>()
+            .checksum_component::<CombatState>(checksum_combat_state)
+            .checksum_component_with_hash::<Stamina>()
+            .checksum_component_with_hash::<Invincible>()
+            .checksum_component_with_hash::<Guard>()
+            .checksum_component::<AttackBox>(checksum_attack_box)
+            .checksum_resource::<Scores>(checksum_scores)
+            .checksum_resource::<RoundEndTimer>(checksum_round_end_timer);
+    }
+}
+
+// Derived `Hash` feeds `usize` fields, enum discriminants (as `isize`) and array lengths to the
+// hasher at pointer width, 4 bytes on wasm32 and 8 on x86_64, so types holding any of those
+// get a checksum fn that widens them to fixed-size integers first.
+
+/// Floats are hashed by bit pattern; NaN has many of those, so refuse it outright
+fn hash_f32(value: f32, hasher: &mut impl Hasher) {
+    assert!(value.is_finite(), "Hashing is not stable for NaN f32 values.");
+    value.to_bits().hash(hasher);
+}
 
 pub fn checksum_transform(transform: &Transform) -> u64 {
-    // Simple checksum for transform
-    let pos = transform.translation;
-    let rot = transform.rotation;
-    ((pos.x as u64) ^ (pos.y as u64) ^ (pos.z as u64) ^ (rot.x as u64) ^ (rot.y as u64) ^ (rot.z as u64) ^ (rot.w as u64)) as u64
-}
\ No newline at end of file
+    let mut hasher = checksum_hasher();
+
+    hash_f32(transform.translation.x, &mut hasher);
+    hash_f32(transform.translation.y, &mut hasher);
+    hash_f32(transform.translation.z, &mut hasher);
+
+    // skip transform.rotation: it's cosmetic, and glam's SIMD and scalar quaternion
+    // math can round differently, so native and WASM peers may disagree on it
+    // skip transform.scale as it's not used for gameplay
+
+    hasher.finish()
+}
+
+pub fn checksum_round_end_timer(timer: &RoundEndTimer) -> u64 {
+    let mut hasher = checksum_hasher();
+    timer.elapsed().hash(&mut hasher);
+    timer.is_finished().hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn checksum_player(player: &Player) -> u64 {
+    let mut hasher = checksum_hasher();
+    (player.handle as u64).hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn checksum_bullet(bullet: &Bullet) -> u64 {
+    let mut hasher = checksum_hasher();
+    (bullet.owner as u64).hash(&mut hasher);
+    bullet.damage.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn checksum_attack_box(attack_box: &AttackBox) -> u64 {
+    let mut hasher = checksum_hasher();
+    (attack_box.owner as u64).hash(&mut hasher);
+    attack_box.damage.hash(&mut hasher);
+    attack_box.radius.hash(&mut hasher);
+    attack_box.frames_left.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn checksum_combat_state(combat: &CombatState) -> u64 {
+    let mut hasher = checksum_hasher();
+    (combat.current_action as u8).hash(&mut hasher);
+    combat.action_frames.hash(&mut hasher);
+    combat.combo_count.hash(&mut hasher);
+    combat.combo_frames.hash(&mut hasher);
+    combat.attack_held.hash(&mut hasher);
+    combat.dodge_held.hash(&mut hasher);
+    combat.dodge_cooldown.hash(&mut hasher);
+    combat.block_held.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn checksum_scores(scores: &Scores) -> u64 {
+    let mut hasher = checksum_hasher();
+    scores.rounds_played.hash(&mut hasher);
+    for score in scores.by_handle {
+        score.hash(&mut hasher);
+    }
+    hasher.finish()
+}
@@ -1,2 +1,37 @@
 // helpers.rs
-// Helper utility functions (checksum_transform moved to checksum.rs)
\ No newline at end of file
+// Helper utility functions (checksum_transform moved to checksum.rs)
+
+/// Writes `json` next to the executable, or offers it as a browser download on the web
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_json_file(file_name: &str, json: &str) -> Result<(), String> {
+    std::fs::write(file_name, json).map_err(|e| e.to_string())
+}
+
+/// Writes `json` next to the executable, or offers it as a browser download on the web
+#[cfg(target_arch = "wasm32")]
+pub fn save_json_file(file_name: &str, json: &str) -> Result<(), String> {
+    use wasm_bindgen::{JsCast, JsValue};
+    use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(json));
+    let options = BlobPropertyBag::new();
+    options.set_type("application/json");
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)
+        .map_err(|e| format!("Blob error: {:?}", e))?;
+    let url = Url::create_object_url_with_blob(&blob)
+        .map_err(|e| format!("Object URL error: {:?}", e))?;
+
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("No document object")?;
+    let anchor: HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(|e| format!("Element error: {:?}", e))?
+        .dyn_into()
+        .map_err(|_| "Anchor cast error")?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    Url::revoke_object_url(&url).map_err(|e| format!("Revoke error: {:?}", e))
+}
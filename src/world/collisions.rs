@@ -1,37 +1,44 @@
 // collisions.rs
 use bevy::prelude::*;
-use crate::{core::constants::*, entities::components::{Wall, Bullet, Player}, core::states::RollbackState, core::resources::Scores};
+use bevy_ggrs::RollbackFrameCount;
+use crate::{core::constants::*, core::fixed::{Fixed, FixedVec2}, entities::components::{Aim, Wall, Bullet, CombatState, Guard, Health, Invincible, MoveDir, Player, Position}, core::states::RollbackState, core::resources::{Scores, MatchRules}, game::combat::{guarded_damage, GuardedHit, HitLog, HitRecord}};
+
+/// Centre and size of a wall on the ground plane; map walls sit on half-unit coordinates, so this is exact
+pub(crate) fn wall_bounds(wall_transform: &Transform) -> (FixedVec2, FixedVec2) {
+    let centre = FixedVec2::new(
+        Fixed::from_f32(wall_transform.translation.x),
+        Fixed::from_f32(wall_transform.translation.z),
+    );
+    let size = FixedVec2::new(
+        Fixed::from_f32(wall_transform.scale.x),
+        Fixed::from_f32(wall_transform.scale.z),
+    );
+    (centre, size)
+}
 
 pub fn resolve_wall_collisions(
-    mut players: Query<&mut Transform, With<Player>>,
+    mut players: Query<&mut Position, With<Player>>,
     walls: Query<&Transform, (With<Wall>, Without<Player>)>,
 ) {
-    for mut player_transform in &mut players {
-        for wall_transform in &walls {
-            let wall_scale = wall_transform.scale;
-            let wall_size = Vec3::new(wall_scale.x, WALL_HEIGHT, wall_scale.z);
-            let wall_pos = wall_transform.translation;
-            let player_pos = player_transform.translation;
+    let player_radius = Fixed::from_f32(PLAYER_RADIUS);
 
-            // Work in XZ plane
-            let wall_pos_xz = Vec2::new(wall_pos.x, wall_pos.z);
-            let player_pos_xz = Vec2::new(player_pos.x, player_pos.z);
-            let wall_size_xz = Vec2::new(wall_size.x, wall_size.z);
+    for mut player_pos in &mut players {
+        for wall_transform in &walls {
+            let (wall_pos, wall_size) = wall_bounds(wall_transform);
 
-            let wall_to_player = player_pos_xz - wall_pos_xz;
-            let wall_to_player_abs = wall_to_player.abs();
-            let wall_corner_to_player_center = wall_to_player_abs - wall_size_xz / 2.;
+            let wall_to_player = player_pos.0 - wall_pos;
+            let wall_corner_to_player_center = wall_to_player.abs() - wall_size * Fixed::HALF;
 
-            let corner_to_corner = wall_corner_to_player_center - Vec2::splat(PLAYER_RADIUS);
+            let corner_to_corner = wall_corner_to_player_center - FixedVec2::new(player_radius, player_radius);
 
-            if corner_to_corner.x > 0. || corner_to_corner.y > 0. {
+            if corner_to_corner.x > Fixed::ZERO || corner_to_corner.y > Fixed::ZERO {
                 continue;
             }
 
             if corner_to_corner.x > corner_to_corner.y {
-                player_transform.translation.x -= wall_to_player.x.signum() * corner_to_corner.x;
+                player_pos.0.x -= wall_to_player.x.signum() * corner_to_corner.x;
             } else {
-                player_transform.translation.z -= wall_to_player.y.signum() * corner_to_corner.y;
+                player_pos.0.y -= wall_to_player.y.signum() * corner_to_corner.y;
             }
         }
     }
@@ -39,33 +46,26 @@ pub fn resolve_wall_collisions(
 
 pub fn bullet_wall_collisions(
     mut commands: Commands,
-    bullets: Query<(Entity, &Transform), With<Bullet>>,
+    bullets: Query<(Entity, &Position), With<Bullet>>,
     walls: Query<&Transform, (With<Wall>, Without<Bullet>)>,
 ) {
-    let map_limit = MAP_SIZE as f32 / 2.;
+    let map_limit = Fixed::from_ratio(MAP_SIZE, 2);
 
-    for (bullet_entity, bullet_transform) in &bullets {
-        let bullet_pos = bullet_transform.translation;
+    for (bullet_entity, bullet_pos) in &bullets {
+        let bullet_pos = bullet_pos.0;
 
-        if bullet_pos.x.abs() > map_limit || bullet_pos.z.abs() > map_limit {
+        if bullet_pos.x.abs() > map_limit || bullet_pos.y.abs() > map_limit {
             commands.entity(bullet_entity).despawn();
             continue;
         }
 
         for wall_transform in &walls {
-            let wall_scale = wall_transform.scale;
-            let wall_size = Vec3::new(wall_scale.x, WALL_HEIGHT, wall_scale.z);
-            let wall_pos = wall_transform.translation;
-            
-            let wall_pos_xz = Vec2::new(wall_pos.x, wall_pos.z);
-            let bullet_pos_xz = Vec2::new(bullet_pos.x, bullet_pos.z);
-            let wall_size_xz = Vec2::new(wall_size.x, wall_size.z);
-            
-            let center_to_center = wall_pos_xz - bullet_pos_xz;
-            let center_to_center = center_to_center.abs();
-            let corner_to_center = center_to_center - wall_size_xz / 2.;
-            
-            if corner_to_center.x < 0. && corner_to_center.y < 0. {
+            let (wall_pos, wall_size) = wall_bounds(wall_transform);
+
+            let center_to_center = (wall_pos - bullet_pos).abs();
+            let corner_to_center = center_to_center - wall_size * Fixed::HALF;
+
+            if corner_to_center.x < Fixed::ZERO && corner_to_center.y < Fixed::ZERO {
                 commands.entity(bullet_entity).despawn();
                 break;
             }
@@ -73,31 +73,108 @@ pub fn bullet_wall_collisions(
     }
 }
 
+/// What a bullet reads and changes on the player it hits
+type BulletTarget = (
+    &'static Position,
+    &'static Player,
+    &'static Aim,
+    &'static Guard,
+    &'static CombatState,
+    &'static mut Health,
+    &'static Invincible,
+);
+
+/// Each bullet damages the first enemy it touches and is spent, unless parried back at the
+/// shooter; dying is left to `kill_players`
+pub fn bullet_hits(
+    mut commands: Commands,
+    mut players: Query<BulletTarget, Without<Bullet>>,
+    mut bullets: Query<(Entity, &Position, &mut Bullet, &mut MoveDir)>,
+    rules: Res<MatchRules>,
+    frame: Res<RollbackFrameCount>,
+    mut hits: ResMut<HitLog>,
+) {
+    let hit_distance = Fixed::from_f32(PLAYER_RADIUS + BULLET_RADIUS);
+
+    for (bullet_entity, bullet_pos, mut bullet, mut bullet_dir) in &mut bullets {
+        // No friendly fire; overlapping players are resolved by handle so every peer agrees
+        let mut targets: Vec<_> = players
+            .iter_mut()
+            .filter(|(_, player, ..)| rules.team_of(bullet.owner) != rules.team_of(player.handle))
+            .collect();
+        targets.sort_by_key(|(_, player, ..)| player.handle);
+
+        // Compare squared distances to stay clear of sqrt
+        let target = targets.into_iter().find(|(player_pos, .., invincible)| {
+            !invincible.active() && (player_pos.0 - bullet_pos.0).length_squared() < hit_distance * hit_distance
+        });
+        let Some((player_pos, player, facing, guard, combat, mut health, _)) = target else {
+            continue;
+        };
+
+        // Judged from where the bullet came from, not where it's overlapping now
+        let source = bullet_pos.0 - bullet_dir.0;
+        let Some(GuardedHit { damage, .. }) =
+            guarded_damage(combat, guard, facing.0, player_pos.0, source, bullet.damage)
+        else {
+            bullet.owner = player.handle;
+            bullet_dir.0 = -bullet_dir.0;
+            continue;
+        };
+
+        health.current = (health.current - damage).max(0);
+        commands.entity(bullet_entity).despawn();
+        hits.record(HitRecord {
+            frame: frame.0,
+            victim: player.handle,
+            attacker: bullet.owner,
+            damage,
+            position: player_pos.0,
+        });
+    }
+}
+
+/// Removes everyone out of health, and ends the round once a single team is left
 pub fn kill_players(
     mut commands: Commands,
-    players: Query<(Entity, &Transform, &Player), Without<Bullet>>,
-    bullets: Query<&Transform, With<Bullet>>,
+    players: Query<(Entity, &Player, &Health)>,
+    rules: Res<MatchRules>,
     mut next_state: ResMut<NextState<RollbackState>>,
     mut scores: ResMut<Scores>,
 ) {
-    for (player_entity, player_transform, player) in &players {
-        for bullet_transform in &bullets {
-            let player_pos = player_transform.translation;
-            let bullet_pos = bullet_transform.translation;
-
-            let distance = player_pos.distance(bullet_pos);
-
-            if distance < PLAYER_RADIUS + BULLET_RADIUS {
-                commands.entity(player_entity).despawn();
-                next_state.set(RollbackState::RoundEnd);
-
-                if player.handle == 0 {
-                    scores.1 += 1;
-                } else {
-                    scores.0 += 1;
-                }
-                info!("player died: {scores:?}")
-            }
+    let mut killed = Vec::new();
+
+    for (player_entity, player, health) in &players {
+        if health.current <= 0 {
+            commands.entity(player_entity).despawn();
+            killed.push(player.handle);
+        }
+    }
+
+    if killed.is_empty() {
+        return;
+    }
+
+    // The round is over once at most one team is left standing
+    let mut surviving_teams: Vec<usize> = players
+        .iter()
+        .filter(|(_, player, _)| !killed.contains(&player.handle))
+        .map(|(_, player, _)| rules.team_of(player.handle))
+        .collect();
+    surviving_teams.sort_unstable();
+    surviving_teams.dedup();
+
+    if surviving_teams.len() > 1 {
+        return;
+    }
+
+    if let Some(&winning_team) = surviving_teams.first() {
+        for handle in rules.team_members(winning_team) {
+            scores.by_handle[handle] += 1;
         }
     }
-}
\ No newline at end of file
+    scores.rounds_played += 1;
+    next_state.set(RollbackState::RoundEnd);
+    // Resimulated with every rollback across the deciding frame
+    debug!("round over: {scores:?}")
+}
@@ -17,7 +17,7 @@ pub fn generate_map(
         commands.entity(wall).despawn();
     }
 
-    let mut rng = Xoshiro256PlusPlus::seed_from_u64((scores.0 + scores.1) as u64 ^ **session_seed);
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(scores.rounds_played as u64 ^ **session_seed);
 
     for _ in 0..20 {
         let max_box_size = MAP_SIZE / 4;